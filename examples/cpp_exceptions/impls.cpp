@@ -0,0 +1,32 @@
+#include "generated.h"
+
+#include <cmath>
+#include <stdexcept>
+#include <string>
+
+static ::std::string to_std_string(rust::Ref<rust::Str> s) {
+  return ::std::string(reinterpret_cast<const char *>(s.as_ptr()), s.len());
+}
+
+uint16_t rust::exported_functions::parse_port(rust::Ref<rust::Str> s) {
+  ::std::string text = to_std_string(s);
+  if (text.empty() || text.find_first_not_of("0123456789") != ::std::string::npos) {
+    throw ::std::invalid_argument("`" + text + "` is not a number");
+  }
+  unsigned long port = ::std::stoul(text);
+  if (port > 65535) {
+    throw ::std::out_of_range("port out of range");
+  }
+  return port;
+}
+
+::double_t rust::exported_functions::checked_sqrt(::double_t x) {
+  if (x < 0) {
+    throw ::std::domain_error("square root of a negative number");
+  }
+  return ::std::sqrt(x);
+}
+
+uint32_t rust::Impl<rust::crate::Config>::parse_timeout(rust::Ref<rust::Str> s) {
+  return ::std::stoul(to_std_string(s));
+}
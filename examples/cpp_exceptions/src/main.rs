@@ -0,0 +1,27 @@
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+}
+
+struct Config;
+
+fn main() {
+    dbg!(generated::parse_port("8080").unwrap());
+    match generated::parse_port("http") {
+        Ok(port) => println!("port = {port}"),
+        Err(e) => println!("parse_port failed: {}", e.what()),
+    }
+    if let Err(e) = generated::parse_port("123456") {
+        println!("{e}");
+    }
+
+    dbg!(Config::parse_timeout("30").unwrap());
+    dbg!(Config::parse_timeout("soon").is_err());
+
+    std::panic::set_hook(Box::new(|info| {
+        let message = info.payload().downcast_ref::<String>().unwrap();
+        println!("panicked: {message}");
+    }));
+    dbg!(generated::checked_sqrt(16.0));
+    let r = std::panic::catch_unwind(|| generated::checked_sqrt(-1.0));
+    dbg!(r.is_err());
+}
@@ -0,0 +1,18 @@
+#include <iostream>
+
+#include "generated.h"
+
+int main() {
+  // From parser.zng
+  rust::std::vec::Vec<uint8_t> bytes = rust::crate::parser::parse_bytes("1, 2, 3, 250"_rs);
+  zngur_dbg(bytes);
+  std::cout << "len: " << bytes.len() << std::endl;
+
+  // From storage.zng, using the same `Vec<u8>` with the methods of both files
+  std::cout << "is_empty: " << bytes.is_empty() << std::endl;
+  std::cout << "checksum: " << rust::crate::storage::checksum(bytes) << std::endl;
+
+  // `str` and `rust::Ref` are shared by both files
+  rust::Ref<rust::Str> label = rust::crate::storage::label("  bytes  "_rs);
+  std::cout << "label len: " << label.len() << std::endl;
+}
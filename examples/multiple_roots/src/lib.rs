@@ -0,0 +1,21 @@
+#[rustfmt::skip]
+mod generated;
+
+mod parser {
+    pub fn parse_bytes(input: &str) -> Vec<u8> {
+        input
+            .split(',')
+            .map(|x| x.trim().parse().unwrap())
+            .collect()
+    }
+}
+
+mod storage {
+    pub fn checksum(bytes: &Vec<u8>) -> u32 {
+        bytes.iter().map(|&x| x as u32).sum()
+    }
+
+    pub fn label(name: &str) -> &str {
+        name.trim()
+    }
+}
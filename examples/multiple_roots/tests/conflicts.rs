@@ -0,0 +1,71 @@
+//! The roots are merged into a single spec, so the settings and declarations which can't be merged
+//! are reported instead of being dropped.
+
+use zngur::{Merge, MergeFailure, SpecBuilder, Zngur, ZngurSpec};
+
+fn spec_in_namespace(cpp_namespace: &str) -> ZngurSpec {
+    let mut spec = SpecBuilder::new().build();
+    spec.cpp_namespace = cpp_namespace.to_owned();
+    spec
+}
+
+#[test]
+fn same_settings_merge() {
+    let mut spec = spec_in_namespace("storage");
+    assert!(spec_in_namespace("storage").merge(&mut spec).is_ok());
+    assert!(spec_in_namespace("").merge(&mut spec).is_ok());
+    assert_eq!(spec.cpp_namespace, "storage");
+}
+
+#[test]
+fn different_namespaces_conflict() {
+    let mut spec = spec_in_namespace("parser");
+    let Err(MergeFailure::Conflict(message)) = spec_in_namespace("storage").merge(&mut spec) else {
+        panic!("Different namespaces were merged");
+    };
+    assert_eq!(
+        message,
+        "Conflicting C++ namespaces: `parser` and `storage`"
+    );
+}
+
+#[test]
+fn generate_rejects_different_namespaces() {
+    let Err(e) = Zngur::from_spec(spec_in_namespace("parser"))
+        .with_zng_file(concat!(env!("CARGO_MANIFEST_DIR"), "/storage.zng"))
+        .with_spec(spec_in_namespace("storage"))
+        .try_generate()
+    else {
+        panic!("Different namespaces were generated");
+    };
+    assert_eq!(
+        e.0,
+        "Failed to merge the spec of input 2: Conflicting C++ namespaces: `parser` and `storage`"
+    );
+}
+
+#[test]
+#[should_panic(
+    expected = "Failed to merge the spec of input 1: Function mismatch for `crate::parser::parse_number`"
+)]
+fn generate_rejects_different_result_policies() {
+    let policy_root = r#"
+type ::std::result::Result<i32, ::std::string::String> {
+    #layout(size = 24, align = 8);
+}
+
+mod crate::parser {
+    #result_as_exception
+    fn parse_number(&str) -> ::std::result::Result<i32, ::std::string::String>;
+}
+"#;
+    let plain_root = SpecBuilder::new()
+        .func(
+            "crate::parser",
+            "fn parse_number(&str) -> ::std::result::Result<i32, ::std::string::String>",
+        )
+        .build();
+    Zngur::from_str(policy_root)
+        .with_spec(plain_root)
+        .generate();
+}
@@ -30,7 +30,7 @@ int main() {
   std::iota(v.begin(), v.end(), 1);
   auto slice = rust::std::slice::from_raw_parts(v.data(), v.size());
   auto f = Box<Dyn<Fn<Ref<uint64_t>, Bool>, Sync, Send>>::make_box(
-      [&](Ref<uint64_t> x) { return is_prime(*x); });
+      rust::assume_thread_safe([](Ref<uint64_t> x) { return is_prime(*x); }));
   std::cout << "Sum = " << slice.par_iter().sum() << std::endl;
   std::cout << "Count of primes = "
             << slice.par_iter().copied().filter(std::move(f)).count()
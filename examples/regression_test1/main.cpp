@@ -1,4 +1,7 @@
 #include <iostream>
+#include <numeric>
+#include <optional>
+#include <span>
 #include <vector>
 
 #include "./generated.h"
@@ -21,6 +24,21 @@ void test_dbg_works_for_ref_and_refmut() {
   zngur_dbg(v4);
 }
 
+void test_debug_strings() {
+  auto scope = rust::crate::Scoped::new_("Test debug strings"_rs);
+
+  rust::std::string::String v1 = "foo"_rs.to_owned();
+  std::cout << rust::debug_string(v1) << std::endl;
+  rust::Ref<rust::Str> v2 = "bar"_rs;
+  std::string s = "v2 is " + rust::debug_string(v2);
+  std::cout << s << std::endl;
+  rust::crate::Foo v3 = rust::crate::Foo{1, "baz"_rs.to_owned()};
+  std::cout << rust::debug_string(v3) << std::endl;
+  std::cout << rust::debug_pretty_string(v3) << std::endl;
+  std::cout << rust::debug_string(v3.field2) << std::endl;
+  std::cout << rust::debug_string(int32_t(5)) << std::endl;
+}
+
 template <typename T>
 concept has_push_str = requires(T v, rust::Ref<rust::Str> s) { v.push_str(s); };
 
@@ -57,6 +75,17 @@ void test_fields_and_constructor() {
   zngur_dbg(v4.f1.field2);
   v4.f1.field2.push_str("yyy"_rs);
   zngur_dbg(v4.f1.field2.len());
+
+  // `field1` has no declared offset, so it is read through an accessor.
+  zngur_dbg(*v2.f1.field1());
+  *v4.f1.field1() += 10;
+  zngur_dbg(*v3.f1.field1());
+
+  // Fields with an offset can be called like an accessor too.
+  zngur_dbg(v2.f1.field2().len());
+  v4.f1.field2().push_str("z"_rs);
+  zngur_dbg(v3.f1.field2().len());
+  zngur_dbg(v2.f0().len());
 }
 
 void test_field_underlying_conversions() {
@@ -88,6 +117,28 @@ void test_field_underlying_conversions() {
   zngur_dbg(pmut.f1.len());
 }
 
+void test_field_paths() {
+  auto scope = rust::crate::Scoped::new_("Test field paths"_rs);
+
+  using rust::crate::Point;
+  using rust::crate::Segment;
+
+  Point p{1, 2};
+  *p.y() = 5;
+  zngur_dbg(p);
+
+  Segment s{p, Point{10, 20}};
+  zngur_dbg(s.f0());
+  *s.f1_y() += 1;
+  zngur_dbg(s);
+
+  rust::Ref<Segment> r = s;
+  zngur_dbg(*r.f1().x());
+  rust::RefMut<Segment> m = s;
+  *m.f0().x() = 7;
+  zngur_dbg(s);
+}
+
 void test_floats() {
   auto scope = rust::crate::Scoped::new_("Test floats"_rs);
 
@@ -110,9 +161,309 @@ void test_floats() {
   zngur_dbg(fvec);
 }
 
+void test_std_bridges() {
+  auto scope = rust::crate::Scoped::new_("Test std bridges"_rs);
+
+  std::string s1 = "hello from C++";
+  rust::std::string::String v1 = rust::std::string::String::from_std_lossy(s1);
+  zngur_dbg(v1);
+  v1.push_str("!"_rs);
+  std::cout << v1.to_std() << std::endl;
+  rust::Ref<rust::std::string::String> v2 = v1;
+  zngur_dbg(v2.to_std().size());
+  zngur_dbg(rust::std::string::String::from_std_lossy(""));
+
+  std::vector<float> s2 = {1.5, 2.5, 3.5};
+  rust::std::vec::Vec<float> v3 = rust::std::vec::Vec<float>::from_span(s2);
+  zngur_dbg(v3);
+  v3.push(4.5);
+  rust::RefMut<rust::std::vec::Vec<float>> v4 = v3;
+  for (float x : v4.to_vector()) {
+    std::cout << x << std::endl;
+  }
+  zngur_dbg(rust::std::vec::Vec<float>::from_span(nullptr, 0));
+}
+
+void test_slices() {
+  auto scope = rust::crate::Scoped::new_("Test slices"_rs);
+
+  std::vector<float> buf = {1.5, 2.5, 3.5};
+  rust::Ref<rust::Slice<float>> v1 = buf;
+  zngur_dbg(v1);
+  zngur_dbg(v1.size());
+  zngur_dbg(float(v1[2]));
+  rust::RefMut<rust::Slice<float>> v2 = buf;
+  v2[0] = 10;
+  for (float &x : v2) {
+    x *= 2;
+  }
+  zngur_dbg(buf[0] + buf[1] + buf[2]);
+  rust::std::vec::Vec<float> v3 = v1.to_vec();
+  v3.as_mut_slice()[1] = 7;
+  zngur_dbg(v3);
+  std::span<const float> v4 = v3.as_slice();
+  zngur_dbg(std::accumulate(v4.begin(), v4.end(), 0.0f));
+  rust::Ref<rust::Slice<float>> v5(buf.data() + 1, 2);
+  zngur_dbg(v5);
+}
+
+void test_options() {
+  auto scope = rust::crate::Scoped::new_("Test options"_rs);
+
+  rust::std::vec::Vec<float> fvec = rust::std::vec::Vec<float>::new_();
+  fvec.push(1.5);
+  rust::std::option::Option<rust::Ref<float>> v1 = fvec.get(0);
+  std::cout << std::boolalpha << v1.has_value() << std::endl;
+  if (v1) {
+    zngur_dbg(*v1.value());
+    zngur_dbg(**v1);
+  }
+  rust::std::option::Option<rust::Ref<float>> v2 = fvec.get(5);
+  std::cout << bool(v2) << std::endl;
+  float fallback = 2.5;
+  zngur_dbg(*v2.value_or(fallback));
+  std::optional<rust::Ref<float>> v3 = v1;
+  std::cout << v3.has_value() << std::endl;
+
+  rust::std::option::Option<rust::RefMut<float>> v4 = fvec.get_mut(0);
+  *std::move(v4).value() = 4.5;
+  zngur_dbg(fvec);
+
+  rust::std::option::Option<rust::std::string::String> v5 = std::nullopt;
+  zngur_dbg(v5);
+  v5 = "hello"_rs.to_owned();
+  zngur_dbg(v5);
+  std::optional<rust::std::string::String> v6 = std::move(v5);
+  zngur_dbg(v6->len());
+  v5 = std::move(v6);
+  zngur_dbg(std::move(v5).value_or("world"_rs.to_owned()));
+  rust::std::option::Option<rust::std::string::String> v7 =
+      std::optional<rust::std::string::String>();
+  zngur_dbg(std::move(v7).value_or("world"_rs.to_owned()));
+}
+
+void test_results() {
+  auto scope = rust::crate::Scoped::new_("Test results"_rs);
+
+  zngur_dbg(rust::crate::parse_number("42"_rs));
+  try {
+    rust::crate::parse_number("forty two"_rs);
+  } catch (const rust::Error<rust::std::string::String> &e) {
+    zngur_dbg(e.error());
+  }
+
+  rust::Expected<int32_t, rust::std::string::String> r1 =
+      rust::crate::checked_div(10, 3);
+  std::cout << std::boolalpha << r1.has_value() << std::endl;
+  zngur_dbg(*r1);
+  rust::Expected<int32_t, rust::std::string::String> r2 =
+      rust::crate::checked_div(10, 0);
+  std::cout << bool(r2) << std::endl;
+  zngur_dbg(r2.error());
+  try {
+    r2.value();
+  } catch (const std::exception &e) {
+    std::cout << e.what() << std::endl;
+  }
+  zngur_dbg(rust::crate::checked_div(1, 0).value_or(-1));
+}
+
+void test_panics() {
+  auto scope = rust::crate::Scoped::new_("Test panics"_rs);
+
+  try {
+    rust::crate::panic_with(5);
+  } catch (const std::exception &e) {
+    std::cout << e.what() << std::endl;
+  }
+  try {
+    rust::crate::panic_with(7);
+  } catch (const rust::Panic &e) {
+    std::cout << e.what() << " at " << e.file() << ":" << e.line()
+              << std::endl;
+    std::cout << std::boolalpha << e.has_backtrace() << std::endl;
+  }
+  // The location of a panic caught inside Rust isn't reported for the next one.
+  std::cout << std::boolalpha << rust::crate::catch_inside() << std::endl;
+  try {
+    rust::crate::resume_panic();
+  } catch (const rust::Panic &e) {
+    std::cout << e.what() << " at line " << e.line() << std::endl;
+  }
+}
+
+void test_heap_allocated() {
+  auto scope = rust::crate::Scoped::new_("Test heap allocated"_rs);
+
+  size_t before = rust::crate::heap_allocations();
+  auto small = rust::crate::Small::new_(5);
+  auto moved = std::move(small);
+  zngur_dbg(moved);
+  std::cout << "Allocations for Small: "
+            << rust::crate::heap_allocations() - before << std::endl;
+
+  before = rust::crate::heap_allocations();
+  auto big = rust::crate::Big::new_(3);
+  zngur_dbg(big.sum());
+  std::cout << "Allocations for Big: "
+            << rust::crate::heap_allocations() - before << std::endl;
+
+  before = rust::crate::heap_allocations();
+  auto empty = rust::crate::Empty::new_();
+  zngur_dbg(empty);
+  std::cout << "Allocations for Empty: "
+            << rust::crate::heap_allocations() - before << std::endl;
+}
+
+class Square : public rust::crate::Shape {
+public:
+  Square(double side) : side(side) {}
+
+  rust::std::string::String name() override { return "square"_rs.to_owned(); }
+  rust::std::string::String color() override { return "red"_rs.to_owned(); }
+  ::double_t area() override { return side * side; }
+
+private:
+  double side;
+};
+
+template <> struct rust::zngur_thread_safe<Square> : ::std::true_type {};
+
+void test_supertraits() {
+  auto scope = rust::crate::Scoped::new_("Test supertraits"_rs);
+
+  Square square(2);
+  zngur_dbg(rust::crate::describe(square));
+  zngur_dbg(rust::crate::describe_boxed(
+      rust::Box<rust::Dyn<rust::crate::Shape>>::make_box<Square>(3)));
+  auto sendable =
+      rust::Box<rust::Dyn<rust::crate::Shape, rust::Send>>::make_box<Square>(4);
+  zngur_dbg(rust::crate::describe_on_worker(std::move(sendable)));
+}
+
+void test_shared_trait_objects() {
+  auto scope = rust::crate::Scoped::new_("Test shared trait objects"_rs);
+
+  using SharedShape = rust::std::sync::Arc<
+      rust::Dyn<rust::crate::Shape, rust::Send, rust::Sync>>;
+  auto arc = SharedShape::make_arc<Square>(2);
+  zngur_dbg(rust::crate::describe_on_workers(std::move(arc)));
+
+  auto square = std::make_shared<Square>(5);
+  auto from_shared = SharedShape::from_shared_ptr(square);
+  std::cout << "Owners before: " << square.use_count() << std::endl;
+  zngur_dbg(rust::crate::describe_on_workers(std::move(from_shared)));
+  std::cout << "Owners after: " << square.use_count() << std::endl;
+
+  auto rc =
+      rust::std::rc::Rc<rust::Dyn<rust::crate::Shape>>::make_rc<Square>(1);
+  zngur_dbg(rust::crate::count_owners(std::move(rc)));
+}
+
+int32_t rust::exported_functions::apply_twice(
+    rust::Ref<rust::Dyn<rust::Fn<int32_t, int32_t>>> f, int32_t x) {
+  return f(f(x));
+}
+
+rust::Unit rust::exported_functions::feed(
+    rust::RefMut<rust::Dyn<rust::FnMut<int32_t, rust::Unit>>> f, int32_t x) {
+  f(x);
+  f(x + 1);
+  return {};
+}
+
+void test_closures() {
+  auto scope = rust::crate::Scoped::new_("Test closures"_rs);
+
+  rust::crate::call_n_times(
+      4, rust::Box<rust::Dyn<rust::FnMut<int32_t, rust::Unit>>>::make_box(
+             [sum = 0](int32_t x) mutable -> rust::Unit {
+               sum += x;
+               std::cout << "Sum so far: " << sum << std::endl;
+               return {};
+             }));
+
+  auto message = std::make_unique<std::string>("consumed once");
+  auto finish =
+      rust::Box<rust::Dyn<rust::FnOnce<rust::std::string::String>>>::make_box(
+          [message = std::move(message)]() mutable {
+            return rust::std::string::String::from_std_lossy(*message);
+          });
+  zngur_dbg(rust::crate::finish_with(std::move(finish)));
+
+  auto add_five = rust::crate::make_adder(5);
+  zngur_dbg(add_five(10));
+  auto counter = rust::crate::make_counter();
+  counter();
+  counter();
+  zngur_dbg(counter());
+  auto greeting = rust::crate::make_greeting("Zngur"_rs);
+  zngur_dbg(std::move(greeting)());
+  zngur_dbg(rust::crate::apply_with_cpp(7));
+  auto multiplier = rust::crate::maybe_multiplier(true);
+  zngur_dbg(std::move(multiplier).value()(6, 7));
+}
+
+template <typename E, typename... F>
+concept visitable = requires(const E &e, F... f) { e.visit(f...); };
+
+void test_enums() {
+  auto scope = rust::crate::Scoped::new_("Test enums"_rs);
+
+  using rust::crate::Message;
+  auto describe = [](const Message &m) {
+    return m.visit(
+        [](Message::Quit_t) -> std::string { return "quit"; },
+        [](Message::Move_t v) -> std::string {
+          return "move to " + std::to_string(*v.x) + ", " +
+                 std::to_string(*v.y);
+        },
+        [](Message::Write_t w) -> std::string {
+          return "write " + std::to_string(w.f0.len()) + " bytes";
+        });
+  };
+
+  auto quit = Message::Quit();
+  auto move = Message::Move(3, 4);
+  auto write = Message::Write("hello"_rs.to_owned());
+  std::cout << describe(quit) << std::endl;
+  std::cout << describe(move) << std::endl;
+  std::cout << describe(write) << std::endl;
+
+  std::cout << std::boolalpha << (move.tag() == Message::Tag::Move)
+            << std::endl;
+  zngur_dbg(write.as_Write().f0);
+  zngur_dbg(*move.as_Move().y);
+
+  // A generic function accepts every variant.
+  write.visit([](auto v) {
+    std::cout << std::is_same_v<decltype(v), Message::Write_t> << std::endl;
+  });
+
+  // The functions must cover every variant, with the same return type.
+  auto quit_int = [](Message::Quit_t) { return 0; };
+  auto quit_str = [](Message::Quit_t) { return std::string(); };
+  auto other_str = [](auto) { return std::string(); };
+  static_assert(visitable<Message, decltype(quit_str), decltype(other_str)>);
+  static_assert(!visitable<Message, decltype(quit_int), decltype(other_str)>);
+  static_assert(!visitable<Message, decltype(quit_str)>);
+}
+
 int main() {
   test_dbg_works_for_ref_and_refmut();
+  test_debug_strings();
   test_fields_and_constructor();
   test_field_underlying_conversions();
+  test_field_paths();
   test_floats();
+  test_std_bridges();
+  test_slices();
+  test_options();
+  test_results();
+  test_panics();
+  test_heap_allocated();
+  test_supertraits();
+  test_shared_trait_objects();
+  test_closures();
+  test_enums();
 }
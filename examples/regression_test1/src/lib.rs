@@ -1,3 +1,8 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 #[rustfmt::skip]
 mod generated;
 
@@ -8,6 +13,182 @@ struct Foo {
     field2: String,
 }
 
+fn parse_number(s: &str) -> Result<i32, String> {
+    s.parse().map_err(|e| format!("invalid number {s:?}: {e}"))
+}
+
+fn checked_div(a: i32, b: i32) -> Result<i32, String> {
+    a.checked_div(b)
+        .ok_or_else(|| format!("can not divide {a} by {b}"))
+}
+
+fn panic_with(n: i32) {
+    panic!("panic with {n}");
+}
+
+fn catch_inside() -> bool {
+    std::panic::catch_unwind(|| panic!("caught inside Rust")).is_err()
+}
+
+fn resume_panic() {
+    // Unlike `panic!`, this doesn't run the panic hook, so the panic has no location.
+    std::panic::resume_unwind(Box::new("resumed"));
+}
+
+struct CountingAllocator(AtomicUsize);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+static COUNTING_ALLOCATOR: CountingAllocator = CountingAllocator(AtomicUsize::new(0));
+
+fn heap_allocations() -> usize {
+    COUNTING_ALLOCATOR.0.load(Ordering::Relaxed)
+}
+
+#[allow(unused)]
+#[derive(Debug)]
+struct Small(u64);
+
+impl Small {
+    fn new(n: u64) -> Self {
+        Self(n)
+    }
+}
+
+struct Big([u64; 8]);
+
+impl Big {
+    fn new(n: u64) -> Self {
+        Self([n; 8])
+    }
+
+    fn sum(&self) -> u64 {
+        self.0.iter().sum()
+    }
+}
+
+#[derive(Debug)]
+struct Empty;
+
+impl Empty {
+    fn new() -> Self {
+        Self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Debug)]
+struct Segment(Point, Point);
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Message {
+    Quit,
+    Move { x: i32, y: i32 },
+    Write(String),
+}
+
+trait Named {
+    fn name(&self) -> String;
+}
+
+trait Colored: Named {
+    fn color(&self) -> String;
+}
+
+trait Shape: Named + Colored {
+    fn area(&self) -> f64;
+}
+
+fn describe(shape: &dyn Shape) -> String {
+    format!(
+        "{} {} with area {}",
+        shape.color(),
+        shape.name(),
+        shape.area()
+    )
+}
+
+fn describe_boxed(shape: Box<dyn Shape>) -> String {
+    describe(&*shape)
+}
+
+fn describe_on_worker(shape: Box<dyn Shape + Send>) -> String {
+    std::thread::spawn(move || describe(&*shape))
+        .join()
+        .unwrap()
+}
+
+fn describe_on_workers(shape: Arc<dyn Shape + Send + Sync>) -> String {
+    let workers = (0..2)
+        .map(|_| {
+            let shape = shape.clone();
+            std::thread::spawn(move || describe(&*shape))
+        })
+        .collect::<Vec<_>>();
+    workers
+        .into_iter()
+        .map(|x| x.join().unwrap())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn count_owners(shape: Rc<dyn Shape>) -> usize {
+    let copy = shape.clone();
+    Rc::strong_count(&copy)
+}
+
+fn call_n_times(n: i32, mut f: Box<dyn FnMut(i32)>) {
+    for i in 0..n {
+        f(i);
+    }
+}
+
+fn finish_with(f: Box<dyn FnOnce() -> String>) -> String {
+    f()
+}
+
+fn make_adder(n: i32) -> Box<dyn Fn(i32) -> i32> {
+    Box::new(move |x| x + n)
+}
+
+fn make_counter() -> Box<dyn FnMut() -> i32> {
+    let mut count = 0;
+    Box::new(move || {
+        count += 1;
+        count
+    })
+}
+
+fn make_greeting(name: &str) -> Box<dyn FnOnce() -> String> {
+    let name = name.to_owned();
+    Box::new(move || format!("Hello, {name}!"))
+}
+
+fn maybe_multiplier(enabled: bool) -> Option<Box<dyn Fn(i32, i32) -> i32>> {
+    enabled.then(|| Box::new(|a, b| a * b) as Box<dyn Fn(i32, i32) -> i32>)
+}
+
+fn apply_with_cpp(n: i32) -> i32 {
+    let mut sum = 0;
+    generated::feed(&mut |x| sum += x, n);
+    generated::apply_twice(&|x| x * 3, n) + sum
+}
+
 struct Scoped(&'static str);
 
 impl Scoped {
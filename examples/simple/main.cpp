@@ -45,8 +45,8 @@ int main() {
   try {
     std::cout << "s[2] = " << *s.get(2).unwrap() << std::endl;
     std::cout << "s[4] = " << *s.get(4).unwrap() << std::endl;
-  } catch (rust::Panic e) {
-    std::cout << "Rust panic happened" << std::endl;
+  } catch (const rust::Panic &e) {
+    std::cout << "Rust panic happened: " << e.what() << std::endl;
   }
   int state = 0;
   // You can convert a C++ lambda into a `Box<dyn Fn>` and friends.
@@ -0,0 +1,8 @@
+// This file only uses the types of `crate::shapes`, so it includes only their header instead of
+// the whole `generated.h`.
+#include "rust.crate.shapes.h"
+
+rust::crate::shapes::Segment diagonal(int32_t size) {
+  rust::crate::shapes::Point start{0, 0};
+  return rust::crate::shapes::Segment{start, start.moved(size, size)};
+}
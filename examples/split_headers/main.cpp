@@ -0,0 +1,15 @@
+#include <iostream>
+
+#include "generated.h"
+
+rust::crate::shapes::Segment diagonal(int32_t size);
+
+int main() {
+  rust::crate::shapes::Segment segment = diagonal(3);
+  zngur_dbg(segment);
+  std::cout << "length: " << segment.end.manhattan() << std::endl;
+
+  rust::std::string::String text = rust::crate::report::describe(segment);
+  zngur_dbg(text);
+  std::cout << "text len: " << text.len() << std::endl;
+}
@@ -0,0 +1,40 @@
+#[rustfmt::skip]
+mod generated;
+
+mod shapes {
+    #[derive(Debug, Clone, Copy)]
+    pub struct Point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    impl Point {
+        pub fn manhattan(&self) -> i32 {
+            self.x.abs() + self.y.abs()
+        }
+
+        pub fn moved(self, dx: i32, dy: i32) -> Point {
+            Point {
+                x: self.x + dx,
+                y: self.y + dy,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct Segment {
+        pub start: Point,
+        pub end: Point,
+    }
+}
+
+mod report {
+    use crate::shapes::Segment;
+
+    pub fn describe(segment: &Segment) -> String {
+        format!(
+            "from ({}, {}) to ({}, {})",
+            segment.start.x, segment.start.y, segment.end.x, segment.end.y
+        )
+    }
+}
@@ -16,7 +16,7 @@ fn check_book_formatting() -> Result<()> {
 }
 
 fn check_examples(sh: &Shell, fix: bool) -> Result<()> {
-    const CARGO_PROJECTS: &[&str] = &["cxx_demo", "tutorial_cpp"];
+    const CARGO_PROJECTS: &[&str] = &["cpp_exceptions", "cxx_demo", "tutorial_cpp"];
     sh.change_dir("examples");
     let examples = cmd!(sh, "ls").read()?;
     for example in examples.lines() {
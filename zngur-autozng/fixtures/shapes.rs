@@ -0,0 +1,152 @@
+//! The crate documented by `shapes.json`, the rustdoc JSON fixture of the tests. It includes the
+//! private items, for the checks of private fields, and is generated with:
+//!
+//! ```sh
+//! RUSTC_BOOTSTRAP=1 rustdoc --edition 2024 --crate-type lib --crate-name shapes \
+//!     -Z unstable-options --output-format json --document-private-items shapes.rs -o /tmp/shapes
+//! # Keep only the external paths that the items use.
+//! jq -c '[.index | .. | objects | .id? | numbers] as $ids | .paths |= with_entries(select(
+//!     .value.crate_id == 0 or ((.key | tonumber) as $k | $ids | index($k))))' \
+//!     /tmp/shapes/shapes.json > shapes.json
+//! ```
+
+pub mod geometry {
+    use std::ops::Deref;
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    impl Point {
+        pub fn new(x: i32, y: i32) -> Point {
+            Point { x, y }
+        }
+
+        pub fn norm(&self) -> f64 {
+            ((self.x * self.x + self.y * self.y) as f64).sqrt()
+        }
+
+        pub fn translate(&mut self, dx: i32, dy: i32) {
+            self.x += dx;
+            self.y += dy;
+        }
+
+        pub fn into_tuple(self) -> (i32, i32) {
+            (self.x, self.y)
+        }
+
+        pub fn nearest<'a>(&self, points: &'a [Point]) -> Option<&'a Point> {
+            points.first()
+        }
+    }
+
+    pub trait Area {
+        fn area(&self) -> f64;
+
+        fn is_empty(&self) -> bool {
+            self.area() == 0.0
+        }
+    }
+
+    pub struct Polygon {
+        pub points: Vec<Point>,
+        name: String,
+    }
+
+    impl Polygon {
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+
+        pub fn map(&self, f: Box<dyn Fn(Point) -> Point>) -> Polygon {
+            Polygon {
+                points: self.points.iter().map(|&x| f(x)).collect(),
+                name: self.name.clone(),
+            }
+        }
+
+        pub fn scaled<T: Into<f64>>(&self, scale: T) -> f64 {
+            scale.into()
+        }
+
+        pub fn as_ptr(&self) -> *const Point {
+            self.points.as_ptr()
+        }
+    }
+
+    impl Area for Polygon {
+        fn area(&self) -> f64 {
+            0.0
+        }
+    }
+
+    pub enum Shape {
+        Empty,
+        Circle { center: Point, radius: f64 },
+        Polygon(Polygon),
+    }
+
+    impl Shape {
+        pub fn area(&self) -> f64 {
+            match self {
+                Shape::Empty => 0.0,
+                Shape::Circle { radius, .. } => 3.14 * radius * radius,
+                Shape::Polygon(polygon) => polygon.area(),
+            }
+        }
+    }
+
+    /// A point with a label, which derefs to the point.
+    pub struct Labeled {
+        pub label: String,
+        pub point: Point,
+    }
+
+    impl Deref for Labeled {
+        type Target = Point;
+
+        fn deref(&self) -> &Point {
+            &self.point
+        }
+    }
+
+    pub struct Wrapper<T> {
+        pub value: T,
+    }
+
+    impl<T> Wrapper<T> {
+        pub fn get(&self) -> &T {
+            &self.value
+        }
+    }
+}
+
+pub mod report {
+    use crate::geometry::Polygon;
+
+    pub struct Summary {
+        pub count: usize,
+    }
+
+    pub fn summarize(polygons: &[Polygon]) -> Summary {
+        Summary {
+            count: polygons.len(),
+        }
+    }
+
+    pub fn total(summaries: Vec<Summary>) -> Option<usize> {
+        Some(summaries.iter().map(|x| x.count).sum())
+    }
+}
+
+pub mod internal {
+    pub struct Cache {
+        pub size: usize,
+    }
+
+    pub fn clear(cache: &mut Cache) {
+        cache.size = 0;
+    }
+}
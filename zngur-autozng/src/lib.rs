@@ -0,0 +1,1035 @@
+//! Generates a starting .zng file for a crate from its rustdoc JSON output.
+//!
+//! Every selected struct and enum gets a `type` block with its methods, public fields and enum
+//! constructors, and every type that appears in a selected signature gets a `type` block as well.
+//! Layouts are unknown without compiling the crate, so types are `#heap_allocated`, which works for
+//! any sized type and can be replaced by `#layout` later. Items which can't be expressed in a .zng
+//! file, like generic functions, are left out with a comment saying why.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+
+use zngur_def::{
+    LayoutPolicy, Merge, MergeFailure, Mutability, PrimitiveRustType, RustPathAndGenerics,
+    RustTrait, RustType, ZngurSpec, ZngurWellknownTrait,
+};
+
+use resolve::Resolver;
+use rustdoc::{Crate, Function, Id, ItemEnum, StructKind, Type, VariantKind, Visibility};
+
+mod resolve;
+pub mod rustdoc;
+#[cfg(test)]
+mod tests;
+mod verify;
+
+pub use verify::{Mismatch, verify};
+
+/// Which items of the documented crate end up in the .zng file.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Names of the crates whose items are selected. Empty means the documented crate.
+    pub crates: Vec<String>,
+    /// Patterns like `crate::shapes::*` for the items to select. Empty means all items.
+    pub allow: Vec<String>,
+    /// Patterns for items to leave out, even if they match `allow`.
+    pub deny: Vec<String>,
+}
+
+impl Options {
+    fn is_selected(&self, path: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|p| glob_matches(p, path)))
+            && !self.deny.iter().any(|p| glob_matches(p, path))
+    }
+}
+
+/// Matches `text` against `pattern`, in which `*` matches any sequence of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| glob_matches(rest, &text[i..]))
+        }
+    }
+}
+
+fn parse_crate(json: &str) -> Result<Crate, String> {
+    serde_json::from_str(json).map_err(|e| {
+        let version = serde_json::from_str::<serde_json::Value>(json)
+            .ok()
+            .and_then(|x| x.get("format_version")?.as_u64());
+        match version {
+            Some(version) => format!("Unsupported rustdoc JSON (format version {version}): {e}"),
+            None => format!("Invalid rustdoc JSON: {e}"),
+        }
+    })
+}
+
+/// Generates the .zng file for the rustdoc JSON in `json`.
+pub fn generate(json: &str, options: &Options) -> Result<ZngFile, String> {
+    let krate = parse_crate(json)?;
+    let mut generator = Generator::new(&krate, options)?;
+    generator.run();
+    Ok(generator.file)
+}
+
+/// A generated .zng file, before it is rendered.
+#[derive(Default)]
+pub struct ZngFile {
+    /// Type blocks, by their type as written in the .zng file.
+    types: BTreeMap<String, TypeBlock>,
+    /// Free functions, by the path of their module.
+    funcs: BTreeMap<Vec<String>, Vec<Item>>,
+    /// Comments for the items that were left out entirely.
+    skipped: Vec<String>,
+}
+
+#[derive(Default)]
+struct TypeBlock {
+    ty: Option<RustType>,
+    is_enum: bool,
+    wellknown_traits: Vec<&'static str>,
+    constructors: Vec<Item>,
+    fields: Vec<Item>,
+    methods: Vec<Item>,
+    method_names: HashSet<String>,
+    /// The layout of the type in the file this one is merged into, which the new block has to
+    /// repeat.
+    existing: Option<(LayoutPolicy, Vec<ZngurWellknownTrait>)>,
+}
+
+impl TypeBlock {
+    fn items(&self) -> impl Iterator<Item = &Item> {
+        self.constructors
+            .iter()
+            .chain(&self.fields)
+            .chain(&self.methods)
+    }
+}
+
+enum Item {
+    Constructor {
+        name: Option<String>,
+        fields: ConstructorFields,
+    },
+    Field {
+        name: String,
+        ty: RustType,
+    },
+    Fn {
+        name: String,
+        receiver: Option<&'static str>,
+        inputs: Vec<RustType>,
+        output: RustType,
+        use_path: Option<Vec<String>>,
+    },
+    Comment(String),
+}
+
+enum ConstructorFields {
+    Unit,
+    Tuple(Vec<RustType>),
+    Named(Vec<(String, RustType)>),
+}
+
+impl Item {
+    fn types(&self) -> Vec<&RustType> {
+        match self {
+            Item::Constructor { fields, .. } => match fields {
+                ConstructorFields::Unit => vec![],
+                ConstructorFields::Tuple(x) => x.iter().collect(),
+                ConstructorFields::Named(x) => x.iter().map(|x| &x.1).collect(),
+            },
+            Item::Field { ty, .. } => vec![ty],
+            Item::Fn { inputs, output, .. } => inputs.iter().chain([output]).collect(),
+            Item::Comment(_) => vec![],
+        }
+    }
+}
+
+struct Generator<'a> {
+    krate: &'a Crate,
+    options: &'a Options,
+    crate_ids: HashSet<u32>,
+    resolver: Resolver<'a>,
+    file: ZngFile,
+}
+
+impl<'a> Generator<'a> {
+    fn new(krate: &'a Crate, options: &'a Options) -> Result<Self, String> {
+        let mut crate_ids = HashSet::new();
+        let root_name = krate
+            .index
+            .get(&krate.root)
+            .and_then(|x| x.name.clone())
+            .unwrap_or_default();
+        if options.crates.is_empty() {
+            crate_ids.insert(0);
+        }
+        for name in &options.crates {
+            let id = if *name == root_name {
+                Some(0)
+            } else {
+                krate
+                    .external_crates
+                    .iter()
+                    .find(|(_, x)| x.name == *name)
+                    .map(|(id, _)| *id)
+            };
+            crate_ids.insert(id.ok_or_else(|| format!("Unknown crate `{name}`"))?);
+        }
+        Ok(Self {
+            krate,
+            options,
+            crate_ids,
+            resolver: Resolver::new(krate),
+            file: ZngFile::default(),
+        })
+    }
+
+    fn run(&mut self) {
+        let mut methods = HashSet::<Id>::new();
+        for item in self.krate.index.values() {
+            match &item.inner {
+                ItemEnum::Impl(imp) => methods.extend(&imp.items),
+                ItemEnum::Trait(tr) => methods.extend(&tr.items),
+                _ => (),
+            }
+        }
+        let mut items = self
+            .krate
+            .index
+            .values()
+            .filter(|x| self.crate_ids.contains(&x.crate_id))
+            .filter_map(|x| Some((self.krate.paths.get(&x.id)?, x)))
+            .map(|(summary, x)| {
+                (
+                    self.resolver.resolve_path(&summary.path, summary.crate_id),
+                    x,
+                )
+            })
+            .collect::<Vec<_>>();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        // Types go first, so that the types used by functions already have their full block.
+        for (path, item) in &items {
+            let name = path.join("::");
+            if !self.options.is_selected(&name) {
+                continue;
+            }
+            match &item.inner {
+                ItemEnum::Struct(st) if st.generics.has_non_lifetime_params() => self.skip(&name),
+                ItemEnum::Enum(en) if en.generics.has_non_lifetime_params() => self.skip(&name),
+                ItemEnum::Struct(st) => {
+                    let ty = adt(path.clone());
+                    self.add_struct(&ty, &st.kind);
+                    self.add_impls(&ty, &name, &st.impls);
+                }
+                ItemEnum::Enum(en) => {
+                    let ty = adt(path.clone());
+                    self.add_enum(&ty, &en.variants);
+                    self.add_impls(&ty, &name, &en.impls);
+                }
+                _ => (),
+            }
+        }
+        for (path, item) in &items {
+            let name = path.join("::");
+            if !self.options.is_selected(&name) || methods.contains(&item.id) {
+                continue;
+            }
+            if let ItemEnum::Function(f) = &item.inner {
+                // Functions with another ABI are usually callbacks for C, not for Zngur.
+                if f.header.abi != "Rust" {
+                    continue;
+                }
+                let item = self.function(path.last().unwrap(), f, None, None);
+                let module = path[..path.len() - 1].to_vec();
+                self.file
+                    .funcs
+                    .entry(module)
+                    .or_default()
+                    .push(item.unwrap_or_else(|e| Item::Comment(format!("Skipped `{name}`: {e}"))));
+            }
+        }
+    }
+
+    fn skip(&mut self, name: &str) {
+        self.file.skipped.push(format!(
+            "Skipped `{name}`: generic types need a `type` block for each instantiation"
+        ));
+    }
+
+    fn block(&mut self, ty: &RustType) -> &mut TypeBlock {
+        let block = self.file.types.entry(ty.to_string()).or_default();
+        block.ty.get_or_insert_with(|| ty.clone());
+        block
+    }
+
+    /// Adds a `type` block for `ty` and the types it is made of, if they need one.
+    fn reach(&mut self, ty: &RustType) {
+        match ty {
+            RustType::Primitive(PrimitiveRustType::Str) => {
+                self.block(ty);
+            }
+            RustType::Primitive(_) => (),
+            RustType::Ref(_, inner) | RustType::Raw(_, inner) => self.reach(inner),
+            // Closures get their `type` blocks from Zngur itself.
+            RustType::Boxed(inner) if matches!(**inner, RustType::Dyn(RustTrait::Fn { .. }, _)) => {
+            }
+            RustType::Dyn(RustTrait::Fn { .. }, _) => (),
+            RustType::Boxed(inner) | RustType::Slice(inner) => {
+                self.block(ty);
+                self.reach(inner);
+            }
+            RustType::Dyn(RustTrait::Normal(pg), _) | RustType::Adt(pg) => {
+                self.block(ty);
+                for generic in pg
+                    .generics
+                    .iter()
+                    .chain(pg.named_generics.iter().map(|x| &x.1))
+                {
+                    self.reach(generic);
+                }
+            }
+            RustType::Tuple(fields) => {
+                if !fields.is_empty() {
+                    self.block(ty);
+                }
+                for field in fields {
+                    self.reach(field);
+                }
+            }
+        }
+    }
+
+    fn add_struct(&mut self, ty: &RustType, kind: &StructKind) {
+        let index = &self.krate.index;
+        let (fields, has_stripped) = match kind {
+            StructKind::Unit => (vec![], false),
+            StructKind::Tuple(fields) => (
+                fields
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(n, id)| Some((n.to_string(), index.get(id.as_ref()?)?)))
+                    .collect(),
+                fields.iter().any(|x| x.is_none()),
+            ),
+            StructKind::Plain {
+                fields,
+                has_stripped_fields,
+            } => (
+                fields
+                    .iter()
+                    .filter_map(|id| {
+                        let item = index.get(id)?;
+                        Some((item.name.clone()?, item))
+                    })
+                    .collect(),
+                *has_stripped_fields,
+            ),
+        };
+        let mut all_visible = !has_stripped;
+        let mut items = vec![];
+        let mut inputs = vec![];
+        for (name, item) in fields {
+            let ItemEnum::StructField(field_ty) = &item.inner else {
+                continue;
+            };
+            // The generated Rust code lives in the crate, so it can use crate visible fields.
+            if !matches!(item.visibility, Visibility::Public | Visibility::Crate) {
+                all_visible = false;
+                continue;
+            }
+            match self.resolver.convert(field_ty, Some(ty)) {
+                Ok(RustType::Ref(..) | RustType::Raw(..)) => {
+                    all_visible = false;
+                    items.push(Item::Comment(format!(
+                        "Skipped field `{name}`: fields of pointer types are not supported"
+                    )));
+                }
+                Ok(field_ty) => {
+                    self.reach(&field_ty);
+                    items.push(Item::Field {
+                        name: name.clone(),
+                        ty: field_ty.clone(),
+                    });
+                    inputs.push((name, field_ty));
+                }
+                Err(e) => {
+                    all_visible = false;
+                    items.push(Item::Comment(format!("Skipped field `{name}`: {e}")));
+                }
+            }
+        }
+        let constructor = match kind {
+            // A unit constructor would clash with the default constructor in C++.
+            StructKind::Unit => None,
+            _ if !all_visible => None,
+            StructKind::Tuple(_) => Some(ConstructorFields::Tuple(
+                inputs.into_iter().map(|x| x.1).collect(),
+            )),
+            StructKind::Plain { .. } => Some(ConstructorFields::Named(inputs)),
+        };
+        let block = self.block(ty);
+        block
+            .constructors
+            .extend(constructor.map(|fields| Item::Constructor { name: None, fields }));
+        block.fields = items;
+    }
+
+    fn add_enum(&mut self, ty: &RustType, variants: &[Id]) {
+        let index = &self.krate.index;
+        let mut constructors = vec![];
+        let mut is_complete = !variants.is_empty();
+        for id in variants {
+            let Some(item) = index.get(id) else {
+                is_complete = false;
+                continue;
+            };
+            let (Some(name), ItemEnum::Variant(variant)) = (&item.name, &item.inner) else {
+                continue;
+            };
+            let fields = match &variant.kind {
+                VariantKind::Plain => Ok(ConstructorFields::Unit),
+                VariantKind::Tuple(fields) => fields
+                    .iter()
+                    .map(|id| self.field_type(id.as_ref(), ty))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(ConstructorFields::Tuple),
+                VariantKind::Struct {
+                    has_stripped_fields: true,
+                    ..
+                } => Err("some fields are not documented".to_owned()),
+                VariantKind::Struct { fields, .. } => fields
+                    .iter()
+                    .map(|id| {
+                        let name = index.get(id).and_then(|x| x.name.as_ref());
+                        Ok((
+                            name.ok_or("unnamed field")?.clone(),
+                            self.field_type(Some(id), ty)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+                    .map(ConstructorFields::Named),
+            };
+            match fields {
+                Ok(fields) => constructors.push(Item::Constructor {
+                    name: Some(name.clone()),
+                    fields,
+                }),
+                Err(e) => {
+                    is_complete = false;
+                    constructors.push(Item::Comment(format!("Skipped variant `{name}`: {e}")));
+                }
+            }
+        }
+        let block = self.block(ty);
+        // `#enum` needs a constructor for every variant.
+        block.is_enum = is_complete;
+        block.constructors = constructors;
+    }
+
+    fn field_type(&mut self, id: Option<&Id>, owner: &RustType) -> Result<RustType, String> {
+        let item = id
+            .and_then(|id| self.krate.index.get(id))
+            .ok_or("some fields are not documented")?;
+        let ItemEnum::StructField(field_ty) = &item.inner else {
+            return Err("unknown field".to_owned());
+        };
+        let field_ty = self.resolver.convert(field_ty, Some(owner))?;
+        self.reach(&field_ty);
+        Ok(field_ty)
+    }
+
+    fn add_impls(&mut self, ty: &RustType, name: &str, impls: &[Id]) {
+        for id in impls {
+            let Some(ItemEnum::Impl(imp)) = self.krate.index.get(id).map(|x| &x.inner) else {
+                continue;
+            };
+            if imp.is_synthetic
+                || imp.is_negative
+                || imp.blanket_impl.is_some()
+                || imp.generics.has_non_lifetime_params()
+            {
+                continue;
+            }
+            let tr = imp.trait_.as_ref().map(|tr| self.resolver.path_of(tr));
+            if let Some(tr) = &tr {
+                match tr.join("::").as_str() {
+                    "std::fmt::Debug" => self.block(ty).wellknown_traits.push("Debug"),
+                    "std::marker::Copy" => self.block(ty).wellknown_traits.push("Copy"),
+                    _ => (),
+                }
+                // The methods of formatting traits need a `Formatter`, and `Drop::drop` can't be
+                // called directly.
+                if tr.starts_with(&["std".to_owned(), "fmt".to_owned()])
+                    || tr.join("::") == "std::ops::Drop"
+                {
+                    continue;
+                }
+            }
+            for item in &imp.items {
+                let Some(item) = self.krate.index.get(item) else {
+                    continue;
+                };
+                let (Some(method), ItemEnum::Function(f)) = (&item.name, &item.inner) else {
+                    continue;
+                };
+                if !self.options.is_selected(&format!("{name}::{method}")) {
+                    continue;
+                }
+                let item = if self.block(ty).method_names.contains(method) {
+                    Err("a method with the same name is already declared".to_owned())
+                } else {
+                    self.function(method, f, Some(ty), tr.clone())
+                };
+                let block = self.block(ty);
+                match item {
+                    Ok(item) => {
+                        block.method_names.insert(method.clone());
+                        block.methods.push(item);
+                    }
+                    Err(e) => block
+                        .methods
+                        .push(Item::Comment(format!("Skipped `{method}`: {e}"))),
+                }
+            }
+        }
+    }
+
+    /// Converts a function or method declaration, and adds `type` blocks for the types in it.
+    fn function(
+        &mut self,
+        name: &str,
+        f: &Function,
+        self_ty: Option<&RustType>,
+        use_path: Option<Vec<String>>,
+    ) -> Result<Item, String> {
+        if f.generics.has_non_lifetime_params() {
+            return Err("generic functions are not supported".to_owned());
+        }
+        if f.header.is_async {
+            return Err("async functions are not supported".to_owned());
+        }
+        if f.sig.is_c_variadic {
+            return Err("variadic functions are not supported".to_owned());
+        }
+        let mut receiver = None;
+        let mut inputs = vec![];
+        for (n, (arg, ty)) in f.sig.inputs.iter().enumerate() {
+            if n == 0 && arg == "self" {
+                receiver = Some(match ty {
+                    Type::Generic(x) if x == "Self" => "self",
+                    Type::BorrowedRef { is_mutable, inner } if matches!(&**inner, Type::Generic(x) if x == "Self") => {
+                        if *is_mutable {
+                            "&mut self"
+                        } else {
+                            "&self"
+                        }
+                    }
+                    _ => {
+                        return Err(
+                            "only `self`, `&self` and `&mut self` receivers are supported"
+                                .to_owned(),
+                        );
+                    }
+                });
+                continue;
+            }
+            inputs.push(self.resolver.convert(ty, self_ty)?);
+        }
+        let output = match &f.sig.output {
+            Some(ty) => self.resolver.convert(ty, self_ty)?,
+            None => RustType::UNIT,
+        };
+        for ty in inputs.iter().chain([&output]) {
+            self.reach(ty);
+        }
+        Ok(Item::Fn {
+            name: name.to_owned(),
+            receiver,
+            inputs,
+            output,
+            use_path,
+        })
+    }
+}
+
+impl ZngFile {
+    /// Renders the file. Paths of other crates get a `use` alias when their name is unambiguous,
+    /// and the items of the crate are nested in `mod` blocks of their modules.
+    pub fn render(&self) -> String {
+        self.render_with(&self.aliases(), true)
+    }
+
+    /// Removes the items which are already declared in `existing`, and merges the rest into it.
+    /// Returns the .zng code for the added items, which is empty if there is none.
+    ///
+    /// The added items use full paths, so that they mean the same thing next to the `use` aliases
+    /// of the existing file. The added blocks of existing types repeat their layout policy.
+    pub fn merge_into(mut self, existing: &mut ZngurSpec) -> Result<String, String> {
+        self.skipped.clear();
+        for block in self.types.values_mut() {
+            let ty = block.ty.as_ref().unwrap();
+            let is_new = |item: &Item| match item {
+                Item::Comment(_) => false,
+                _ => existing
+                    .types
+                    .iter()
+                    .filter(|x| x.ty == *ty)
+                    .all(|x| match item {
+                        Item::Constructor { name, .. } => {
+                            x.constructors.iter().all(|c| c.name != *name)
+                        }
+                        Item::Field { name, .. } => x.fields.iter().all(|f| f.name != *name),
+                        Item::Fn { name, .. } => x.methods.iter().all(|m| m.data.name != *name),
+                        Item::Comment(_) => false,
+                    }),
+            };
+            block.constructors.retain(is_new);
+            block.fields.retain(is_new);
+            block.methods.retain(is_new);
+            if let Some(x) = existing.types.iter().find(|x| x.ty == *ty) {
+                let traits = x.wellknown_traits.clone();
+                // Only `Debug` can be added, the others would change how the type is used.
+                block
+                    .wellknown_traits
+                    .retain(|x| *x == "Debug" && !traits.contains(&ZngurWellknownTrait::Debug));
+                block.is_enum &= !x.is_enum;
+                block.existing = Some((x.layout, traits));
+            }
+        }
+        self.types.retain(|_, block| {
+            block.existing.is_none()
+                || block.items().next().is_some()
+                || !block.wellknown_traits.is_empty()
+                || block.is_enum
+        });
+        for (module, items) in &mut self.funcs {
+            items.retain(|item| match item {
+                Item::Fn { name, .. } => existing.funcs.iter().all(|f| {
+                    f.path.path.len() != module.len() + 1
+                        || !f.path.path.starts_with(module)
+                        || f.path.path.last() != Some(name)
+                }),
+                _ => false,
+            });
+        }
+        self.funcs.retain(|_, items| !items.is_empty());
+        if self.types.is_empty() && self.funcs.is_empty() {
+            return Ok(String::new());
+        }
+        let text = self.render_with(&BTreeMap::new(), false);
+        let added = zngur_parser::ParsedZngFile::parse_str(&text);
+        let conflict = |e: MergeFailure| match e {
+            MergeFailure::Conflict(e) => e,
+        };
+        for ty in added.types {
+            let name = ty.ty.to_string();
+            ty.merge(existing)
+                .map_err(|e| format!("Failed to merge `{name}`: {}", conflict(e)))?;
+        }
+        for f in added.funcs {
+            f.merge(existing).map_err(conflict)?;
+        }
+        Ok(text)
+    }
+
+    /// Picks the `use` aliases for the paths of other crates, by their full path.
+    fn aliases(&self) -> BTreeMap<Vec<String>, String> {
+        let mut external = BTreeSet::new();
+        let mut local_names = HashSet::new();
+        let mut add = |path: &[String]| {
+            if path[0] == "crate" {
+                local_names.extend(path[1..].iter().cloned());
+            } else if path.len() > 1 {
+                external.insert(path.to_vec());
+            }
+        };
+        let items = self
+            .types
+            .values()
+            .flat_map(|x| x.items())
+            .chain(self.funcs.values().flatten());
+        for ty in self.types.values().map(|x| x.ty.as_ref().unwrap()) {
+            visit_paths(ty, &mut add);
+        }
+        for item in items {
+            for ty in item.types() {
+                visit_paths(ty, &mut add);
+            }
+            if let Item::Fn {
+                use_path: Some(path),
+                ..
+            } = item
+            {
+                add(path);
+            }
+        }
+        for module in self.funcs.keys() {
+            add(module);
+        }
+        let mut aliases = BTreeMap::new();
+        for path in &external {
+            let name = path.last().unwrap();
+            // The keywords of .zng files, and `Box` which is a built-in type there.
+            const RESERVED: &[&str] = &[
+                "as", "dyn", "mod", "type", "trait", "crate", "fn", "mut", "const", "use", "for",
+                "extern", "impl", "import", "Box", "Self",
+            ];
+            // Relative paths starting with an alias are expanded by it, so an alias can't share
+            // its name with anything in the crate.
+            if RESERVED.contains(&name.as_str())
+                || local_names.contains(name)
+                || external.iter().filter(|x| x.last() == Some(name)).count() > 1
+            {
+                continue;
+            }
+            aliases.insert(path.clone(), name.clone());
+        }
+        aliases
+    }
+
+    fn render_with(&self, aliases: &BTreeMap<Vec<String>, String>, nest: bool) -> String {
+        let mut root = Module::default();
+        for block in self.types.values() {
+            let module = match block.ty.as_ref().unwrap() {
+                RustType::Adt(pg) if nest && pg.path[0] == "crate" => &pg.path[..pg.path.len() - 1],
+                _ => &[],
+            };
+            root.get(module).types.push(block);
+        }
+        for (module, items) in &self.funcs {
+            root.get(module).funcs.extend(items);
+        }
+        let mut out = String::new();
+        for line in &self.skipped {
+            writeln!(out, "// {line}").unwrap();
+        }
+        if !self.skipped.is_empty() {
+            writeln!(out).unwrap();
+        }
+        for (path, name) in aliases {
+            writeln!(out, "use {} as {name};", render_path(path)).unwrap();
+        }
+        if !aliases.is_empty() {
+            writeln!(out).unwrap();
+        }
+        let printer = Printer {
+            aliases,
+            module: vec![],
+            nest,
+        };
+        root.render(&printer, 0, &mut out);
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+}
+
+/// The items of a `mod` block.
+#[derive(Default)]
+struct Module<'a> {
+    types: Vec<&'a TypeBlock>,
+    funcs: Vec<&'a Item>,
+    children: BTreeMap<String, Module<'a>>,
+}
+
+impl<'a> Module<'a> {
+    fn get(&mut self, path: &[String]) -> &mut Module<'a> {
+        match path.split_first() {
+            Some((first, rest)) => self.children.entry(first.clone()).or_default().get(rest),
+            None => self,
+        }
+    }
+
+    fn render(&self, printer: &Printer, depth: usize, out: &mut String) {
+        let indent = "    ".repeat(depth);
+        for block in &self.types {
+            printer.type_block(block, &indent, out);
+        }
+        for item in &self.funcs {
+            writeln!(out, "{indent}{}", printer.item(item)).unwrap();
+        }
+        if !self.funcs.is_empty() {
+            writeln!(out).unwrap();
+        }
+        for (name, child) in &self.children {
+            // Modules with nothing but a single module are merged into it.
+            let mut path = vec![name.clone()];
+            let mut child = child;
+            while child.types.is_empty() && child.funcs.is_empty() && child.children.len() == 1 {
+                let (name, grandchild) = child.children.iter().next().unwrap();
+                path.push(name.clone());
+                child = grandchild;
+            }
+            let mut module = printer.module.clone();
+            module.extend(path.iter().cloned());
+            let header = if printer.module.is_empty() {
+                render_path(&path)
+            } else {
+                path.join("::")
+            };
+            writeln!(out, "{indent}mod {header} {{").unwrap();
+            let printer = Printer {
+                aliases: printer.aliases,
+                module,
+                nest: printer.nest,
+            };
+            child.render(&printer, depth + 1, out);
+            out.truncate(out.trim_end().len());
+            writeln!(out, "\n{indent}}}\n").unwrap();
+        }
+    }
+}
+
+/// Writes types and paths as seen from a module of the file.
+struct Printer<'a> {
+    aliases: &'a BTreeMap<Vec<String>, String>,
+    module: Vec<String>,
+    /// Whether the paths of the crate are written relative to `module`.
+    nest: bool,
+}
+
+impl Printer<'_> {
+    fn path(&self, path: &[String]) -> String {
+        if let Some(alias) = self.aliases.get(path) {
+            return alias.clone();
+        }
+        if self.nest
+            && path[0] == "crate"
+            && path.len() > self.module.len()
+            && path.starts_with(&self.module)
+        {
+            return path[self.module.len()..].join("::");
+        }
+        render_path(path)
+    }
+
+    fn path_and_generics(&self, pg: &RustPathAndGenerics) -> String {
+        let mut out = self.path(&pg.path);
+        if !pg.generics.is_empty() || !pg.named_generics.is_empty() {
+            let generics = pg
+                .generics
+                .iter()
+                .map(|x| self.ty(x))
+                .chain(
+                    pg.named_generics
+                        .iter()
+                        .map(|(name, ty)| format!("{name} = {}", self.ty(ty))),
+                )
+                .collect::<Vec<_>>();
+            write!(out, "<{}>", generics.join(", ")).unwrap();
+        }
+        out
+    }
+
+    fn ty(&self, ty: &RustType) -> String {
+        match ty {
+            RustType::Ref(Mutability::Not, ty) => format!("&{}", self.ty(ty)),
+            RustType::Ref(Mutability::Mut, ty) => format!("&mut {}", self.ty(ty)),
+            RustType::Raw(Mutability::Not, ty) => format!("*const {}", self.ty(ty)),
+            RustType::Raw(Mutability::Mut, ty) => format!("*mut {}", self.ty(ty)),
+            RustType::Boxed(ty) => format!("Box<{}>", self.ty(ty)),
+            RustType::Slice(ty) => format!("[{}]", self.ty(ty)),
+            RustType::Tuple(fields) => format!("({})", self.types(fields)),
+            RustType::Adt(pg) => self.path_and_generics(pg),
+            RustType::Dyn(tr, markers) => {
+                let tr = match tr {
+                    RustTrait::Normal(pg) => self.path_and_generics(pg),
+                    RustTrait::Fn {
+                        name,
+                        inputs,
+                        output,
+                    } => {
+                        let mut tr = format!("{name}({})", self.types(inputs));
+                        if **output != RustType::UNIT {
+                            write!(tr, " -> {}", self.ty(output)).unwrap();
+                        }
+                        tr
+                    }
+                };
+                let mut out = format!("dyn {tr}");
+                for marker in markers {
+                    write!(out, " + {marker}").unwrap();
+                }
+                out
+            }
+            RustType::Primitive(_) => ty.to_string(),
+        }
+    }
+
+    fn types(&self, types: &[RustType]) -> String {
+        types
+            .iter()
+            .map(|x| self.ty(x))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn item(&self, item: &Item) -> String {
+        match item {
+            Item::Constructor { name, fields } => {
+                let mut out = "constructor".to_owned();
+                if let Some(name) = name {
+                    write!(out, " {name}").unwrap();
+                }
+                match fields {
+                    ConstructorFields::Unit => (),
+                    ConstructorFields::Tuple(fields) => {
+                        write!(out, "({})", self.types(fields)).unwrap()
+                    }
+                    ConstructorFields::Named(fields) => {
+                        let fields = fields
+                            .iter()
+                            .map(|(name, ty)| format!("{name}: {}", self.ty(ty)))
+                            .collect::<Vec<_>>();
+                        write!(out, " {{ {} }}", fields.join(", ")).unwrap();
+                    }
+                }
+                out + ";"
+            }
+            Item::Field { name, ty } => format!("field {name}: {};", self.ty(ty)),
+            Item::Fn {
+                name,
+                receiver,
+                inputs,
+                output,
+                use_path,
+            } => {
+                let inputs = receiver
+                    .map(|x| x.to_owned())
+                    .into_iter()
+                    .chain(inputs.iter().map(|x| self.ty(x)))
+                    .collect::<Vec<_>>();
+                let mut out = format!("fn {name}({})", inputs.join(", "));
+                if *output != RustType::UNIT {
+                    write!(out, " -> {}", self.ty(output)).unwrap();
+                }
+                if let Some(use_path) = use_path {
+                    write!(out, " use {}", self.path(use_path)).unwrap();
+                }
+                out + ";"
+            }
+            Item::Comment(comment) => format!("// {comment}"),
+        }
+    }
+
+    fn type_block(&self, block: &TypeBlock, indent: &str, out: &mut String) {
+        let ty = block.ty.as_ref().unwrap();
+        let mut header = vec![];
+        let mut wellknown_traits = vec![];
+        match &block.existing {
+            None => {
+                let is_unsized = matches!(
+                    ty,
+                    RustType::Primitive(PrimitiveRustType::Str)
+                        | RustType::Slice(_)
+                        | RustType::Dyn(..)
+                );
+                if is_unsized {
+                    wellknown_traits.push("?Sized");
+                } else {
+                    header.push("#heap_allocated;".to_owned());
+                }
+            }
+            Some((layout, traits)) => {
+                if traits.contains(&ZngurWellknownTrait::Unsized) {
+                    wellknown_traits.push("?Sized");
+                } else {
+                    header.push(match layout {
+                        LayoutPolicy::StackAllocated { size, align } => {
+                            format!("#layout(size = {size}, align = {align});")
+                        }
+                        LayoutPolicy::HeapAllocated { inline_size: None } => {
+                            "#heap_allocated;".to_owned()
+                        }
+                        LayoutPolicy::HeapAllocated {
+                            inline_size: Some(inline),
+                        } => format!("#heap_allocated(inline = {inline});"),
+                        LayoutPolicy::OnlyByRef => "#only_by_ref;".to_owned(),
+                    });
+                }
+                // Without `Copy`, the added block would make the type `Drop` as well.
+                if traits.contains(&ZngurWellknownTrait::Copy) {
+                    wellknown_traits.push("Copy");
+                }
+            }
+        }
+        if block.is_enum {
+            header.push("#enum;".to_owned());
+        }
+        wellknown_traits.extend(&block.wellknown_traits);
+        if !wellknown_traits.is_empty() {
+            header.push(format!(
+                "wellknown_traits({});",
+                wellknown_traits.join(", ")
+            ));
+        }
+        writeln!(out, "{indent}type {} {{", self.ty(ty)).unwrap();
+        let sections = [
+            header,
+            block.constructors.iter().map(|x| self.item(x)).collect(),
+            block.fields.iter().map(|x| self.item(x)).collect(),
+            block.methods.iter().map(|x| self.item(x)).collect(),
+        ];
+        let mut first = true;
+        for section in sections.iter().filter(|x| !x.is_empty()) {
+            if !first {
+                writeln!(out).unwrap();
+            }
+            first = false;
+            for line in section {
+                writeln!(out, "{indent}    {line}").unwrap();
+            }
+        }
+        writeln!(out, "{indent}}}\n").unwrap();
+    }
+}
+
+/// Calls `f` for the paths of the types and traits in `ty`.
+fn visit_paths(ty: &RustType, f: &mut impl FnMut(&[String])) {
+    match ty {
+        RustType::Primitive(_) => (),
+        RustType::Ref(_, ty) | RustType::Raw(_, ty) | RustType::Boxed(ty) | RustType::Slice(ty) => {
+            visit_paths(ty, f)
+        }
+        RustType::Tuple(fields) => {
+            for field in fields {
+                visit_paths(field, f);
+            }
+        }
+        RustType::Adt(pg) | RustType::Dyn(RustTrait::Normal(pg), _) => {
+            f(&pg.path);
+            for ty in pg
+                .generics
+                .iter()
+                .chain(pg.named_generics.iter().map(|x| &x.1))
+            {
+                visit_paths(ty, f);
+            }
+        }
+        RustType::Dyn(RustTrait::Fn { inputs, output, .. }, _) => {
+            for ty in inputs.iter().chain([&**output]) {
+                visit_paths(ty, f);
+            }
+        }
+    }
+}
+
+fn adt(path: Vec<String>) -> RustType {
+    RustType::Adt(RustPathAndGenerics {
+        path,
+        generics: vec![],
+        named_generics: vec![],
+    })
+}
+
+fn render_path(path: &[String]) -> String {
+    adt(path.to_vec()).to_string()
+}
@@ -0,0 +1,204 @@
+//! Conversion of rustdoc paths and types into Zngur ones.
+
+use zngur_def::{Mutability, PrimitiveRustType, RustPathAndGenerics, RustTrait, RustType};
+
+use crate::rustdoc::{self, Crate, GenericArg, GenericArgs, Type};
+
+pub(crate) struct Resolver<'a> {
+    pub krate: &'a Crate,
+    /// Types of the generic parameters in scope, by their name.
+    pub generics: Vec<(String, RustType)>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(krate: &'a Crate) -> Self {
+        Self {
+            krate,
+            generics: vec![],
+        }
+    }
+
+    /// Turns a path from rustdoc `paths` into the path used in .zng files.
+    pub fn resolve_path(&self, path: &[String], crate_id: u32) -> Vec<String> {
+        let mut path = path.to_vec();
+        if crate_id == 0 {
+            path[0] = "crate".to_owned();
+        } else if matches!(path[0].as_str(), "std" | "core" | "alloc") {
+            path[0] = "std".to_owned();
+            // `paths` has where the item is defined, like `core::ops::function::Fn`, and the
+            // modules under the top level ones of std are mostly private. The items are
+            // reexported by the top level modules, except for the public nested modules below.
+            const PUBLIC_NESTED_MODULES: &[&str] = &[
+                "atomic",
+                "mpsc",
+                "hash_map",
+                "hash_set",
+                "btree_map",
+                "btree_set",
+                "binary_heap",
+                "linked_list",
+                "vec_deque",
+                "unix",
+                "windows",
+            ];
+            if path.len() > 3 && !PUBLIC_NESTED_MODULES.contains(&path[2].as_str()) {
+                path.drain(2..path.len() - 1);
+            }
+        }
+        path
+    }
+
+    pub fn path_of(&self, path: &rustdoc::Path) -> Vec<String> {
+        match self.krate.paths.get(&path.id) {
+            Some(summary) => self.resolve_path(&summary.path, summary.crate_id),
+            None => path.path.split("::").map(|x| x.to_owned()).collect(),
+        }
+    }
+
+    /// Converts a rustdoc type into a Zngur type. `Self` is replaced by `self_ty`.
+    pub fn convert(&self, ty: &Type, self_ty: Option<&RustType>) -> Result<RustType, String> {
+        Ok(match ty {
+            Type::Primitive(p) => RustType::Primitive(match p.as_str() {
+                "bool" => PrimitiveRustType::Bool,
+                "str" => PrimitiveRustType::Str,
+                "usize" => PrimitiveRustType::Usize,
+                "f32" => PrimitiveRustType::Float(32),
+                "f64" => PrimitiveRustType::Float(64),
+                p => match (p.split_at(1), p[1..].parse()) {
+                    (("u", _), Ok(bits)) => PrimitiveRustType::Uint(bits),
+                    (("i", _), Ok(bits)) => PrimitiveRustType::Int(bits),
+                    _ => return Err(format!("`{p}` is not supported")),
+                },
+            }),
+            Type::Generic(x) if x == "Self" => self_ty.ok_or("`Self` outside of an impl")?.clone(),
+            Type::Generic(x) => match self.generics.iter().find(|(name, _)| name == x) {
+                Some((_, ty)) => ty.clone(),
+                None => return Err(format!("generic type `{x}` is not supported")),
+            },
+            Type::Tuple(fields) => RustType::Tuple(
+                fields
+                    .iter()
+                    .map(|x| self.convert(x, self_ty))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::Slice(inner) => RustType::Slice(Box::new(self.convert(inner, self_ty)?)),
+            Type::RawPointer { is_mutable, inner } => RustType::Raw(
+                mutability(*is_mutable),
+                Box::new(self.convert(inner, self_ty)?),
+            ),
+            Type::BorrowedRef { is_mutable, inner } => RustType::Ref(
+                mutability(*is_mutable),
+                Box::new(self.convert(inner, self_ty)?),
+            ),
+            Type::ResolvedPath(path) => {
+                let pg = self.path_and_generics(path, self_ty)?;
+                if pg.path == ["std", "boxed", "Box"] {
+                    match (pg.generics.as_slice(), pg.named_generics.as_slice()) {
+                        ([inner], []) => RustType::Boxed(Box::new(inner.clone())),
+                        _ => {
+                            return Err("`Box` with a custom allocator is not supported".to_owned());
+                        }
+                    }
+                } else {
+                    RustType::Adt(pg)
+                }
+            }
+            Type::DynTrait(dyn_trait) => {
+                let mut tr = None;
+                let mut markers = vec![];
+                for poly in &dyn_trait.traits {
+                    let path = self.path_of(&poly.trait_);
+                    match path.last().map(|x| x.as_str()) {
+                        Some(marker @ ("Send" | "Sync")) => markers.push(marker.to_owned()),
+                        _ if tr.is_some() => {
+                            return Err(
+                                "trait objects of several traits are not supported".to_owned()
+                            );
+                        }
+                        _ => tr = Some(self.convert_trait(&poly.trait_, path, self_ty)?),
+                    }
+                }
+                RustType::Dyn(
+                    tr.ok_or("trait objects without a trait are not supported")?,
+                    markers,
+                )
+            }
+            Type::QualifiedPath { name } => {
+                return Err(format!("associated type `{name}` is not supported"));
+            }
+            Type::Other(_) => return Err("this kind of type is not supported".to_owned()),
+        })
+    }
+
+    fn convert_trait(
+        &self,
+        tr: &rustdoc::Path,
+        path: Vec<String>,
+        self_ty: Option<&RustType>,
+    ) -> Result<RustTrait, String> {
+        let name = path.last().unwrap();
+        if let ("Fn" | "FnMut" | "FnOnce", Some(GenericArgs::Parenthesized { inputs, output })) =
+            (name.as_str(), tr.args.as_deref())
+        {
+            return Ok(RustTrait::Fn {
+                name: name.clone(),
+                inputs: inputs
+                    .iter()
+                    .map(|x| self.convert(x, self_ty))
+                    .collect::<Result<_, _>>()?,
+                output: Box::new(match output {
+                    Some(output) => self.convert(output, self_ty)?,
+                    None => RustType::UNIT,
+                }),
+            });
+        }
+        Ok(RustTrait::Normal(self.path_and_generics(tr, self_ty)?))
+    }
+
+    pub fn path_and_generics(
+        &self,
+        path: &rustdoc::Path,
+        self_ty: Option<&RustType>,
+    ) -> Result<RustPathAndGenerics, String> {
+        let mut pg = RustPathAndGenerics {
+            path: self.path_of(path),
+            generics: vec![],
+            named_generics: vec![],
+        };
+        match path.args.as_deref() {
+            None => (),
+            Some(GenericArgs::AngleBracketed { args, constraints }) => {
+                for arg in args {
+                    match arg {
+                        GenericArg::Lifetime(_) => (),
+                        GenericArg::Type(ty) => pg.generics.push(self.convert(ty, self_ty)?),
+                        GenericArg::Other(_) => {
+                            return Err("const generics are not supported".to_owned());
+                        }
+                    }
+                }
+                for constraint in constraints {
+                    let rustdoc::AssocItemConstraintKind::Equality(rustdoc::Term::Type(ty)) =
+                        &constraint.binding
+                    else {
+                        return Err("associated type bounds are not supported".to_owned());
+                    };
+                    pg.named_generics
+                        .push((constraint.name.clone(), self.convert(ty, self_ty)?));
+                }
+            }
+            Some(_) => {
+                return Err("parenthesized generics are only supported on `Fn` traits".to_owned());
+            }
+        }
+        Ok(pg)
+    }
+}
+
+fn mutability(is_mutable: bool) -> Mutability {
+    if is_mutable {
+        Mutability::Mut
+    } else {
+        Mutability::Not
+    }
+}
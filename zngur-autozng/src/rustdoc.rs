@@ -0,0 +1,282 @@
+//! The parts of the rustdoc JSON format (`--output-format json`) that autozng reads. Unknown
+//! fields are ignored, and unknown kinds of items and types are kept as raw JSON values.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+pub type Id = u32;
+
+#[derive(Debug, Deserialize)]
+pub struct Crate {
+    pub root: Id,
+    pub index: HashMap<Id, Item>,
+    pub paths: HashMap<Id, ItemSummary>,
+    pub external_crates: HashMap<u32, ExternalCrate>,
+    pub format_version: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExternalCrate {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemSummary {
+    pub crate_id: u32,
+    pub path: Vec<String>,
+    pub kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Item {
+    pub id: Id,
+    pub crate_id: u32,
+    pub name: Option<String>,
+    pub visibility: Visibility,
+    pub inner: ItemEnum,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Public,
+    /// The visibility of enum variants and trait items, which is that of their parent.
+    Default,
+    Crate,
+    Restricted(Value),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemEnum {
+    Module(Module),
+    Function(Function),
+    Struct(Struct),
+    StructField(Type),
+    Enum(Enum),
+    Variant(Variant),
+    Impl(Impl),
+    Trait(Trait),
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Trait {
+    pub items: Vec<Id>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Module {
+    pub items: Vec<Id>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Function {
+    pub sig: FunctionSignature,
+    pub generics: Generics,
+    pub header: FunctionHeader,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionSignature {
+    pub inputs: Vec<(String, Type)>,
+    pub output: Option<Type>,
+    pub is_c_variadic: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionHeader {
+    pub is_async: bool,
+    pub abi: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Generics {
+    pub params: Vec<GenericParamDef>,
+}
+
+impl Generics {
+    /// Lifetimes are elided in .zng files, but type and const parameters need to be known.
+    pub fn has_non_lifetime_params(&self) -> bool {
+        self.params
+            .iter()
+            .any(|p| !matches!(p.kind, GenericParamDefKind::Lifetime(_)))
+    }
+
+    /// Names of the parameters which are given explicitly, so not lifetimes or `impl Trait`
+    /// arguments.
+    pub fn explicit_params(&self) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|p| match &p.kind {
+                GenericParamDefKind::Lifetime(_) => false,
+                GenericParamDefKind::Type(x) => x.get("is_synthetic") != Some(&Value::Bool(true)),
+                GenericParamDefKind::Const(_) => true,
+            })
+            .map(|p| p.name.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenericParamDef {
+    pub name: String,
+    pub kind: GenericParamDefKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenericParamDefKind {
+    Lifetime(Value),
+    Type(Value),
+    Const(Value),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Struct {
+    pub kind: StructKind,
+    pub generics: Generics,
+    pub impls: Vec<Id>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructKind {
+    Unit,
+    /// `None` for fields stripped from the documentation.
+    Tuple(Vec<Option<Id>>),
+    Plain {
+        fields: Vec<Id>,
+        has_stripped_fields: bool,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Enum {
+    pub generics: Generics,
+    pub variants: Vec<Id>,
+    pub impls: Vec<Id>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Variant {
+    pub kind: VariantKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantKind {
+    Plain,
+    Tuple(Vec<Option<Id>>),
+    Struct {
+        fields: Vec<Id>,
+        has_stripped_fields: bool,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Impl {
+    pub generics: Generics,
+    #[serde(rename = "trait")]
+    pub trait_: Option<Path>,
+    #[serde(rename = "for")]
+    pub for_: Type,
+    pub items: Vec<Id>,
+    pub is_negative: bool,
+    pub is_synthetic: bool,
+    pub blanket_impl: Option<Type>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    ResolvedPath(Path),
+    DynTrait(DynTrait),
+    Generic(String),
+    Primitive(String),
+    Tuple(Vec<Type>),
+    Slice(Box<Type>),
+    RawPointer {
+        is_mutable: bool,
+        #[serde(rename = "type")]
+        inner: Box<Type>,
+    },
+    BorrowedRef {
+        is_mutable: bool,
+        #[serde(rename = "type")]
+        inner: Box<Type>,
+    },
+    QualifiedPath {
+        name: String,
+    },
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Path {
+    /// The path as written in the source, which is only used when `id` is not in `paths`.
+    pub path: String,
+    pub id: Id,
+    pub args: Option<Box<GenericArgs>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenericArgs {
+    AngleBracketed {
+        args: Vec<GenericArg>,
+        constraints: Vec<AssocItemConstraint>,
+    },
+    Parenthesized {
+        inputs: Vec<Type>,
+        output: Option<Type>,
+    },
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenericArg {
+    Lifetime(String),
+    Type(Type),
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssocItemConstraint {
+    pub name: String,
+    pub binding: AssocItemConstraintKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssocItemConstraintKind {
+    Equality(Term),
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Term {
+    Type(Type),
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DynTrait {
+    pub traits: Vec<PolyTrait>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PolyTrait {
+    #[serde(rename = "trait")]
+    pub trait_: Path,
+}
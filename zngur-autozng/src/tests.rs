@@ -0,0 +1,612 @@
+use expect_test::{Expect, expect};
+use itertools::Itertools;
+use zngur_parser::ParsedZngFile;
+
+use crate::{Options, generate, verify};
+
+/// The rustdoc JSON of `fixtures/shapes.rs`.
+const SHAPES: &str = include_str!("../fixtures/shapes.json");
+
+fn check_generate(options: Options, zng: Expect) {
+    zng.assert_eq(&generate(SHAPES, &options).unwrap().render());
+}
+
+fn check_verify(zng: &str, mismatches: Expect) {
+    let spec = ParsedZngFile::parse_str(zng);
+    let result = verify(SHAPES, &spec).unwrap();
+    mismatches.assert_eq(&result.iter().map(|x| &x.message).join("\n"));
+}
+
+#[test]
+fn verify_matching_spec() {
+    check_verify(
+        r#"
+type ::std::vec::Vec<crate::geometry::Point> {
+    #heap_allocated;
+}
+
+type ::std::option::Option<&crate::geometry::Point> {
+    #heap_allocated;
+}
+
+type [crate::geometry::Point] {
+    wellknown_traits(?Sized);
+}
+
+type [crate::geometry::Polygon] {
+    wellknown_traits(?Sized);
+}
+
+mod crate::geometry {
+    type Point {
+        #heap_allocated;
+
+        constructor { x: i32, y: i32 };
+        field x: i32;
+
+        fn new(i32, i32) -> Point;
+        fn norm(&self) -> f64;
+        fn translate(&mut self, i32, i32);
+        fn into_tuple(self) -> (i32, i32);
+        fn nearest(&self, &[Point]) -> ::std::option::Option<&Point>;
+        fn clone(&self) -> Point;
+    }
+
+    type Polygon {
+        #heap_allocated;
+
+        field points: ::std::vec::Vec<Point>;
+
+        fn map(&self, Box<dyn Fn(Point) -> Point>) -> Polygon;
+        fn area(&self) -> f64 use Area;
+        fn is_empty(&self) -> bool use Area;
+    }
+
+    type Shape {
+        #heap_allocated;
+
+        constructor Empty;
+        constructor Circle { center: Point, radius: f64 };
+        constructor Polygon(Polygon);
+    }
+
+    type Labeled {
+        #heap_allocated;
+
+        field point.x: i32;
+
+        fn norm(&self) -> f64 deref Point;
+    }
+
+    type Wrapper<i32> {
+        #heap_allocated;
+
+        field value: i32;
+
+        fn get(&self) -> &i32;
+    }
+}
+
+mod crate::report {
+    fn summarize(&[crate::geometry::Polygon]) -> Summary;
+}
+"#,
+        expect![""],
+    );
+}
+
+#[test]
+fn verify_missing_method() {
+    check_verify(
+        r#"
+type crate::geometry::Point {
+    #heap_allocated;
+
+    fn length(&self) -> f64;
+    fn is_empty(&self) -> bool use crate::geometry::Area;
+}
+"#,
+        expect![[r#"
+            `crate::geometry::Point` has no method `length`
+            `crate::geometry::Point` has no method `is_empty` from `crate::geometry::Area`"#]],
+    );
+}
+
+#[test]
+fn verify_wrong_arity() {
+    check_verify(
+        r#"
+type crate::geometry::Point {
+    #heap_allocated;
+
+    fn translate(&mut self, i32) -> ();
+}
+
+mod crate::report {
+    fn summarize() -> Summary;
+}
+"#,
+        expect![[r#"
+            `crate::geometry::Point::translate` takes 2 arguments, but 1 are declared
+            `crate::report::summarize` takes 1 arguments, but 0 are declared"#]],
+    );
+}
+
+#[test]
+fn verify_wrong_receiver() {
+    check_verify(
+        r#"
+type crate::geometry::Point {
+    #heap_allocated;
+
+    fn norm(self) -> f64;
+    fn translate(&self, i32, i32);
+    fn new(&self, i32, i32) -> crate::geometry::Point;
+}
+"#,
+        expect![[r#"
+            `crate::geometry::Point::norm` takes `&self`, but `self` is declared
+            `crate::geometry::Point::translate` takes `&mut self`, but `&self` is declared
+            `crate::geometry::Point::new` takes no `self`, but `&self` is declared"#]],
+    );
+}
+
+#[test]
+fn verify_wrong_types() {
+    check_verify(
+        r#"
+type crate::geometry::Point {
+    #heap_allocated;
+
+    constructor { x: i64, y: i32 };
+
+    fn new(i32, u32) -> crate::geometry::Point;
+    fn norm(&self) -> f32;
+}
+
+type crate::geometry::Wrapper<i32> {
+    #heap_allocated;
+
+    fn get(&self) -> &i64;
+}
+"#,
+        expect![[r#"
+            Argument 2 of `crate::geometry::Point::new` is `i32`, but `u32` is declared
+            `crate::geometry::Point::norm` returns `f64`, but `f32` is declared
+            Field `x` is `i32`, but `i64` is declared
+            `crate::geometry::Wrapper::<i32>::get` returns `&i32`, but `&i64` is declared"#]],
+    );
+}
+
+#[test]
+fn verify_missing_fields() {
+    check_verify(
+        r#"
+type crate::geometry::Point {
+    #heap_allocated;
+
+    constructor { x: i32 };
+    field z: i32;
+}
+
+type crate::geometry::Shape {
+    #heap_allocated;
+
+    constructor Square(f64);
+    constructor Circle { center: crate::geometry::Point, diameter: f64 };
+}
+"#,
+        expect![[r#"
+            Field `y` is missing
+            `crate::geometry::Point` has no field `z`
+            `crate::geometry::Shape` has no variant `Square`
+            There is no field `diameter`
+            Field `radius` is missing"#]],
+    );
+}
+
+#[test]
+fn verify_private_field() {
+    check_verify(
+        r#"
+type crate::geometry::Polygon {
+    #heap_allocated;
+
+    field name: ::std::string::String;
+}
+"#,
+        expect!["Field `name` of `crate::geometry::Polygon` is private"],
+    );
+}
+
+#[test]
+fn verify_missing_items() {
+    check_verify(
+        r#"
+type crate::geometry::Circle {
+    #heap_allocated;
+}
+
+type crate::geometry::Wrapper {
+    #heap_allocated;
+}
+
+mod crate::report {
+    fn average(&[crate::geometry::Polygon]) -> f64;
+}
+"#,
+        expect![[r#"
+            `crate::geometry::Circle` is not found in the crate
+            `crate::geometry::Wrapper` has 1 generic parameters, but 0 are given
+            `crate::report::average` is not found in the crate"#]],
+    );
+}
+
+#[test]
+fn generate_all() {
+    check_generate(
+        Options::default(),
+        expect![[r#"
+        // Skipped `crate::geometry::Wrapper`: generic types need a `type` block for each instantiation
+
+        use ::std::clone::Clone as Clone;
+        use ::std::ops::Deref as Deref;
+        use ::std::option::Option as Option;
+        use ::std::string::String as String;
+        use ::std::vec::Vec as Vec;
+
+        type (i32, i32) {
+            #heap_allocated;
+        }
+
+        type Option<&crate::geometry::Point> {
+            #heap_allocated;
+        }
+
+        type Option<usize> {
+            #heap_allocated;
+        }
+
+        type String {
+            #heap_allocated;
+        }
+
+        type Vec<crate::geometry::Point> {
+            #heap_allocated;
+        }
+
+        type Vec<crate::report::Summary> {
+            #heap_allocated;
+        }
+
+        type [crate::geometry::Point] {
+            wellknown_traits(?Sized);
+        }
+
+        type [crate::geometry::Polygon] {
+            wellknown_traits(?Sized);
+        }
+
+        type str {
+            wellknown_traits(?Sized);
+        }
+
+        mod crate {
+            mod geometry {
+                type Labeled {
+                    #heap_allocated;
+
+                    constructor { label: String, point: Point };
+
+                    field label: String;
+                    field point: Point;
+
+                    fn deref(&self) -> &Point use Deref;
+                }
+
+                type Point {
+                    #heap_allocated;
+                    wellknown_traits(Debug, Copy);
+
+                    constructor { x: i32, y: i32 };
+
+                    field x: i32;
+                    field y: i32;
+
+                    fn new(i32, i32) -> Point;
+                    fn norm(&self) -> f64;
+                    fn translate(&mut self, i32, i32);
+                    fn into_tuple(self) -> (i32, i32);
+                    fn nearest(&self, &[Point]) -> Option<&Point>;
+                    fn clone(&self) -> Point use Clone;
+                }
+
+                type Polygon {
+                    #heap_allocated;
+
+                    field points: Vec<Point>;
+
+                    fn name(&self) -> &str;
+                    fn map(&self, Box<dyn Fn(Point) -> Point>) -> Polygon;
+                    // Skipped `scaled`: generic functions are not supported
+                    fn as_ptr(&self) -> *const Point;
+                    fn area(&self) -> f64 use Area;
+                }
+
+                type Shape {
+                    #heap_allocated;
+                    #enum;
+
+                    constructor Empty;
+                    constructor Circle { center: Point, radius: f64 };
+                    constructor Polygon(Polygon);
+
+                    fn area(&self) -> f64;
+                }
+            }
+
+            mod internal {
+                type Cache {
+                    #heap_allocated;
+
+                    constructor { size: usize };
+
+                    field size: usize;
+                }
+
+                fn clear(&mut Cache);
+            }
+
+            mod report {
+                type Summary {
+                    #heap_allocated;
+
+                    constructor { count: usize };
+
+                    field count: usize;
+                }
+
+                fn summarize(&[crate::geometry::Polygon]) -> Summary;
+                fn total(Vec<Summary>) -> Option<usize>;
+            }
+        }
+    "#]],
+    );
+}
+
+#[test]
+fn generate_selected_crate() {
+    let options = Options {
+        crates: vec!["shapes".to_owned()],
+        ..Options::default()
+    };
+    assert_eq!(
+        generate(SHAPES, &options).unwrap().render(),
+        generate(SHAPES, &Options::default()).unwrap().render(),
+    );
+    let options = Options {
+        crates: vec!["geometry".to_owned()],
+        ..Options::default()
+    };
+    expect!["Unknown crate `geometry`"].assert_eq(&generate(SHAPES, &options).err().unwrap());
+}
+
+#[test]
+fn generate_allow_and_deny() {
+    // The types in the signatures of the selected functions get a `type` block, without their
+    // methods.
+    check_generate(
+        Options {
+            allow: vec!["crate::report::*".to_owned()],
+            deny: vec!["crate::report::total".to_owned()],
+            ..Options::default()
+        },
+        expect![[r#"
+            type [crate::geometry::Polygon] {
+                wellknown_traits(?Sized);
+            }
+
+            mod crate {
+                mod geometry {
+                    type Polygon {
+                        #heap_allocated;
+                    }
+                }
+
+                mod report {
+                    type Summary {
+                        #heap_allocated;
+
+                        constructor { count: usize };
+
+                        field count: usize;
+                    }
+
+                    fn summarize(&[crate::geometry::Polygon]) -> Summary;
+                }
+            }
+        "#]],
+    );
+}
+
+#[test]
+fn generate_methods_by_pattern() {
+    check_generate(
+        Options {
+            allow: vec!["crate::geometry::Point*".to_owned()],
+            deny: vec!["*::clone".to_owned(), "*::n*".to_owned()],
+            ..Options::default()
+        },
+        expect![[r#"
+            type (i32, i32) {
+                #heap_allocated;
+            }
+
+            mod crate::geometry {
+                type Point {
+                    #heap_allocated;
+                    wellknown_traits(Debug, Copy);
+
+                    constructor { x: i32, y: i32 };
+
+                    field x: i32;
+                    field y: i32;
+
+                    fn translate(&mut self, i32, i32);
+                    fn into_tuple(self) -> (i32, i32);
+                }
+            }
+        "#]],
+    );
+}
+
+#[test]
+fn generate_unsupported_json() {
+    let error = generate(r#"{"format_version": 1}"#, &Options::default()).err();
+    expect![
+        "Unsupported rustdoc JSON (format version 1): missing field `root` at line 1 column 21"
+    ]
+    .assert_eq(&error.unwrap());
+}
+
+#[test]
+fn merge_into_existing_spec() {
+    let mut spec = ParsedZngFile::parse_str(
+        r#"
+type crate::geometry::Point {
+    #layout(size = 8, align = 4);
+    wellknown_traits(Copy);
+
+    field x (offset = 0, type = i32);
+
+    fn norm(&self) -> f64;
+}
+
+mod crate::report {
+    fn summarize(&[crate::geometry::Polygon]) -> crate::report::Summary;
+}
+"#,
+    );
+    let options = Options {
+        allow: vec![
+            "crate::geometry::Point*".to_owned(),
+            "crate::report::*".to_owned(),
+        ],
+        deny: vec!["*::clone".to_owned()],
+        ..Options::default()
+    };
+    let file = generate(SHAPES, &options).unwrap();
+    // The added block of `Point` repeats its layout, and has only the items that are missing.
+    expect![[r#"
+        type (i32, i32) {
+            #heap_allocated;
+        }
+
+        type ::std::option::Option<&crate::geometry::Point> {
+            #heap_allocated;
+        }
+
+        type ::std::option::Option<usize> {
+            #heap_allocated;
+        }
+
+        type ::std::vec::Vec<crate::report::Summary> {
+            #heap_allocated;
+        }
+
+        type [crate::geometry::Point] {
+            wellknown_traits(?Sized);
+        }
+
+        type [crate::geometry::Polygon] {
+            wellknown_traits(?Sized);
+        }
+
+        type crate::geometry::Point {
+            #layout(size = 8, align = 4);
+            wellknown_traits(Copy, Debug);
+
+            constructor { x: i32, y: i32 };
+
+            field y: i32;
+
+            fn new(i32, i32) -> crate::geometry::Point;
+            fn translate(&mut self, i32, i32);
+            fn into_tuple(self) -> (i32, i32);
+            fn nearest(&self, &[crate::geometry::Point]) -> ::std::option::Option<&crate::geometry::Point>;
+        }
+
+        type crate::geometry::Polygon {
+            #heap_allocated;
+        }
+
+        type crate::report::Summary {
+            #heap_allocated;
+
+            constructor { count: usize };
+
+            field count: usize;
+        }
+
+        mod crate::report {
+            fn total(::std::vec::Vec<crate::report::Summary>) -> ::std::option::Option<usize>;
+        }
+    "#]].assert_eq(&file.merge_into(&mut spec).unwrap());
+    expect![[r#"
+        type crate::geometry::Point {
+            #layout(size = 8, align = 4);
+            wellknown_traits(Copy, Debug);
+            constructor { x: i32, y: i32 };
+            field x (offset = 0, type = i32);
+            field y: i32;
+            fn norm(&self) -> f64;
+            fn new(i32, i32) -> crate::geometry::Point;
+            fn translate(&mut self, i32, i32);
+            fn into_tuple(self) -> (i32, i32);
+            fn nearest(&self, &[crate::geometry::Point]) -> ::std::option::Option::<&crate::geometry::Point>;
+        }
+
+        type (i32, i32) {
+            #heap_allocated;
+        }
+
+        type ::std::option::Option::<&crate::geometry::Point> {
+            #heap_allocated;
+        }
+
+        type ::std::option::Option::<usize> {
+            #heap_allocated;
+        }
+
+        type ::std::vec::Vec::<crate::report::Summary> {
+            #heap_allocated;
+        }
+
+        type [crate::geometry::Point] {
+            wellknown_traits(?Sized);
+        }
+
+        type [crate::geometry::Polygon] {
+            wellknown_traits(?Sized);
+        }
+
+        type crate::geometry::Polygon {
+            #heap_allocated;
+        }
+
+        type crate::report::Summary {
+            #heap_allocated;
+            constructor { count: usize };
+            field count: usize;
+        }
+
+        mod crate::report {
+            fn summarize(&[crate::geometry::Polygon]) -> crate::report::Summary;
+            fn total(::std::vec::Vec::<crate::report::Summary>) -> ::std::option::Option::<usize>;
+        }
+    "#]].assert_eq(&ParsedZngFile::print(&spec));
+
+    // Merging again adds nothing.
+    let file = generate(SHAPES, &options).unwrap();
+    assert_eq!(file.merge_into(&mut spec).unwrap(), "");
+}
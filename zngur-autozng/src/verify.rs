@@ -0,0 +1,757 @@
+//! Checks the declarations of a spec against the rustdoc JSON of the crate, so that mistakes are
+//! reported at the .zng file instead of as errors in the generated Rust code.
+//!
+//! Only the items of the documented crate are checked, since the JSON has no details of other
+//! crates. Paths are compared by their crate and their last segment, because an item can be
+//! reached by several paths through reexports, and types that can't be converted, like associated
+//! types, are not compared.
+
+use zngur_def::{
+    Mutability, RustPathAndGenerics, RustTrait, RustType, ZngurConstructor, ZngurField, ZngurFn,
+    ZngurMethodDetails, ZngurMethodReceiver, ZngurSpec, ZngurType,
+};
+use zngur_parser::SpecItem;
+
+use crate::resolve::Resolver;
+use crate::rustdoc::{
+    Crate, Function, GenericArg, GenericArgs, Generics, Id, Impl, Item, ItemEnum, StructKind, Type,
+    VariantKind, Visibility,
+};
+
+/// A declaration of the spec which doesn't match the crate.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub item: SpecItem,
+    pub message: String,
+}
+
+/// Checks the methods, functions, constructors and fields of `spec` against the rustdoc JSON in
+/// `json`.
+pub fn verify(json: &str, spec: &ZngurSpec) -> Result<Vec<Mismatch>, String> {
+    let krate = crate::parse_crate(json)?;
+    let mut verifier = Verifier::new(&krate);
+    for ty in &spec.types {
+        verifier.verify_type(ty);
+    }
+    for f in &spec.funcs {
+        verifier.verify_fn(f);
+    }
+    Ok(verifier.mismatches)
+}
+
+/// Methods of the traits in the prelude, which don't need a `use` and are often implemented by
+/// blanket impls that the JSON has no items for.
+const PRELUDE_METHODS: &[&str] = &[
+    "clone",
+    "clone_from",
+    "to_owned",
+    "to_string",
+    "into",
+    "try_into",
+    "from",
+    "try_from",
+    "eq",
+    "ne",
+    "partial_cmp",
+    "cmp",
+    "lt",
+    "le",
+    "gt",
+    "ge",
+    "max",
+    "min",
+    "clamp",
+    "default",
+    "into_iter",
+    "next",
+    "extend",
+    "as_ref",
+    "as_mut",
+];
+
+enum Lookup<'a> {
+    /// The method, and the generic parameters of its impl.
+    Found(&'a Function, Vec<(String, RustType)>),
+    NotFound,
+    /// The method might come from a trait which the JSON has no items for.
+    Unknown,
+}
+
+struct Verifier<'a> {
+    resolver: Resolver<'a>,
+    /// The structs and enums of the crate.
+    types: Vec<(Vec<String>, &'a Item)>,
+    traits: Vec<(Vec<String>, &'a Item)>,
+    fns: Vec<(Vec<String>, &'a Item)>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(krate: &'a Crate) -> Self {
+        let resolver = Resolver::new(krate);
+        let mut types = vec![];
+        let mut traits = vec![];
+        let mut fns = vec![];
+        for item in krate.index.values().filter(|x| x.crate_id == 0) {
+            let Some(summary) = krate.paths.get(&item.id) else {
+                continue;
+            };
+            let path = resolver.resolve_path(&summary.path, summary.crate_id);
+            match &item.inner {
+                ItemEnum::Struct(_) | ItemEnum::Enum(_) => types.push((path, item)),
+                ItemEnum::Trait(_) => traits.push((path, item)),
+                ItemEnum::Function(_) => fns.push((path, item)),
+                _ => (),
+            }
+        }
+        Self {
+            resolver,
+            types,
+            traits,
+            fns,
+            mismatches: vec![],
+        }
+    }
+
+    fn report(&mut self, item: SpecItem, message: String) {
+        self.mismatches.push(Mismatch { item, message });
+    }
+
+    fn get(&self, id: &Id) -> Option<&'a Item> {
+        self.resolver.krate.index.get(id)
+    }
+
+    fn convert(&self, ty: &Type, self_ty: &RustType) -> Option<RustType> {
+        self.resolver.convert(ty, Some(self_ty)).ok()
+    }
+
+    /// The struct or enum at `path` and the generic arguments of `ty`, as the generic parameters
+    /// of the item.
+    fn find_type(&self, ty: &RustType) -> Option<(&'a Item, Vec<(String, RustType)>)> {
+        let RustType::Adt(pg) = ty else {
+            return None;
+        };
+        let item = find(&self.types, &pg.path)?;
+        let generics = match &item.inner {
+            ItemEnum::Struct(st) => &st.generics,
+            ItemEnum::Enum(en) => &en.generics,
+            _ => return None,
+        };
+        Some((item, bind(generics, &pg.generics)?))
+    }
+
+    fn verify_type(&mut self, ty: &ZngurType) {
+        let RustType::Adt(pg) = &ty.ty else {
+            return;
+        };
+        if pg.path[0] != "crate" {
+            return;
+        }
+        let Some(item) = find(&self.types, &pg.path) else {
+            self.report(
+                SpecItem::Type(ty.ty.clone()),
+                format!("`{}` is not found in the crate", ty.ty),
+            );
+            return;
+        };
+        let Some((_, generics)) = self.find_type(&ty.ty) else {
+            let count = match &item.inner {
+                ItemEnum::Struct(st) => st.generics.explicit_params().len(),
+                ItemEnum::Enum(en) => en.generics.explicit_params().len(),
+                _ => 0,
+            };
+            self.report(
+                SpecItem::Type(ty.ty.clone()),
+                format!(
+                    "`{}` has {count} generic parameters, but {} are given",
+                    ty.ty,
+                    pg.generics.len()
+                ),
+            );
+            return;
+        };
+        for method in &ty.methods {
+            self.resolver.generics = generics.clone();
+            self.verify_method(&ty.ty, item, method);
+        }
+        for constructor in &ty.constructors {
+            self.resolver.generics = generics.clone();
+            self.verify_constructor(&ty.ty, item, constructor);
+        }
+        for field in &ty.fields {
+            self.resolver.generics = generics.clone();
+            self.verify_field(&ty.ty, item, field);
+        }
+        self.resolver.generics.clear();
+    }
+
+    fn verify_method(&mut self, ty: &RustType, item: &'a Item, method: &ZngurMethodDetails) {
+        let spec_item = SpecItem::Method(ty.clone(), method.data.name.clone());
+        // Methods reached through `Deref` are looked up on the target type.
+        let (owner_ty, owner) = match &method.deref {
+            Some((target, _)) => match self.find_type(target) {
+                Some((owner, generics)) => {
+                    self.resolver.generics = generics;
+                    (target, owner)
+                }
+                None => return,
+            },
+            None => (ty, item),
+        };
+        let name = &method.data.name;
+        match self.find_method(owner_ty, owner, name, method.use_path.as_deref()) {
+            Lookup::Found(f, impl_generics) => {
+                self.resolver.generics.extend(impl_generics);
+                let what = format!("`{owner_ty}::{name}`");
+                self.verify_signature(
+                    spec_item,
+                    &what,
+                    f,
+                    owner_ty,
+                    method.data.receiver,
+                    &method.data.generics,
+                    &method.data.inputs,
+                    &method.data.output,
+                );
+            }
+            Lookup::NotFound => {
+                let message = match &method.use_path {
+                    Some(tr) => format!(
+                        "`{owner_ty}` has no method `{name}` from `{}`",
+                        tr.join("::")
+                    ),
+                    None => format!("`{owner_ty}` has no method `{name}`"),
+                };
+                self.report(spec_item, message);
+            }
+            Lookup::Unknown => (),
+        }
+    }
+
+    fn find_method(
+        &mut self,
+        owner_ty: &RustType,
+        owner: &'a Item,
+        name: &str,
+        use_path: Option<&[String]>,
+    ) -> Lookup<'a> {
+        let impls = match &owner.inner {
+            ItemEnum::Struct(st) => &st.impls,
+            ItemEnum::Enum(en) => &en.impls,
+            _ => return Lookup::Unknown,
+        };
+        let impls = impls
+            .iter()
+            .filter_map(|id| match &self.get(id)?.inner {
+                ItemEnum::Impl(imp) if !imp.is_negative => Some(imp),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Blanket impls apply to types that the JSON doesn't resolve, so the methods of their
+        // traits are unknown.
+        let has_blanket_impl = impls
+            .iter()
+            .any(|imp| imp.blanket_impl.is_some() && self.is_impl_of(imp, use_path));
+        let mut impls = impls
+            .iter()
+            .filter(|imp| imp.blanket_impl.is_none())
+            .collect::<Vec<_>>();
+        // Inherent methods are found first, like in Rust.
+        impls.sort_by_key(|imp| imp.trait_.is_some());
+        let owner_generics = self.resolver.generics.clone();
+        let mut is_implemented = false;
+        for imp in impls {
+            if use_path.is_some() && !self.is_impl_of(imp, use_path) {
+                continue;
+            }
+            // The parameters of the impl are bound by matching its type with the declared one,
+            // which also skips the impls of other instantiations.
+            let mut impl_generics = vec![];
+            if let (Type::ResolvedPath(path), RustType::Adt(pg)) = (&imp.for_, owner_ty) {
+                if let Some(GenericArgs::AngleBracketed { args, .. }) = path.args.as_deref() {
+                    let types = args.iter().filter_map(|x| match x {
+                        GenericArg::Type(ty) => Some(ty),
+                        _ => None,
+                    });
+                    for (arg, ty) in types.zip(&pg.generics) {
+                        if let Type::Generic(param) = arg {
+                            impl_generics.push((param.clone(), ty.clone()));
+                        }
+                    }
+                }
+            }
+            self.resolver.generics = owner_generics
+                .iter()
+                .cloned()
+                .chain(impl_generics.clone())
+                .collect();
+            let for_ = self.convert(&imp.for_, owner_ty);
+            self.resolver.generics = owner_generics.clone();
+            if !for_.is_some_and(|x| same_type(&x, owner_ty)) {
+                continue;
+            }
+            is_implemented = true;
+            for id in &imp.items {
+                if let Some(Item {
+                    name: Some(item_name),
+                    inner: ItemEnum::Function(f),
+                    ..
+                }) = self.get(id)
+                {
+                    if item_name == name {
+                        return Lookup::Found(f, impl_generics);
+                    }
+                }
+            }
+        }
+        match use_path {
+            Some(_) if !is_implemented && has_blanket_impl => Lookup::Unknown,
+            Some(_) if !is_implemented => Lookup::NotFound,
+            Some(use_path) => {
+                // Provided methods of a trait are only in the items of the trait.
+                let Some(tr) = find(&self.traits, use_path) else {
+                    return Lookup::Unknown;
+                };
+                let ItemEnum::Trait(tr) = &tr.inner else {
+                    return Lookup::Unknown;
+                };
+                for id in &tr.items {
+                    if let Some(Item {
+                        name: Some(item_name),
+                        inner: ItemEnum::Function(f),
+                        ..
+                    }) = self.get(id)
+                    {
+                        if item_name == name {
+                            return Lookup::Found(f, vec![]);
+                        }
+                    }
+                }
+                Lookup::NotFound
+            }
+            None if PRELUDE_METHODS.contains(&name) => Lookup::Unknown,
+            None => Lookup::NotFound,
+        }
+    }
+
+    /// Whether `imp` implements the trait at `path`.
+    fn is_impl_of(&self, imp: &Impl, path: Option<&[String]>) -> bool {
+        match (&imp.trait_, path) {
+            (Some(tr), Some(path)) => same_path(&self.resolver.path_of(tr), path),
+            _ => false,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn verify_signature(
+        &mut self,
+        spec_item: SpecItem,
+        what: &str,
+        f: &Function,
+        self_ty: &RustType,
+        receiver: ZngurMethodReceiver,
+        generics: &[RustType],
+        inputs: &[RustType],
+        output: &RustType,
+    ) {
+        let params = f.generics.explicit_params();
+        if params.len() != generics.len() {
+            self.report(
+                spec_item,
+                format!(
+                    "{what} has {} generic parameters, but {} are given",
+                    params.len(),
+                    generics.len()
+                ),
+            );
+            return;
+        }
+        self.resolver.generics.extend(
+            params
+                .iter()
+                .map(|x| x.to_string())
+                .zip(generics.iter().cloned()),
+        );
+        let mut rust_inputs = f.sig.inputs.iter().map(|x| &x.1).collect::<Vec<_>>();
+        let rust_receiver = match f.sig.inputs.first() {
+            Some((name, ty)) if name == "self" => {
+                rust_inputs.remove(0);
+                match ty {
+                    Type::Generic(x) if x == "Self" => Some(ZngurMethodReceiver::Move),
+                    Type::BorrowedRef { is_mutable, inner } if matches!(&**inner, Type::Generic(x) if x == "Self") => {
+                        Some(ZngurMethodReceiver::Ref(if *is_mutable {
+                            Mutability::Mut
+                        } else {
+                            Mutability::Not
+                        }))
+                    }
+                    // Receivers like `self: Box<Self>` can't be declared anyway.
+                    _ => None,
+                }
+            }
+            _ => Some(ZngurMethodReceiver::Static),
+        };
+        if let Some(rust_receiver) = rust_receiver {
+            if rust_receiver != receiver {
+                self.report(
+                    spec_item,
+                    format!(
+                        "{what} takes {}, but {} is declared",
+                        receiver_name(rust_receiver),
+                        receiver_name(receiver)
+                    ),
+                );
+                return;
+            }
+        }
+        if rust_inputs.len() != inputs.len() {
+            self.report(
+                spec_item,
+                format!(
+                    "{what} takes {} arguments, but {} are declared",
+                    rust_inputs.len(),
+                    inputs.len()
+                ),
+            );
+            return;
+        }
+        for (n, (rust_ty, ty)) in rust_inputs.into_iter().zip(inputs).enumerate() {
+            if let Some(rust_ty) = self.convert(rust_ty, self_ty) {
+                if !same_type(&rust_ty, ty) {
+                    self.report(
+                        spec_item.clone(),
+                        format!(
+                            "Argument {} of {what} is `{rust_ty}`, but `{ty}` is declared",
+                            n + 1
+                        ),
+                    );
+                }
+            }
+        }
+        let rust_output = match &f.sig.output {
+            Some(ty) => self.convert(ty, self_ty),
+            None => Some(RustType::UNIT),
+        };
+        if let Some(rust_output) = rust_output {
+            if !same_type(&rust_output, output) {
+                self.report(
+                    spec_item,
+                    format!("{what} returns `{rust_output}`, but `{output}` is declared"),
+                );
+            }
+        }
+    }
+
+    /// The fields of a struct or a variant, by name, and whether some fields are not documented.
+    fn fields_of(&self, kind: FieldsKind<'a>) -> (Vec<(String, &'a Item)>, bool) {
+        let (ids, has_stripped) = match kind {
+            FieldsKind::Unit => return (vec![], false),
+            FieldsKind::Tuple(ids) => {
+                return (
+                    ids.iter()
+                        .enumerate()
+                        .filter_map(|(n, id)| Some((n.to_string(), self.get(id.as_ref()?)?)))
+                        .collect(),
+                    ids.iter().any(|x| x.is_none()),
+                );
+            }
+            FieldsKind::Named(ids, has_stripped) => (ids, has_stripped),
+        };
+        (
+            ids.iter()
+                .filter_map(|id| {
+                    let item = self.get(id)?;
+                    Some((item.name.clone()?, item))
+                })
+                .collect(),
+            has_stripped,
+        )
+    }
+
+    fn verify_constructor(
+        &mut self,
+        ty: &RustType,
+        item: &'a Item,
+        constructor: &ZngurConstructor,
+    ) {
+        let spec_item = SpecItem::Constructor(ty.clone(), constructor.name.clone());
+        let kind = match (&item.inner, &constructor.name) {
+            (ItemEnum::Struct(st), None) => FieldsKind::of_struct(&st.kind),
+            (ItemEnum::Struct(_), Some(_)) => {
+                self.report(
+                    spec_item,
+                    format!("`{ty}` is a struct, so its constructor has no name"),
+                );
+                return;
+            }
+            (ItemEnum::Enum(_), None) => {
+                self.report(
+                    spec_item,
+                    format!("`{ty}` is an enum, so its constructors need a variant name"),
+                );
+                return;
+            }
+            (ItemEnum::Enum(en), Some(name)) => {
+                let variant =
+                    en.variants
+                        .iter()
+                        .filter_map(|id| self.get(id))
+                        .find_map(|x| match &x.inner {
+                            ItemEnum::Variant(v) if x.name.as_ref() == Some(name) => Some(v),
+                            _ => None,
+                        });
+                let Some(variant) = variant else {
+                    self.report(spec_item, format!("`{ty}` has no variant `{name}`"));
+                    return;
+                };
+                FieldsKind::of_variant(&variant.kind)
+            }
+            _ => return,
+        };
+        let (fields, has_stripped) = self.fields_of(kind);
+        for (name, field_ty) in &constructor.inputs {
+            let Some((_, field)) = fields.iter().find(|x| x.0 == *name) else {
+                if !has_stripped {
+                    self.report(spec_item.clone(), format!("There is no field `{name}`"));
+                }
+                continue;
+            };
+            let ItemEnum::StructField(rust_ty) = &field.inner else {
+                continue;
+            };
+            if let Some(rust_ty) = self.convert(rust_ty, ty) {
+                if !same_type(&rust_ty, field_ty) {
+                    self.report(
+                        spec_item.clone(),
+                        format!("Field `{name}` is `{rust_ty}`, but `{field_ty}` is declared"),
+                    );
+                }
+            }
+        }
+        if !has_stripped {
+            for (name, _) in &fields {
+                if constructor.inputs.iter().all(|x| x.0 != *name) {
+                    self.report(spec_item.clone(), format!("Field `{name}` is missing"));
+                }
+            }
+        }
+    }
+
+    fn verify_field(&mut self, ty: &RustType, item: &'a Item, field: &ZngurField) {
+        let spec_item = SpecItem::Field(ty.clone(), field.name.clone());
+        let mut owner = item;
+        let mut owner_ty = ty.clone();
+        let segments = field.name.split('.').collect::<Vec<_>>();
+        for (n, segment) in segments.iter().enumerate() {
+            let ItemEnum::Struct(st) = &owner.inner else {
+                self.report(spec_item, format!("`{owner_ty}` is not a struct"));
+                return;
+            };
+            let (fields, has_stripped) = self.fields_of(FieldsKind::of_struct(&st.kind));
+            let Some((_, rust_field)) = fields.iter().find(|x| x.0 == *segment) else {
+                if !has_stripped {
+                    self.report(spec_item, format!("`{owner_ty}` has no field `{segment}`"));
+                }
+                return;
+            };
+            // The generated code is in a child module of the crate root, so it can see the fields
+            // which are private to the root, which rustdoc calls crate visible, but not the ones
+            // which are private to other modules.
+            if matches!(rust_field.visibility, Visibility::Restricted(_)) {
+                self.report(
+                    spec_item,
+                    format!("Field `{segment}` of `{owner_ty}` is private"),
+                );
+                return;
+            }
+            let ItemEnum::StructField(rust_ty) = &rust_field.inner else {
+                return;
+            };
+            let Some(rust_ty) = self.convert(rust_ty, &owner_ty) else {
+                return;
+            };
+            if n + 1 == segments.len() {
+                if !same_type(&rust_ty, &field.ty) {
+                    self.report(
+                        spec_item,
+                        format!(
+                            "Field `{}` is `{rust_ty}`, but `{}` is declared",
+                            field.name, field.ty
+                        ),
+                    );
+                }
+                return;
+            }
+            let Some((next, generics)) = self.find_type(&rust_ty) else {
+                return;
+            };
+            self.resolver.generics = generics;
+            owner = next;
+            owner_ty = rust_ty;
+        }
+    }
+
+    fn verify_fn(&mut self, f: &ZngurFn) {
+        if f.path.path[0] != "crate" {
+            return;
+        }
+        let spec_item = SpecItem::Fn(f.path.path.clone());
+        let Some(item) = find(&self.fns, &f.path.path) else {
+            self.report(
+                spec_item,
+                format!("`{}` is not found in the crate", f.path.path.join("::")),
+            );
+            return;
+        };
+        let ItemEnum::Function(rust_fn) = &item.inner else {
+            return;
+        };
+        self.resolver.generics.clear();
+        let what = format!("`{}`", f.path.path.join("::"));
+        self.verify_signature(
+            spec_item,
+            &what,
+            rust_fn,
+            &RustType::UNIT,
+            ZngurMethodReceiver::Static,
+            &f.path.generics,
+            &f.inputs,
+            &f.output,
+        );
+        self.resolver.generics.clear();
+    }
+}
+
+enum FieldsKind<'a> {
+    Unit,
+    Tuple(&'a [Option<Id>]),
+    Named(&'a [Id], bool),
+}
+
+impl<'a> FieldsKind<'a> {
+    fn of_struct(kind: &'a StructKind) -> Self {
+        match kind {
+            StructKind::Unit => FieldsKind::Unit,
+            StructKind::Tuple(ids) => FieldsKind::Tuple(ids),
+            StructKind::Plain {
+                fields,
+                has_stripped_fields,
+            } => FieldsKind::Named(fields, *has_stripped_fields),
+        }
+    }
+
+    fn of_variant(kind: &'a VariantKind) -> Self {
+        match kind {
+            VariantKind::Plain => FieldsKind::Unit,
+            VariantKind::Tuple(ids) => FieldsKind::Tuple(ids),
+            VariantKind::Struct {
+                fields,
+                has_stripped_fields,
+            } => FieldsKind::Named(fields, *has_stripped_fields),
+        }
+    }
+}
+
+/// Binds the generic parameters of an item to the given arguments.
+fn bind(generics: &Generics, args: &[RustType]) -> Option<Vec<(String, RustType)>> {
+    let params = generics.explicit_params();
+    (params.len() == args.len()).then(|| {
+        params
+            .into_iter()
+            .map(|x| x.to_owned())
+            .zip(args.iter().cloned())
+            .collect()
+    })
+}
+
+/// Finds the item at `path`, or else the only item with the same name, which is reexported.
+fn find<'a>(items: &[(Vec<String>, &'a Item)], path: &[String]) -> Option<&'a Item> {
+    if let Some((_, item)) = items.iter().find(|x| x.0 == path) {
+        return Some(item);
+    }
+    let mut candidates = items.iter().filter(|x| same_path(&x.0, path));
+    match (candidates.next(), candidates.next()) {
+        (Some((_, item)), None) => Some(item),
+        _ => None,
+    }
+}
+
+fn crate_of(path: &[String]) -> &str {
+    match path[0].as_str() {
+        "core" | "alloc" => "std",
+        x => x,
+    }
+}
+
+fn same_path(a: &[String], b: &[String]) -> bool {
+    a == b || (crate_of(a) == crate_of(b) && a.last() == b.last())
+}
+
+fn same_path_and_generics(a: &RustPathAndGenerics, b: &RustPathAndGenerics) -> bool {
+    same_path(&a.path, &b.path)
+        && a.generics.len() == b.generics.len()
+        && a.generics
+            .iter()
+            .zip(&b.generics)
+            .all(|(a, b)| same_type(a, b))
+        && a.named_generics.len() == b.named_generics.len()
+        && a.named_generics.iter().all(|(name, a)| {
+            b.named_generics
+                .iter()
+                .any(|x| x.0 == *name && same_type(a, &x.1))
+        })
+}
+
+fn same_types(a: &[RustType], b: &[RustType]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_type(a, b))
+}
+
+/// Compares two types, with paths compared by [`same_path`].
+fn same_type(a: &RustType, b: &RustType) -> bool {
+    match (a, b) {
+        (RustType::Adt(a), RustType::Adt(b)) => same_path_and_generics(a, b),
+        (RustType::Ref(m1, a), RustType::Ref(m2, b))
+        | (RustType::Raw(m1, a), RustType::Raw(m2, b)) => m1 == m2 && same_type(a, b),
+        (RustType::Boxed(a), RustType::Boxed(b)) | (RustType::Slice(a), RustType::Slice(b)) => {
+            same_type(a, b)
+        }
+        (RustType::Tuple(a), RustType::Tuple(b)) => same_types(a, b),
+        (RustType::Dyn(a, markers_a), RustType::Dyn(b, markers_b)) => {
+            let same_trait = match (a, b) {
+                (RustTrait::Normal(a), RustTrait::Normal(b)) => same_path_and_generics(a, b),
+                (
+                    RustTrait::Fn {
+                        name: name_a,
+                        inputs: inputs_a,
+                        output: output_a,
+                    },
+                    RustTrait::Fn {
+                        name: name_b,
+                        inputs: inputs_b,
+                        output: output_b,
+                    },
+                ) => {
+                    name_a == name_b
+                        && same_types(inputs_a, inputs_b)
+                        && same_type(output_a, output_b)
+                }
+                _ => false,
+            };
+            let mut markers_a = markers_a.clone();
+            let mut markers_b = markers_b.clone();
+            markers_a.sort();
+            markers_b.sort();
+            same_trait && markers_a == markers_b
+        }
+        _ => a == b,
+    }
+}
+
+fn receiver_name(receiver: ZngurMethodReceiver) -> &'static str {
+    match receiver {
+        ZngurMethodReceiver::Static => "no `self`",
+        ZngurMethodReceiver::Ref(Mutability::Not) => "`&self`",
+        ZngurMethodReceiver::Ref(Mutability::Mut) => "`&mut self`",
+        ZngurMethodReceiver::Move => "`self`",
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use zngur_parser::ParsedZngFile;
+
+#[derive(clap::Args)]
+pub struct Dump {
+    /// Path to the zng file
+    path: PathBuf,
+
+    /// Print JSON, which `zngur generate` accepts instead of a zng file, instead of the Rust debug
+    /// format
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run(args: Dump) {
+    let spec = ParsedZngFile::parse(args.path);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&spec).unwrap());
+    } else {
+        println!("{spec:#?}");
+    }
+}
@@ -0,0 +1,25 @@
+use std::{path::PathBuf, process::exit};
+
+use zngur_parser::ParsedZngFile;
+
+#[derive(clap::Args)]
+pub struct Flatten {
+    /// Path to the zng file
+    path: PathBuf,
+
+    /// Path of the flattened file. Default is printing it to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn run(args: Flatten) {
+    let spec = ParsedZngFile::parse(args.path);
+    let text = ParsedZngFile::print(&spec);
+    match args.output {
+        Some(output) => std::fs::write(&output, text).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {e}", output.display());
+            exit(1);
+        }),
+        None => print!("{text}"),
+    }
+}
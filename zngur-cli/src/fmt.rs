@@ -0,0 +1,37 @@
+use std::{path::PathBuf, process::exit};
+
+use zngur_parser::ParsedZngFile;
+
+#[derive(clap::Args)]
+pub struct Fmt {
+    /// Paths to the zng files
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Don't write the files, but exit with an error if any of them is not formatted
+    #[arg(long)]
+    check: bool,
+}
+
+pub fn run(args: Fmt) {
+    let mut unformatted = false;
+    for path in args.paths {
+        let text = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {e}", path.display());
+            exit(1);
+        });
+        let formatted = ParsedZngFile::format(path.clone(), &text);
+        if formatted == text {
+            continue;
+        }
+        if args.check {
+            eprintln!("{} is not formatted", path.display());
+            unformatted = true;
+        } else {
+            std::fs::write(&path, formatted).unwrap();
+        }
+    }
+    if unformatted {
+        exit(1);
+    }
+}
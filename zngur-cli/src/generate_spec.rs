@@ -0,0 +1,160 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, exit},
+};
+
+use zngur_autozng::Options;
+use zngur_parser::ParsedZngFile;
+
+#[derive(clap::Args)]
+pub struct GenerateSpec {
+    /// Path to the Cargo.toml of the crate, which is documented by running rustdoc
+    ///
+    /// Default is the Cargo.toml in the current directory
+    #[arg(long, conflicts_with = "rustdoc_json")]
+    manifest_path: Option<PathBuf>,
+
+    /// Path to the rustdoc JSON of the crate, if it is already generated
+    #[arg(long)]
+    rustdoc_json: Option<PathBuf>,
+
+    /// The toolchain used for running rustdoc. The JSON output needs a nightly one
+    #[arg(long, default_value = "nightly")]
+    toolchain: String,
+
+    /// Name of a crate whose items are included. Can be repeated
+    ///
+    /// Default is the documented crate
+    #[arg(long = "crate")]
+    crates: Vec<String>,
+
+    /// Only include the items matching this pattern, like `crate::shapes::*`. Can be repeated
+    #[arg(long)]
+    allow: Vec<String>,
+
+    /// Leave out the items matching this pattern, like `crate::generated::*`. Can be repeated
+    #[arg(long)]
+    deny: Vec<String>,
+
+    /// Path of the zng file. If it already exists, the items it is missing are appended to it,
+    /// and the rest of it is kept as is
+    ///
+    /// Default is printing a new zng file to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{message}");
+    exit(1);
+}
+
+fn write(path: &Path, contents: &str) {
+    std::fs::write(path, contents)
+        .unwrap_or_else(|e| fail(format!("Failed to write {}: {e}", path.display())));
+}
+
+fn invalid_metadata() -> ! {
+    fail("Unexpected output of `cargo metadata`");
+}
+
+/// Runs `cargo rustdoc` with the JSON output, and returns the path of the JSON file.
+fn run_rustdoc(manifest_path: &Path, toolchain: &str) -> PathBuf {
+    let manifest_path = manifest_path
+        .canonicalize()
+        .unwrap_or_else(|e| fail(format!("Failed to find {}: {e}", manifest_path.display())));
+    let metadata = Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+            "--manifest-path",
+        ])
+        .arg(&manifest_path)
+        .output()
+        .unwrap_or_else(|e| fail(format!("Failed to run cargo: {e}")));
+    if !metadata.status.success() {
+        fail(String::from_utf8_lossy(&metadata.stderr));
+    }
+    let metadata: serde_json::Value =
+        serde_json::from_slice(&metadata.stdout).unwrap_or_else(|_| invalid_metadata());
+    let package = metadata["packages"]
+        .as_array()
+        .unwrap_or_else(|| invalid_metadata())
+        .iter()
+        .find(|x| x["manifest_path"].as_str().map(Path::new) == Some(&manifest_path))
+        .unwrap_or_else(|| fail("The manifest has no package"));
+    let lib = package["targets"]
+        .as_array()
+        .unwrap_or_else(|| invalid_metadata())
+        .iter()
+        .find(|x| {
+            x["kind"]
+                .as_array()
+                .unwrap_or_else(|| invalid_metadata())
+                .iter()
+                .any(|x| x.as_str().is_some_and(|x| x.ends_with("lib")))
+        })
+        .unwrap_or_else(|| fail("The package has no library target"));
+    let status = Command::new("cargo")
+        .arg(format!("+{toolchain}"))
+        .args(["rustdoc", "--lib", "--manifest-path"])
+        .arg(&manifest_path)
+        .args(["--", "-Z", "unstable-options", "--output-format", "json"])
+        .status()
+        .unwrap_or_else(|e| fail(format!("Failed to run cargo: {e}")));
+    if !status.success() {
+        exit(status.code().unwrap_or(1));
+    }
+    let target_directory = metadata["target_directory"]
+        .as_str()
+        .unwrap_or_else(|| invalid_metadata());
+    let name = lib["name"].as_str().unwrap_or_else(|| invalid_metadata());
+    Path::new(target_directory)
+        .join("doc")
+        .join(format!("{}.json", name.replace('-', "_")))
+}
+
+pub fn run(args: GenerateSpec) {
+    let json_path = match args.rustdoc_json {
+        Some(path) => path,
+        None => run_rustdoc(
+            &args
+                .manifest_path
+                .unwrap_or_else(|| PathBuf::from("Cargo.toml")),
+            &args.toolchain,
+        ),
+    };
+    let json = std::fs::read_to_string(&json_path)
+        .unwrap_or_else(|e| fail(format!("Failed to read {}: {e}", json_path.display())));
+    let options = Options {
+        crates: args.crates,
+        allow: args.allow,
+        deny: args.deny,
+    };
+    let zng = zngur_autozng::generate(&json, &options).unwrap_or_else(|e| fail(e));
+    let Some(output) = args.output else {
+        print!("{}", zng.render());
+        return;
+    };
+    if !output.exists() {
+        write(&output, &zng.render());
+        return;
+    }
+    let mut spec = ParsedZngFile::parse(output.clone());
+    let added = zng.merge_into(&mut spec).unwrap_or_else(|e| fail(e));
+    if added.is_empty() {
+        eprintln!("{} already has all of the items", output.display());
+        return;
+    }
+    let mut text = std::fs::read_to_string(&output)
+        .unwrap_or_else(|e| fail(format!("Failed to read {}: {e}", output.display())));
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push('\n');
+    text.push_str(&added);
+    write(&output, &text);
+    eprintln!("Added the missing items to {}", output.display());
+}
@@ -0,0 +1,672 @@
+//! A language server for zng files, which talks JSON-RPC over stdin and stdout. It analyzes every
+//! open document with its imports on each change, using the text of the open documents instead of
+//! the files on disk.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeResult, Location, MarkupContent, MarkupKind, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, ServerInfo, SymbolKind,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Exit, Notification, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Initialize, Request,
+        Shutdown,
+    },
+};
+use serde_json::{Value, json};
+use zngur_def::{RustTrait, RustType};
+use zngur_parser::{Analysis, ImportResolver, Located, ParsedZngFile, SpecItem};
+
+const KEYWORDS: &[&str] = &[
+    "type",
+    "trait",
+    "fn",
+    "mod",
+    "use",
+    "import",
+    "extern \"C++\"",
+    "impl",
+    "constructor",
+    "field",
+    "wellknown_traits",
+    "deref",
+    "dyn",
+    "mut",
+    "crate",
+    "self",
+    "Box",
+    "Debug",
+    "Copy",
+    "?Sized",
+    "#layout",
+    "#heap_allocated",
+    "#only_by_ref",
+    "#cpp_value",
+    "#cpp_ref",
+    "#cpp_std_bridge",
+    "#enum",
+    "#result_as_exception",
+    "#result_as_expected",
+    "#cpp_exception",
+    "#convert_panic_to_exception",
+    "#heap_allocator",
+    "#cpp_additional_includes",
+];
+
+pub fn run() {
+    let mut server = Server::default();
+    let mut input = std::io::stdin().lock();
+    while let Some(message) = read_message(&mut input) {
+        server.handle(message);
+        for message in server.outbox.drain(..) {
+            write_message(message);
+        }
+    }
+}
+
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn write_message(message: Value) {
+    let body = message.to_string();
+    let mut output = std::io::stdout().lock();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    output.flush().unwrap();
+}
+
+fn notification<N: Notification>(params: N::Params) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": N::METHOD,
+        "params": params,
+    })
+}
+
+/// The path of the file at `uri`, if it is a `file:` URI.
+fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    let encoded = uri.as_str().strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = encoded.as_bytes();
+    while let [first, tail @ ..] = rest {
+        match (first, tail) {
+            (b'%', [a, b, tail @ ..]) => {
+                let hex = std::str::from_utf8(&[*a, *b]).ok()?.to_owned();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                rest = tail;
+            }
+            _ => {
+                bytes.push(*first);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // Windows paths are written like `/C:/dir/file.zng`.
+    match path.as_bytes() {
+        [b'/', _, b':', ..] => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+fn path_to_uri(path: &Path) -> Uri {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri += &format!("%{byte:02X}"),
+        }
+    }
+    Uri::from_str(&uri).unwrap()
+}
+
+/// Makes the paths of the same file equal, like the import resolver of the parser does.
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .unwrap_or_else(|_| path.components().collect())
+}
+
+fn position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |x| x + 1);
+    Position::new(
+        text[..offset].matches('\n').count() as u32,
+        text[line_start..offset].encode_utf16().count() as u32,
+    )
+}
+
+fn range(text: &str, range: &std::ops::Range<usize>) -> Range {
+    // Errors at the end of input have a reversed span.
+    let start = range.start.min(range.end);
+    Range::new(position(text, start), position(text, range.end.max(start)))
+}
+
+fn offset(text: &str, position: Position) -> usize {
+    let line_start = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(|x| x.len())
+        .sum::<usize>();
+    let mut character = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if character >= position.character as usize || c == '\n' {
+            return line_start + i;
+        }
+        character += c.len_utf16();
+    }
+    text.len()
+}
+
+fn render_path(path: &[String]) -> String {
+    match path.first().map(|x| x.as_str()) {
+        Some("crate") => path.join("::"),
+        _ => format!("::{}", path.join("::")),
+    }
+}
+
+/// The text of the open documents, which is used instead of the files on disk.
+struct Documents<'a>(&'a HashMap<PathBuf, String>);
+
+impl ImportResolver for Documents<'_> {
+    fn resolve_import(&self, cwd: &Path, relpath: &Path) -> Result<String, String> {
+        let path = normalize(&cwd.join(relpath));
+        match self.0.get(&path) {
+            Some(text) => Ok(text.clone()),
+            None => std::fs::read_to_string(path).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<PathBuf, String>,
+    /// The analyses of the open documents, with normalized paths.
+    analyses: HashMap<PathBuf, Analysis>,
+    /// The files which have diagnostics from the analysis of each open document.
+    published: HashMap<PathBuf, Vec<PathBuf>>,
+    shutdown: bool,
+    /// The messages to send to the client.
+    outbox: Vec<Value>,
+}
+
+impl Server {
+    fn handle(&mut self, message: Value) {
+        let Some(method) = message["method"].as_str() else {
+            // A response, but the server doesn't send requests.
+            return;
+        };
+        let params = message["params"].clone();
+        let Some(id) = message.get("id").cloned() else {
+            self.handle_notification(method, params);
+            return;
+        };
+        let response = match self.handle_request(method, params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        self.outbox.push(response);
+    }
+
+    fn handle_request(&mut self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        fn call<R: Request>(
+            params: Value,
+            f: impl FnOnce(R::Params) -> R::Result,
+        ) -> Result<Value, (i64, String)> {
+            let params = serde_json::from_value(params).map_err(|e| (-32602, e.to_string()))?;
+            Ok(serde_json::to_value(f(params)).unwrap())
+        }
+        if self.shutdown {
+            return Err((-32600, "The server is shut down".to_owned()));
+        }
+        match method {
+            Initialize::METHOD => call::<Initialize>(params, |_| InitializeResult {
+                capabilities: ServerCapabilities {
+                    text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                        TextDocumentSyncKind::FULL,
+                    )),
+                    hover_provider: Some(HoverProviderCapability::Simple(true)),
+                    definition_provider: Some(OneOf::Left(true)),
+                    completion_provider: Some(CompletionOptions {
+                        trigger_characters: Some(vec!["#".to_owned(), ":".to_owned()]),
+                        ..Default::default()
+                    }),
+                    document_symbol_provider: Some(OneOf::Left(true)),
+                    ..Default::default()
+                },
+                server_info: Some(ServerInfo {
+                    name: "zngur".to_owned(),
+                    version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+                }),
+            }),
+            Shutdown::METHOD => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            HoverRequest::METHOD => call::<HoverRequest>(params, |x| self.hover(x)),
+            GotoDefinition::METHOD => call::<GotoDefinition>(params, |x| self.definition(x)),
+            Completion::METHOD => call::<Completion>(params, |x| self.completion(x)),
+            DocumentSymbolRequest::METHOD => {
+                call::<DocumentSymbolRequest>(params, |x| self.document_symbols(x))
+            }
+            _ => Err((-32601, format!("Unknown method {method}"))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: Value) {
+        match method {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(params) else {
+                    return;
+                };
+                if let Some(path) = uri_to_path(&params.text_document.uri) {
+                    self.documents
+                        .insert(normalize(&path), params.text_document.text);
+                    self.analyze_all();
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidChangeTextDocumentParams>(params)
+                else {
+                    return;
+                };
+                // The changes are full documents, as requested in the capabilities.
+                let (Some(path), Some(change)) = (
+                    uri_to_path(&params.text_document.uri),
+                    params.content_changes.into_iter().last(),
+                ) else {
+                    return;
+                };
+                self.documents.insert(normalize(&path), change.text);
+                self.analyze_all();
+            }
+            DidSaveTextDocument::METHOD => self.analyze_all(),
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidCloseTextDocumentParams>(params)
+                else {
+                    return;
+                };
+                let Some(path) = uri_to_path(&params.text_document.uri) else {
+                    return;
+                };
+                let path = normalize(&path);
+                self.documents.remove(&path);
+                self.analyses.remove(&path);
+                for file in self.published.remove(&path).unwrap_or_default() {
+                    self.outbox.push(notification::<PublishDiagnostics>(
+                        PublishDiagnosticsParams::new(path_to_uri(&file), vec![], None),
+                    ));
+                }
+            }
+            Exit::METHOD => std::process::exit(if self.shutdown { 0 } else { 1 }),
+            _ => (),
+        }
+    }
+
+    /// Analyzes every open document, since each of them can be imported by the others, and
+    /// publishes the diagnostics.
+    fn analyze_all(&mut self) {
+        let mut paths = self.documents.keys().cloned().collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            let text = &self.documents[&path];
+            let documents = Documents(&self.documents);
+            // A bug in the parser shouldn't stop the server, but the user should know about it.
+            let analysis = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                ParsedZngFile::analyze(path.clone(), text, &documents)
+            }))
+            .unwrap_or_else(|payload| {
+                let reason = payload
+                    .downcast_ref::<&str>()
+                    .map(|x| x.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                Analysis {
+                    diagnostics: vec![Located {
+                        file: path.clone(),
+                        range: 0..0,
+                        value: format!(
+                            "Analysis of this file failed, the parser panicked: {reason}"
+                        ),
+                    }],
+                    ..Default::default()
+                }
+            });
+            let analysis = normalize_analysis(analysis);
+            let mut files = analysis.sources.keys().cloned().collect::<Vec<_>>();
+            files.sort();
+            files.push(path.clone());
+            files.dedup();
+            for file in &files {
+                let Some(text) = analysis
+                    .sources
+                    .get(file)
+                    .or_else(|| self.documents.get(file))
+                else {
+                    continue;
+                };
+                let diagnostics = analysis
+                    .diagnostics
+                    .iter()
+                    .filter(|x| x.file == *file)
+                    .map(|x| Diagnostic {
+                        range: range(text, &x.range),
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        source: Some("zngur".to_owned()),
+                        message: x.value.clone(),
+                        ..Default::default()
+                    })
+                    .collect();
+                self.outbox.push(notification::<PublishDiagnostics>(
+                    PublishDiagnosticsParams::new(path_to_uri(file), diagnostics, None),
+                ));
+            }
+            self.published.insert(path.clone(), files);
+            self.analyses.insert(path, analysis);
+        }
+    }
+
+    /// The analysis of the document and the offset of a position in it.
+    fn locate(&self, params: &TextDocumentPositionParams) -> Option<(PathBuf, usize, &Analysis)> {
+        let path = normalize(&uri_to_path(&params.text_document.uri)?);
+        let analysis = self.analyses.get(&path)?;
+        let offset = offset(analysis.sources.get(&path)?, params.position);
+        Some((path, offset, analysis))
+    }
+
+    fn location<T>(&self, analysis: &Analysis, located: &Located<T>) -> Option<Location> {
+        let text = analysis.sources.get(&located.file)?;
+        Some(Location::new(
+            path_to_uri(&located.file),
+            range(text, &located.range),
+        ))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (path, offset, analysis) = self.locate(&params.text_document_position_params)?;
+        let reference = innermost(&analysis.references, &path, offset)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```rust\n{}\n```", render_path(&reference.value)),
+            }),
+            range: Some(range(&analysis.sources[&path], &reference.range)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let (path, offset, analysis) = self.locate(&params.text_document_position_params)?;
+        if let Some(import) = innermost(&analysis.imports, &path, offset) {
+            let target = normalize(&path.parent()?.join(&import.value));
+            return Some(GotoDefinitionResponse::Scalar(Location::new(
+                path_to_uri(&target),
+                Range::default(),
+            )));
+        }
+        let reference = innermost(&analysis.references, &path, offset)?;
+        let locations = analysis
+            .declarations
+            .iter()
+            .filter(|x| match &x.value {
+                SpecItem::Type(RustType::Adt(pg)) | SpecItem::Trait(RustTrait::Normal(pg)) => {
+                    pg.path == reference.value
+                }
+                _ => false,
+            })
+            .filter_map(|x| self.location(analysis, x))
+            .collect::<Vec<_>>();
+        (!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = &params.text_document_position;
+        let path = normalize(&uri_to_path(&position.text_document.uri)?);
+        let text = self.documents.get(&path)?;
+        let offset = offset(text, position.position);
+        let after_sharp = text[..offset].ends_with('#');
+        let mut items = KEYWORDS
+            .iter()
+            .map(|keyword| CompletionItem {
+                label: keyword.to_string(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                insert_text: after_sharp
+                    .then(|| keyword.strip_prefix('#'))
+                    .flatten()
+                    .map(|x| x.to_owned()),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        if let Some(analysis) = self.analyses.get(&path) {
+            let mut declared = analysis
+                .declarations
+                .iter()
+                .filter_map(|x| match &x.value {
+                    SpecItem::Type(ty) => Some((ty.to_string(), CompletionItemKind::STRUCT)),
+                    SpecItem::Trait(tr) => Some((tr.to_string(), CompletionItemKind::INTERFACE)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            declared.sort_by(|a, b| a.0.cmp(&b.0));
+            declared.dedup_by(|a, b| a.0 == b.0);
+            items.extend(declared.into_iter().map(|(label, kind)| CompletionItem {
+                label,
+                kind: Some(kind),
+                ..Default::default()
+            }));
+        }
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let path = normalize(&uri_to_path(&params.text_document.uri)?);
+        let analysis = self.analyses.get(&path)?;
+        let text = analysis.sources.get(&path)?;
+        let mut symbols: Vec<DocumentSymbol> = vec![];
+        // The type whose block is the last one seen, which owns the items that follow it.
+        let mut owner: Option<&RustType> = None;
+        for declaration in analysis.declarations.iter().filter(|x| x.file == path) {
+            let (name, kind, member_of) = match &declaration.value {
+                SpecItem::Type(ty) => {
+                    owner = Some(ty);
+                    (ty.to_string(), SymbolKind::STRUCT, None)
+                }
+                SpecItem::Trait(tr) => (tr.to_string(), SymbolKind::INTERFACE, None),
+                SpecItem::Fn(path) => (render_path(path), SymbolKind::FUNCTION, None),
+                SpecItem::Constructor(ty, name) => (
+                    match name {
+                        Some(name) => format!("constructor {name}"),
+                        None => "constructor".to_owned(),
+                    },
+                    SymbolKind::CONSTRUCTOR,
+                    Some(ty),
+                ),
+                SpecItem::Field(ty, name) => (name.clone(), SymbolKind::FIELD, Some(ty)),
+                SpecItem::Method(ty, name) => (name.clone(), SymbolKind::METHOD, Some(ty)),
+            };
+            let range = range(text, &declaration.range);
+            #[allow(deprecated)]
+            let symbol = DocumentSymbol {
+                name,
+                detail: None,
+                kind,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range: range,
+                children: None,
+            };
+            match (member_of, symbols.last_mut()) {
+                (Some(ty), Some(parent)) if owner == Some(ty) => {
+                    // The range of the type covers its items.
+                    parent.range.end = range.end;
+                    parent.children.get_or_insert_with(Vec::new).push(symbol);
+                }
+                _ => symbols.push(symbol),
+            }
+        }
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+}
+
+/// The value with the smallest range of `file` that contains `offset`.
+fn innermost<'a, T>(items: &'a [Located<T>], file: &Path, offset: usize) -> Option<&'a Located<T>> {
+    items
+        .iter()
+        .filter(|x| x.file == file && x.range.start <= offset && offset <= x.range.end)
+        .min_by_key(|x| x.range.len())
+}
+
+fn normalize_analysis(analysis: Analysis) -> Analysis {
+    let mut cache = HashMap::new();
+    let mut normalize = |path: PathBuf| {
+        cache
+            .entry(path)
+            .or_insert_with_key(|path| normalize(path))
+            .clone()
+    };
+    fn map<T>(items: Vec<Located<T>>, f: &mut impl FnMut(PathBuf) -> PathBuf) -> Vec<Located<T>> {
+        items
+            .into_iter()
+            .map(|x| Located {
+                file: f(x.file),
+                ..x
+            })
+            .collect()
+    }
+    Analysis {
+        sources: analysis
+            .sources
+            .into_iter()
+            .map(|(path, text)| (normalize(path), text))
+            .collect(),
+        diagnostics: map(analysis.diagnostics, &mut normalize),
+        declarations: map(analysis.declarations, &mut normalize),
+        references: map(analysis.references, &mut normalize),
+        imports: map(analysis.imports, &mut normalize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_and_path() {
+        let uri = path_to_uri(Path::new("/home/user/my file é.zng"));
+        assert_eq!(uri.as_str(), "file:///home/user/my%20file%20%C3%A9.zng");
+        assert_eq!(
+            uri_to_path(&uri),
+            Some(PathBuf::from("/home/user/my file é.zng"))
+        );
+        let uri = Uri::from_str("file:///C:/dir/main.zng").unwrap();
+        assert_eq!(uri_to_path(&uri), Some(PathBuf::from("C:/dir/main.zng")));
+        let uri = Uri::from_str("untitled:Untitled-1").unwrap();
+        assert_eq!(uri_to_path(&uri), None);
+    }
+
+    #[test]
+    fn position_and_offset_count_utf16() {
+        // `é` is one UTF-16 unit in two bytes, and `😀` is two units in four bytes.
+        let text = "aé😀b\nc";
+        let b = text.find('b').unwrap();
+        assert_eq!(position(text, b), Position::new(0, 4));
+        assert_eq!(offset(text, Position::new(0, 4)), b);
+        assert_eq!(position(text, text.len()), Position::new(1, 1));
+        assert_eq!(offset(text, Position::new(1, 0)), text.len() - 1);
+        // A character past the end of the line is the end of the line.
+        assert_eq!(
+            offset(text, Position::new(0, 100)),
+            text.find('\n').unwrap()
+        );
+    }
+
+    #[test]
+    fn open_then_hover() {
+        let uri = path_to_uri(Path::new("/nonexistent/main.zng"));
+        let mut server = Server::default();
+        server.handle(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "zng",
+                    "version": 1,
+                    "text": "use ::std::string::String as Str;\n\ntype Str {\n    #layout(size = 24, align = 8);\n}\n",
+                },
+            },
+        }));
+        server.handle(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": 2, "character": 6 },
+            },
+        }));
+        assert_eq!(
+            server.outbox,
+            vec![
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                }),
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": {
+                        "contents": {
+                            "kind": "markdown",
+                            "value": "```rust\n::std::string::String\n```",
+                        },
+                        "range": {
+                            "start": { "line": 2, "character": 5 },
+                            "end": { "line": 2, "character": 8 },
+                        },
+                    },
+                }),
+            ]
+        );
+    }
+}
@@ -3,13 +3,24 @@ use std::path::PathBuf;
 use clap::Parser;
 use zngur::Zngur;
 
+mod dump;
+mod flatten;
+mod fmt;
+mod generate_spec;
+mod lsp;
+mod verify;
+
 #[derive(Parser)]
 #[command(version)]
 enum Command {
     #[command(alias = "g")]
     Generate {
-        /// Path to the zng file
-        path: PathBuf,
+        /// Paths to the zng files, or to JSON specs printed by `zngur dump --json`
+        ///
+        /// Several files are generated together as one, with the types they have in common
+        /// declared once. The default output paths are relative to the first one.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
 
         /// Path of the generated C++ file, if it is needed
         ///
@@ -44,25 +55,58 @@ enum Command {
         /// Default is "rust"
         #[arg(long)]
         cpp_namespace: Option<String>,
+
+        /// Split the header into zngur_runtime.h and headers for each Rust module, which are
+        /// written next to the header file. The header file includes all of them
+        #[arg(long)]
+        split_headers: bool,
     },
+    /// Generates a starting zng file for a crate from its rustdoc JSON output, or adds the
+    /// items missing from an existing one
+    GenerateSpec(generate_spec::GenerateSpec),
+    /// Checks the methods, functions, constructors and fields of a zng file against the rustdoc
+    /// JSON output of the crate
+    Verify(verify::Verify),
+    /// Formats zng files, keeping their comments
+    Fmt(fmt::Fmt),
+    /// Prints the spec of a zng file after merging its imports
+    Dump(dump::Dump),
+    /// Prints a zng file and the files it imports as a single zng file, with full paths
+    Flatten(flatten::Flatten),
+    /// Runs a language server for zng files, which talks to the editor over stdin and stdout
+    Lsp,
 }
 
 fn main() {
     let cmd = Command::parse();
     match cmd {
         Command::Generate {
-            path,
+            paths,
             cpp_file,
             h_file,
             rs_file,
             mangling_base,
             cpp_namespace,
+            split_headers,
         } => {
-            let pp = path.parent().unwrap();
+            let pp = paths[0].parent().unwrap();
             let cpp_file = cpp_file.unwrap_or_else(|| pp.join("generated.cpp"));
             let h_file = h_file.unwrap_or_else(|| pp.join("generated.h"));
             let rs_file = rs_file.unwrap_or_else(|| pp.join("src/generated.rs"));
-            let mut zng = Zngur::from_zng_file(&path)
+            let is_json = |path: &PathBuf| path.extension().is_some_and(|x| x == "json");
+            let zng = if is_json(&paths[0]) {
+                Zngur::from_json_file(&paths[0])
+            } else {
+                Zngur::from_zng_file(&paths[0])
+            };
+            let zng = paths[1..].iter().fold(zng, |zng, path| {
+                if is_json(path) {
+                    zng.with_json_file(path)
+                } else {
+                    zng.with_zng_file(path)
+                }
+            });
+            let mut zng = zng
                 .with_cpp_file(cpp_file)
                 .with_h_file(h_file)
                 .with_rs_file(rs_file);
@@ -72,7 +116,16 @@ fn main() {
             if let Some(cpp_namespace) = cpp_namespace {
                 zng = zng.with_cpp_namespace(&cpp_namespace);
             }
+            if split_headers {
+                zng = zng.with_split_headers();
+            }
             zng.generate();
         }
+        Command::GenerateSpec(args) => generate_spec::run(args),
+        Command::Verify(args) => verify::run(args),
+        Command::Fmt(args) => fmt::run(args),
+        Command::Dump(args) => dump::run(args),
+        Command::Flatten(args) => flatten::run(args),
+        Command::Lsp => lsp::run(),
     }
 }
@@ -0,0 +1,45 @@
+use std::{path::PathBuf, process::exit};
+
+use zngur_parser::ParsedZngFile;
+
+#[derive(clap::Args)]
+pub struct Verify {
+    /// Path to the zng file
+    path: PathBuf,
+
+    /// Path to the rustdoc JSON of the crate
+    #[arg(long)]
+    rustdoc: PathBuf,
+}
+
+pub fn run(args: Verify) {
+    let json = std::fs::read_to_string(&args.rustdoc).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {e}", args.rustdoc.display());
+        exit(1);
+    });
+    let (spec, source_map) = ParsedZngFile::parse_with_source_map(args.path.clone());
+    let mismatches = zngur_autozng::verify(&json, &spec).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1);
+    });
+    if mismatches.is_empty() {
+        eprintln!("{} matches the crate", args.path.display());
+        return;
+    }
+    for mismatch in &mismatches {
+        source_map
+            .write_error(
+                &mismatch.item,
+                &mismatch.message,
+                &mismatch.message,
+                std::io::stderr(),
+            )
+            .unwrap();
+    }
+    eprintln!(
+        "{} items of {} don't match the crate",
+        mismatches.len(),
+        args.path.display()
+    );
+    exit(1);
+}
@@ -6,12 +6,14 @@ mod merge;
 pub use merge::{Merge, MergeFailure, MergeResult};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mutability {
     Mut,
     Not,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ZngurMethodReceiver {
     Static,
     Ref(Mutability),
@@ -19,6 +21,7 @@ pub enum ZngurMethodReceiver {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurMethod {
     pub name: String,
     pub generics: Vec<RustType>,
@@ -28,40 +31,71 @@ pub struct ZngurMethod {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurFn {
     pub path: RustPathAndGenerics,
     pub inputs: Vec<RustType>,
     pub output: RustType,
+    pub result_policy: Option<ResultPolicy>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurExternCppFn {
     pub name: String,
     pub inputs: Vec<RustType>,
     pub output: RustType,
+    pub exception_policy: CppExceptionPolicy,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZngurExternCppMethod {
+    pub data: ZngurMethod,
+    pub exception_policy: CppExceptionPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurExternCppImpl {
     pub tr: Option<RustTrait>,
     pub ty: RustType,
-    pub methods: Vec<ZngurMethod>,
+    pub methods: Vec<ZngurExternCppMethod>,
+}
+
+/// What happens when a function in an `extern "C++"` block throws a C++ exception.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CppExceptionPolicy {
+    /// Print the exception and abort the process.
+    #[default]
+    Abort,
+    /// Panic with the `what()` of the exception.
+    Panic,
+    /// Return it as the `Err` of a `Result<T, ZngurCppException>`, where `T` is the `output` of
+    /// the function. `ZngurCppException` is defined in the generated Rust file.
+    Result,
 }
 
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurConstructor {
     pub name: Option<String>,
     pub inputs: Vec<(String, RustType)>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurField {
+    /// The field name, or a dotted path like `inner.x` or `0.1` for nested fields.
     pub name: String,
     pub ty: RustType,
-    pub offset: usize,
+    /// When `None`, the offset is taken from `offset_of!` at runtime.
+    pub offset: Option<usize>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ZngurWellknownTrait {
     Debug,
     Drop,
@@ -70,6 +104,7 @@ pub enum ZngurWellknownTrait {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ZngurWellknownTraitData {
     Debug {
         pretty_print: String,
@@ -83,9 +118,17 @@ pub enum ZngurWellknownTraitData {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LayoutPolicy {
-    StackAllocated { size: usize, align: usize },
-    HeapAllocated,
+    StackAllocated {
+        size: usize,
+        align: usize,
+    },
+    /// Stored behind a pointer. Values that fit in `inline_size` bytes are kept inline in the C++
+    /// object instead of being allocated.
+    HeapAllocated {
+        inline_size: Option<usize>,
+    },
     OnlyByRef,
 }
 
@@ -93,17 +136,31 @@ impl LayoutPolicy {
     pub const ZERO_SIZED_TYPE: Self = LayoutPolicy::StackAllocated { size: 0, align: 1 };
 }
 
+/// How a function returning `Result<T, E>` is exposed to C++.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResultPolicy {
+    /// Return `T`, and throw `rust::Error<E>` on `Err`.
+    AsException,
+    /// Return `rust::Expected<T, E>`, which is `std::expected<T, E>` in C++23.
+    AsExpected,
+}
+
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurMethodDetails {
     pub data: ZngurMethod,
     pub use_path: Option<Vec<String>>,
     pub deref: Option<(RustType, Mutability)>,
+    pub result_policy: Option<ResultPolicy>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CppValue(pub String, pub String);
 
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CppRef(pub String);
 
 impl Display for CppRef {
@@ -112,7 +169,8 @@ impl Display for CppRef {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurType {
     pub ty: RustType,
     pub layout: LayoutPolicy,
@@ -122,38 +180,134 @@ pub struct ZngurType {
     pub fields: Vec<ZngurField>,
     pub cpp_value: Option<CppValue>,
     pub cpp_ref: Option<CppRef>,
+    pub cpp_std_bridge: bool,
+    pub result_policy: Option<ResultPolicy>,
+    /// The named constructors are all of the variants of this enum.
+    pub is_enum: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ZngurTrait {
     pub tr: RustTrait,
+    pub supertraits: Vec<RustTrait>,
     pub methods: Vec<ZngurMethod>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AdditionalIncludes(pub String);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConvertPanicToException(pub bool);
 
-#[derive(Clone, Debug, Default)]
+/// Where the storage of `#heap_allocated` types comes from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeapAllocator {
+    /// The Rust global allocator.
+    #[default]
+    Global,
+    /// A Rust `static` implementing `GlobalAlloc`.
+    Rust(String),
+    /// A pair of C++ functions with signatures `void* alloc(size_t size, size_t align)` and
+    /// `void free(void* p, size_t size, size_t align)`.
+    Cpp { alloc: String, free: String },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Import(pub std::path::PathBuf);
-#[derive(Debug, Default)]
+#[derive(Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ZngurSpec {
     pub imports: Vec<Import>,
     pub types: Vec<ZngurType>,
+    #[cfg_attr(feature = "serde", serde(with = "traits_as_list"))]
     pub traits: HashMap<RustTrait, ZngurTrait>,
     pub funcs: Vec<ZngurFn>,
     pub extern_cpp_funcs: Vec<ZngurExternCppFn>,
     pub extern_cpp_impls: Vec<ZngurExternCppImpl>,
     pub additional_includes: AdditionalIncludes,
     pub convert_panic_to_exception: ConvertPanicToException,
+    pub heap_allocator: HeapAllocator,
     pub cpp_include_header_name: String,
     pub mangling_base: String,
     pub cpp_namespace: String,
 }
 
+/// Prints the traits sorted by name instead of in hash order, so that the output is the same on
+/// every run.
+impl std::fmt::Debug for ZngurSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        struct SortedTraits<'a>(&'a HashMap<RustTrait, ZngurTrait>);
+
+        impl std::fmt::Debug for SortedTraits<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let mut traits = self.0.iter().collect::<Vec<_>>();
+                traits.sort_by_key(|(tr, _)| tr.to_string());
+                f.debug_map().entries(traits).finish()
+            }
+        }
+
+        f.debug_struct("ZngurSpec")
+            .field("imports", &self.imports)
+            .field("types", &self.types)
+            .field("traits", &SortedTraits(&self.traits))
+            .field("funcs", &self.funcs)
+            .field("extern_cpp_funcs", &self.extern_cpp_funcs)
+            .field("extern_cpp_impls", &self.extern_cpp_impls)
+            .field("additional_includes", &self.additional_includes)
+            .field(
+                "convert_panic_to_exception",
+                &self.convert_panic_to_exception,
+            )
+            .field("heap_allocator", &self.heap_allocator)
+            .field("cpp_include_header_name", &self.cpp_include_header_name)
+            .field("mangling_base", &self.mangling_base)
+            .field("cpp_namespace", &self.cpp_namespace)
+            .finish()
+    }
+}
+
+/// JSON only has string keys, so the traits are a list, where each of them knows its key.
+#[cfg(feature = "serde")]
+mod traits_as_list {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+    use crate::{RustTrait, ZngurTrait};
+
+    pub fn serialize<S: Serializer>(
+        traits: &HashMap<RustTrait, ZngurTrait>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut traits = traits.values().collect::<Vec<_>>();
+        traits.sort_by_key(|t| t.tr.to_string());
+        traits.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<RustTrait, ZngurTrait>, D::Error> {
+        let mut result = HashMap::new();
+        for t in Vec::<ZngurTrait>::deserialize(deserializer)? {
+            let tr = t.tr.clone();
+            if result.insert(tr.clone(), t).is_some() {
+                return Err(D::Error::custom(format!(
+                    "Trait `{tr}` is declared more than once"
+                )));
+            }
+        }
+        Ok(result)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RustTrait {
     Normal(RustPathAndGenerics),
     Fn {
@@ -174,6 +328,7 @@ impl RustTrait {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimitiveRustType {
     Uint(u32),
     Int(u32),
@@ -185,6 +340,7 @@ pub enum PrimitiveRustType {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RustPathAndGenerics {
     pub path: Vec<String>,
     pub generics: Vec<RustType>,
@@ -192,6 +348,7 @@ pub struct RustPathAndGenerics {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RustType {
     Primitive(PrimitiveRustType),
     Ref(Mutability, Box<RustType>),
@@ -205,6 +362,57 @@ pub enum RustType {
 
 impl RustType {
     pub const UNIT: Self = RustType::Tuple(Vec::new());
+
+    /// Returns `T` and `E` if this type is a `Result<T, E>`.
+    pub fn as_result(&self) -> Option<(&RustType, &RustType)> {
+        let RustType::Adt(pg) = self else {
+            return None;
+        };
+        match (
+            pg.path.iter().map(|x| x.as_str()).collect_vec().as_slice(),
+            pg.generics.as_slice(),
+        ) {
+            (["std" | "core", "result", "Result"], [ok, err]) => Some((ok, err)),
+            _ => None,
+        }
+    }
+
+    /// Returns which `#cpp_std_bridge` the generator has for this type, if any.
+    pub fn cpp_std_bridge(&self) -> Option<CppStdBridgeKind<'_>> {
+        let RustType::Adt(pg) = self else {
+            return None;
+        };
+        match (
+            pg.path.iter().map(|x| x.as_str()).collect_vec().as_slice(),
+            pg.generics.as_slice(),
+        ) {
+            (["std" | "alloc", "string", "String"], []) => Some(CppStdBridgeKind::String),
+            (["std" | "alloc", "vec", "Vec"], [elem @ RustType::Primitive(p)])
+                if !matches!(
+                    p,
+                    PrimitiveRustType::Bool
+                        | PrimitiveRustType::Str
+                        | PrimitiveRustType::ZngurCppOpaqueOwnedObject
+                ) =>
+            {
+                Some(CppStdBridgeKind::Vec(elem))
+            }
+            (["std" | "core", "option", "Option"], [elem]) => Some(CppStdBridgeKind::Option(elem)),
+            _ => None,
+        }
+    }
+}
+
+/// The types which `#cpp_std_bridge` converts to and from the C++ standard library, see
+/// [`RustType::cpp_std_bridge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CppStdBridgeKind<'a> {
+    /// `String`, to and from `std::string`.
+    String,
+    /// `Vec<T>` with a numeric `T`, to and from `std::vector<T>`.
+    Vec(&'a RustType),
+    /// `Option<T>`, to and from `std::optional<T>`.
+    Option(&'a RustType),
 }
 
 impl Display for RustPathAndGenerics {
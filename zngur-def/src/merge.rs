@@ -1,7 +1,7 @@
 use crate::{
-    AdditionalIncludes, ConvertPanicToException, CppRef, CppValue, LayoutPolicy, ZngurConstructor,
-    ZngurExternCppFn, ZngurExternCppImpl, ZngurField, ZngurFn, ZngurMethodDetails, ZngurSpec,
-    ZngurTrait, ZngurType,
+    AdditionalIncludes, ConvertPanicToException, CppRef, CppValue, HeapAllocator, LayoutPolicy,
+    ResultPolicy, ZngurConstructor, ZngurExternCppFn, ZngurExternCppImpl, ZngurField, ZngurFn,
+    ZngurMethodDetails, ZngurSpec, ZngurTrait, ZngurType,
 };
 
 /// Trait for types with a partial union operation.
@@ -148,6 +148,9 @@ impl Merge for ZngurType {
 
         self.cpp_value.merge(&mut into.cpp_value)?;
         self.cpp_ref.merge(&mut into.cpp_ref)?;
+        into.cpp_std_bridge |= self.cpp_std_bridge;
+        into.is_enum |= self.is_enum;
+        self.result_policy.merge(&mut into.result_policy)?;
 
         inplace_union(self.wellknown_traits, &mut into.wellknown_traits);
         merge_by_identity(self.methods, &mut into.methods, |a, b| {
@@ -174,6 +177,7 @@ impl Merge for ZngurTrait {
             );
         }
 
+        inplace_union(self.supertraits, &mut into.supertraits);
         inplace_union(self.methods, &mut into.methods);
 
         Ok(())
@@ -206,6 +210,18 @@ impl Merge for CppRef {
     }
 }
 
+impl Merge for ResultPolicy {
+    /// Writes the partial union of `self` and `into` to the latter.
+    ///
+    /// A type can only have one result policy.
+    fn merge(self, into: &mut Self) -> MergeResult {
+        if self != *into {
+            return Err(MergeFailure::Conflict("Result policy mismatch".to_string()));
+        }
+        Ok(())
+    }
+}
+
 impl Merge<ZngurSpec> for ZngurType {
     /// Merges a type into a specification's type list.
     fn merge(self, into: &mut ZngurSpec) -> MergeResult {
@@ -252,11 +268,25 @@ impl Merge<ZngurSpec> for ZngurTrait {
     }
 }
 
+impl Merge for ZngurFn {
+    fn merge(self, into: &mut Self) -> MergeResult {
+        if self != *into {
+            return Err(MergeFailure::Conflict(format!(
+                "Function mismatch for `{}`",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+}
+
 impl Merge<ZngurSpec> for ZngurFn {
     /// Merges a function into a specification's function list.
+    ///
+    /// A function path can only be declared once, so the same path with a different signature
+    /// or result policy is a conflict.
     fn merge(self, into: &mut ZngurSpec) -> MergeResult {
-        push_unique(self, &mut into.funcs);
-        Ok(())
+        merge_by_identity(vec![self], &mut into.funcs, |a, b| a.path == b.path)
     }
 }
 
@@ -295,3 +325,80 @@ impl Merge<ZngurSpec> for ConvertPanicToException {
         Ok(())
     }
 }
+
+impl Merge<ZngurSpec> for HeapAllocator {
+    /// Sets the heap allocator of a specification.
+    ///
+    /// A specification can only have one heap allocator.
+    fn merge(self, into: &mut ZngurSpec) -> MergeResult {
+        if into.heap_allocator != HeapAllocator::Global && into.heap_allocator != self {
+            return Err(MergeFailure::Conflict(
+                "Heap allocator is already declared".to_string(),
+            ));
+        }
+        into.heap_allocator = self;
+        Ok(())
+    }
+}
+
+/// Merges a setting of the generator, like `cpp_namespace`, where an empty string means unset.
+///
+/// A specification can only have one value for each setting.
+fn merge_setting(value: String, into: &mut String, setting: &str) -> MergeResult {
+    if value.is_empty() || value == *into {
+        return Ok(());
+    }
+    if !into.is_empty() {
+        return Err(MergeFailure::Conflict(format!(
+            "Conflicting {setting}: `{into}` and `{value}`"
+        )));
+    }
+    *into = value;
+    Ok(())
+}
+
+impl Merge for ZngurSpec {
+    /// Merges all of the items of a specification into another one, like an import does.
+    ///
+    /// The settings of the generator, like `cpp_namespace`, are taken from whichever
+    /// specification sets them, and different values are a conflict.
+    fn merge(self, into: &mut Self) -> MergeResult {
+        merge_setting(
+            self.cpp_namespace,
+            &mut into.cpp_namespace,
+            "C++ namespaces",
+        )?;
+        merge_setting(
+            self.mangling_base,
+            &mut into.mangling_base,
+            "mangling bases",
+        )?;
+        merge_setting(
+            self.cpp_include_header_name,
+            &mut into.cpp_include_header_name,
+            "header names",
+        )?;
+        inplace_union(self.imports, &mut into.imports);
+        for ty in self.types {
+            ty.merge(into)?;
+        }
+        for tr in self.traits.into_values() {
+            tr.merge(into)?;
+        }
+        for func in self.funcs {
+            func.merge(into)?;
+        }
+        for func in self.extern_cpp_funcs {
+            func.merge(into)?;
+        }
+        for imp in self.extern_cpp_impls {
+            imp.merge(into)?;
+        }
+        self.additional_includes.merge(into)?;
+        self.convert_panic_to_exception.merge(into)?;
+        if self.heap_allocator != HeapAllocator::Global {
+            self.heap_allocator.merge(into)?;
+        }
+        Ok(())
+    }
+}
@@ -1,23 +1,26 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::{Display, Write},
     iter,
 };
 
 use itertools::Itertools;
-use zngur_def::{CppRef, CppValue, RustTrait, ZngurField, ZngurMethodReceiver};
+use zngur_def::{
+    CppExceptionPolicy, CppRef, CppValue, ResultPolicy, RustTrait, ZngurMethodReceiver,
+};
 
 use crate::{
     ZngurWellknownTraitData,
-    template::{CppHeaderTemplate, CppSourceTemplate},
+    rust::IntoCpp,
+    template::{CppHeaderTemplate, CppRuntimeTemplate, CppSourceTemplate, HeaderPart},
 };
 use sailfish::Template;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CppPath(pub Vec<String>);
 
 impl CppPath {
-    fn namespace(&self) -> &[String] {
+    pub(crate) fn namespace(&self) -> &[String] {
         self.0.split_last().unwrap().1
     }
 
@@ -78,7 +81,7 @@ impl Display for CppPath {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CppType {
     pub path: CppPath,
     pub generic_args: Vec<CppType>,
@@ -253,17 +256,19 @@ pub struct CppFnSig {
 pub struct CppFnDefinition {
     pub name: CppPath,
     pub sig: CppFnSig,
+    pub result: Option<CppResultWrapper>,
 }
 
 pub struct CppExportedFnDefinition {
     pub name: String,
     pub sig: CppFnSig,
+    pub exception_policy: CppExceptionPolicy,
 }
 
 pub struct CppExportedImplDefinition {
     pub tr: Option<CppType>,
     pub ty: CppType,
-    pub methods: Vec<(String, CppFnSig)>,
+    pub methods: Vec<(String, CppFnSig, CppExceptionPolicy)>,
 }
 
 #[derive(Debug)]
@@ -271,6 +276,27 @@ pub struct CppMethod {
     pub name: String,
     pub kind: ZngurMethodReceiver,
     pub sig: CppFnSig,
+    pub result: Option<CppResultWrapper>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResultBridgeSymbols {
+    pub is_ok: String,
+    pub unwrap: String,
+    pub unwrap_err: String,
+}
+
+/// A function returning a `Result` which is unpacked on the C++ side according to `policy`.
+///
+/// The `output` of the function signature is the type seen by C++ users, while `result_ty`
+/// is the `Result` type returned by the Rust shim.
+#[derive(Debug)]
+pub struct CppResultWrapper {
+    pub policy: ResultPolicy,
+    pub result_ty: CppType,
+    pub ok: CppType,
+    pub err: CppType,
+    pub symbols: ResultBridgeSymbols,
 }
 
 #[derive(Debug)]
@@ -280,9 +306,14 @@ pub enum CppTraitDefinition {
     },
     Normal {
         as_ty: CppType,
+        supertraits: Vec<RustTrait>,
         methods: Vec<CppTraitMethod>,
+        /// Methods of the supertraits, called through this trait.
+        inherited_methods: Vec<CppTraitMethod>,
         link_name: String,
         link_name_ref: String,
+        /// The Rust builder behind `Arc<dyn Trait>` and `Rc<dyn Trait>`, if either is declared.
+        link_name_shared: Option<String>,
     },
 }
 
@@ -294,24 +325,111 @@ pub enum CppLayoutPolicy {
     },
     HeapAllocated {
         size_fn: String,
-        alloc_fn: String,
-        free_fn: String,
+        align_fn: String,
+        inline_size: Option<usize>,
+        allocator: CppHeapAllocator,
     },
     OnlyByRef,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CppHeapAllocator {
+    Rust { alloc_fn: String, free_fn: String },
+    Cpp { alloc: String, free: String },
+}
+
+#[derive(Debug)]
+pub enum CppStdBridge {
+    String {
+        from_std: String,
+        as_bytes: String,
+    },
+    Vec {
+        elem: CppType,
+        from_span: String,
+        as_slice: String,
+    },
+    Option {
+        elem: CppType,
+        is_some: String,
+        some: String,
+        none: String,
+        unwrap: String,
+        unwrap_or: String,
+    },
+}
+
+/// A Rust enum, exposed to C++ as a tagged union.
+#[derive(Debug)]
+pub struct CppEnum {
+    pub tag_fn: String,
+    pub variants: Vec<CppEnumVariant>,
+}
+
+#[derive(Debug)]
+pub struct CppEnumVariant {
+    pub name: String,
+    /// The C++ names and types of the fields.
+    pub fields: Vec<(String, CppType)>,
+    /// Writes references to the fields. `None` for variants without fields.
+    pub accessor: Option<String>,
+}
+
+/// An `Arc<dyn Trait>` or `Rc<dyn Trait>`, built from C++ objects.
+#[derive(Debug)]
+pub struct CppSharedDyn {
+    pub tr: RustTrait,
+    /// Either `make_arc` or `make_rc`.
+    pub make_fn: &'static str,
+    pub rust_link_name: String,
+}
+
+/// A field at a constant offset, exposed as a `Field*` member.
+#[derive(Debug)]
+pub struct CppField {
+    pub name: String,
+    pub ty: CppType,
+    pub offset: usize,
+}
+
+/// A field read through accessor methods, at an offset computed by Rust.
+#[derive(Debug)]
+pub struct CppFieldAccessor {
+    pub name: String,
+    pub ty: CppType,
+    pub offset_fn: String,
+}
+
+/// The `operator()` of a Rust closure, that is a `Box<dyn Fn*>`, or a `dyn Fn*` behind a reference.
+#[derive(Debug)]
+pub struct CppFnCall {
+    /// One of `Fn`, `FnMut` or `FnOnce`.
+    pub kind: String,
+    pub sig: CppFnSig,
+}
+
 #[derive(Debug)]
 pub struct CppTypeDefinition {
     pub ty: CppType,
     pub layout: CppLayoutPolicy,
     pub methods: Vec<CppMethod>,
     pub constructors: Vec<CppFnSig>,
-    pub fields: Vec<ZngurField>,
+    pub fields: Vec<CppField>,
+    /// Fields without a known offset, and fields of types that aren't stored inline.
+    pub field_accessors: Vec<CppFieldAccessor>,
     pub from_trait: Option<RustTrait>,
     pub from_trait_ref: Option<RustTrait>,
+    /// The type is a `Box<dyn Trait>`, `Arc<dyn Trait>` or `Rc<dyn Trait>` with a `Send` or
+    /// `Sync` bound, so its builders only accept C++ types marked with `rust::zngur_thread_safe`.
+    pub needs_thread_safe: bool,
+    pub shared_dyn: Option<CppSharedDyn>,
+    pub enum_def: Option<CppEnum>,
     pub wellknown_traits: Vec<ZngurWellknownTraitData>,
     pub cpp_value: Option<CppValue>,
     pub cpp_ref: Option<CppRef>,
+    pub cpp_std_bridge: Option<CppStdBridge>,
+    pub slice_elem: Option<CppType>,
+    pub fn_call: Option<CppFnCall>,
 }
 
 impl Default for CppTypeDefinition {
@@ -322,11 +440,18 @@ impl Default for CppTypeDefinition {
             methods: vec![],
             constructors: vec![],
             fields: vec![],
+            field_accessors: vec![],
             wellknown_traits: vec![],
             from_trait: None,
             from_trait_ref: None,
+            needs_thread_safe: false,
+            shared_dyn: None,
+            enum_def: None,
             cpp_value: None,
             cpp_ref: None,
+            cpp_std_bridge: None,
+            slice_elem: None,
+            fn_call: None,
         }
     }
 }
@@ -335,6 +460,7 @@ impl Default for CppTypeDefinition {
 pub struct PanicToExceptionSymbols {
     pub detect_panic: String,
     pub take_panic: String,
+    pub panic_payload: String,
 }
 
 #[derive(Default)]
@@ -349,17 +475,172 @@ pub struct CppFile {
     pub panic_to_exception: Option<PanicToExceptionSymbols>,
 }
 
+/// The name of the header with the runtime, in [`CppFile::render_split`].
+pub const RUNTIME_HEADER_NAME: &str = "zngur_runtime.h";
+
+/// Returns the name of the header of the items in the C++ namespace `namespace`, in
+/// [`CppFile::render_split`], like `rust.std.vec.h` for `rust::std::vec`, or the name of the
+/// header of their declarations, like `rust.std.vec.decl.h`.
+fn module_header_name(namespace: &[String], part: HeaderPart) -> String {
+    match part {
+        HeaderPart::Declarations => format!("{}.decl.h", namespace.join(".")),
+        HeaderPart::All | HeaderPart::Definitions => format!("{}.h", namespace.join(".")),
+    }
+}
+
+/// Calls `f` with `ty` and each of its generic arguments, recursively.
+fn visit_type<'a>(ty: &'a CppType, f: &mut impl FnMut(&'a CppType)) {
+    f(ty);
+    for arg in &ty.generic_args {
+        visit_type(arg, f);
+    }
+}
+
+impl CppFnSig {
+    fn types(&self) -> impl Iterator<Item = &CppType> {
+        self.inputs.iter().chain([&self.output])
+    }
+}
+
+impl CppResultWrapper {
+    fn types(&self) -> impl Iterator<Item = &CppType> {
+        [&self.result_ty, &self.ok, &self.err].into_iter()
+    }
+}
+
+impl CppTypeDefinition {
+    /// The types which need to be complete before the declaration of this type, since it has
+    /// fields or elements of them.
+    fn stored_types(&self) -> impl Iterator<Item = &CppType> {
+        self.fields.iter().map(|x| &x.ty).chain(&self.slice_elem)
+    }
+
+    /// The other types in the declarations and definitions of this type. The traits are
+    /// returned as the types of their C++ classes.
+    fn used_types(&self) -> Vec<CppType> {
+        let mut result = vec![];
+        let mut add = |ty: &CppType| result.push(ty.clone());
+        for method in &self.methods {
+            method.sig.types().for_each(&mut add);
+            method
+                .result
+                .iter()
+                .flat_map(|x| x.types())
+                .for_each(&mut add);
+        }
+        self.constructors
+            .iter()
+            .flat_map(|x| x.types())
+            .for_each(&mut add);
+        self.field_accessors
+            .iter()
+            .map(|x| &x.ty)
+            .for_each(&mut add);
+        self.fn_call
+            .iter()
+            .flat_map(|x| x.sig.types())
+            .for_each(&mut add);
+        if let Some(enum_def) = &self.enum_def {
+            for variant in &enum_def.variants {
+                variant.fields.iter().map(|x| &x.1).for_each(&mut add);
+            }
+        }
+        match &self.cpp_std_bridge {
+            Some(CppStdBridge::Vec { elem, .. } | CppStdBridge::Option { elem, .. }) => add(elem),
+            Some(CppStdBridge::String { .. }) | None => (),
+        }
+        let traits = [&self.from_trait, &self.from_trait_ref]
+            .into_iter()
+            .flatten()
+            .chain(self.shared_dyn.as_ref().map(|x| &x.tr));
+        for tr in traits {
+            match tr {
+                RustTrait::Normal(_) => result.push(tr.into_cpp()),
+                RustTrait::Fn { inputs, output, .. } => {
+                    result.extend(inputs.iter().map(|x| x.into_cpp()));
+                    result.push(output.into_cpp());
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Returns the non-`Fn` trait definitions, with every trait after its supertraits so that the C++
+/// abstract classes can inherit from each other.
+fn normal_trait_defs(
+    trait_defs: &HashMap<RustTrait, CppTraitDefinition>,
+) -> Vec<&CppTraitDefinition> {
+    fn visit<'a>(
+        tr: &RustTrait,
+        trait_defs: &'a HashMap<RustTrait, CppTraitDefinition>,
+        visited: &mut Vec<RustTrait>,
+        result: &mut Vec<&'a CppTraitDefinition>,
+    ) {
+        if visited.contains(tr) {
+            return;
+        }
+        visited.push(tr.clone());
+        let Some(td @ CppTraitDefinition::Normal { supertraits, .. }) = trait_defs.get(tr) else {
+            return;
+        };
+        for supertrait in supertraits {
+            visit(supertrait, trait_defs, visited, result);
+        }
+        result.push(td);
+    }
+    let mut visited = vec![];
+    let mut result = vec![];
+    for tr in trait_defs.keys() {
+        visit(tr, trait_defs, &mut visited, &mut result);
+    }
+    result
+}
+
+/// The items of a C++ namespace, for its header in [`CppFile::render_split`].
+#[derive(Default)]
+struct Module<'a> {
+    type_defs: Vec<&'a CppTypeDefinition>,
+    normal_trait_defs: Vec<&'a CppTraitDefinition>,
+    fn_defs: Vec<&'a CppFnDefinition>,
+    /// The types in the declarations and definitions of the items.
+    used_types: Vec<CppType>,
+    /// The types which need to be complete before the declarations of the items.
+    stored_types: Vec<CppType>,
+}
+
 impl CppFile {
+    fn runtime_template(&self) -> CppRuntimeTemplate<'_> {
+        CppRuntimeTemplate {
+            panic_to_exception: &self.panic_to_exception,
+            additional_includes: &self.additional_includes,
+        }
+    }
+
     fn emit_h_file(&self, state: &mut State) -> std::fmt::Result {
+        let forward_decls = self
+            .type_defs
+            .iter()
+            .map(|x| &x.ty)
+            .chain(
+                self.exported_impls
+                    .iter()
+                    .flat_map(|x| iter::once(&x.ty).chain(&x.tr)),
+            )
+            .collect();
         let template = CppHeaderTemplate {
+            part: HeaderPart::All,
+            includes: vec![],
             panic_to_exception: &self.panic_to_exception,
-            additional_includes: &self.additional_includes,
-            fn_deps: &self.fn_defs,
-            type_defs: &self.type_defs,
+            fn_deps: self.fn_defs.iter().collect(),
+            type_defs: self.type_defs.iter().collect(),
+            forward_decls,
             trait_defs: &self.trait_defs,
-            exported_impls: &self.exported_impls,
-            exported_fn_defs: &self.exported_fn_defs,
+            normal_trait_defs: normal_trait_defs(&self.trait_defs),
+            exported_impls: self.exported_impls.iter().collect(),
+            exported_fn_defs: self.exported_fn_defs.iter().collect(),
         };
+        state.text += self.runtime_template().render().unwrap().as_str();
         state.text += template.render().unwrap().as_str();
         Ok(())
     }
@@ -380,21 +661,235 @@ impl CppFile {
         Ok(())
     }
 
-    pub fn render(self) -> (String, Option<String>) {
-        let mut h_file = State {
-            text: "".to_owned(),
-            panic_to_exception: self.panic_to_exception.clone(),
-        };
+    fn render_cpp_file(&self) -> Option<String> {
         let mut cpp_file = State {
             text: "".to_owned(),
             panic_to_exception: self.panic_to_exception.clone(),
         };
-        self.emit_h_file(&mut h_file).unwrap();
         let mut is_cpp_needed = false;
         self.emit_cpp_file(&mut cpp_file, &mut is_cpp_needed)
             .unwrap();
+        is_cpp_needed.then_some(cpp_file.text)
+    }
+
+    pub fn render(self) -> (String, Option<String>) {
+        let mut h_file = State {
+            text: "".to_owned(),
+            panic_to_exception: self.panic_to_exception.clone(),
+        };
+        self.emit_h_file(&mut h_file).unwrap();
         h_file.remove_no_except_in_panic();
-        (h_file.text, is_cpp_needed.then_some(cpp_file.text))
+        (h_file.text, self.render_cpp_file())
+    }
+
+    /// Groups the items by their C++ namespace, which is the Rust module of a type or function
+    /// prefixed by `rust`. The generic types of the root namespace, like tuples and `Box`, are in
+    /// the `rust` module, along with the C++ functions and impls exported to Rust.
+    fn modules(&self) -> BTreeMap<&[String], Module<'_>> {
+        let mut modules: BTreeMap<&[String], Module<'_>> = BTreeMap::new();
+        for td in &self.type_defs {
+            let module = modules.entry(td.ty.path.namespace()).or_default();
+            module.type_defs.push(td);
+            module.used_types.push(td.ty.clone());
+            module.used_types.extend(td.used_types());
+            module.stored_types.extend(td.stored_types().cloned());
+        }
+        for td in normal_trait_defs(&self.trait_defs) {
+            let CppTraitDefinition::Normal {
+                as_ty,
+                supertraits,
+                methods,
+                inherited_methods,
+                ..
+            } = td
+            else {
+                continue;
+            };
+            let module = modules.entry(as_ty.path.namespace()).or_default();
+            module.normal_trait_defs.push(td);
+            module.used_types.push(as_ty.clone());
+            for method in methods.iter().chain(inherited_methods) {
+                let types = method.inputs.iter().chain([&method.output]);
+                module.used_types.extend(types.cloned());
+            }
+            module
+                .stored_types
+                .extend(supertraits.iter().map(|x| x.into_cpp()));
+        }
+        for fd in &self.fn_defs {
+            let module = modules.entry(fd.name.namespace()).or_default();
+            module.fn_defs.push(fd);
+            let types = fd
+                .sig
+                .types()
+                .chain(fd.result.iter().flat_map(|x| x.types()));
+            module.used_types.extend(types.cloned());
+        }
+        modules
+    }
+
+    /// Renders the header like [`CppFile::render`], split into a header with the runtime, named
+    /// [`RUNTIME_HEADER_NAME`], and two headers for each C++ namespace, like `rust.std.vec.h` and
+    /// `rust.std.vec.decl.h` for the items of `::std::vec`. The header named `header_file_name`
+    /// includes all of them.
+    ///
+    /// The `.decl.h` header has the C++ classes of the module, which is enough to use them in
+    /// declarations, and the other one adds the definitions of their methods. Both include what
+    /// they need, so they can be included alone. Returns the names and contents of the headers,
+    /// and the C++ file.
+    ///
+    /// # Panics
+    ///
+    /// If `header_file_name` is the name of another header.
+    pub fn render_split(self) -> (Vec<(String, String)>, Option<String>) {
+        let modules = self.modules();
+        let module_of_type = modules
+            .iter()
+            .flat_map(|(namespace, module)| {
+                let traits = module.normal_trait_defs.iter().filter_map(|x| match x {
+                    CppTraitDefinition::Normal { as_ty, .. } => Some(as_ty),
+                    CppTraitDefinition::Fn { .. } => None,
+                });
+                let types = module.type_defs.iter().map(|x| &x.ty).chain(traits);
+                types.map(|x| (x.to_string(), *namespace))
+            })
+            .collect::<HashMap<_, _>>();
+        let deps = |types: &mut dyn Iterator<Item = &CppType>| {
+            let mut result = BTreeSet::new();
+            for ty in types {
+                visit_type(ty, &mut |ty| {
+                    if let Some(&namespace) = module_of_type.get(&ty.to_string()) {
+                        result.insert(namespace);
+                    }
+                });
+            }
+            result
+        };
+        // The unit type is declared in the root namespace, so it always has a module.
+        let root = CppPath::from("rust::Unit");
+        let root = root.namespace();
+
+        let mut headers = vec![(
+            RUNTIME_HEADER_NAME.to_owned(),
+            self.runtime_template().render().unwrap(),
+        )];
+        for (namespace, module) in &modules {
+            let is_root = *namespace == root;
+            let exported_fn_defs = if is_root {
+                self.exported_fn_defs.iter().collect()
+            } else {
+                vec![]
+            };
+            let exported_impls = if is_root {
+                self.exported_impls.iter().collect()
+            } else {
+                vec![]
+            };
+            let exported_types = exported_fn_defs
+                .iter()
+                .flat_map(|x| x.sig.types())
+                .chain(exported_impls.iter().flat_map(|x| {
+                    iter::once(&x.ty)
+                        .chain(&x.tr)
+                        .chain(x.methods.iter().flat_map(|x| x.1.types()))
+                }))
+                .collect_vec();
+
+            let mut early_deps = deps(&mut module.stored_types.iter());
+            early_deps.remove(namespace);
+            let mut all_deps = deps(
+                &mut module
+                    .used_types
+                    .iter()
+                    .chain(&module.stored_types)
+                    .chain(exported_types.iter().copied()),
+            );
+            all_deps.remove(namespace);
+
+            let mut forward_decls = vec![];
+            let mut seen = HashSet::new();
+            for ty in module.used_types.iter().chain(&module.stored_types) {
+                visit_type(ty, &mut |ty| {
+                    if module_of_type.contains_key(&ty.to_string()) && seen.insert(ty.to_string()) {
+                        forward_decls.push(ty);
+                    }
+                });
+            }
+            for imp in &exported_impls {
+                forward_decls.extend(iter::once(&imp.ty).chain(&imp.tr));
+            }
+
+            let declarations = CppHeaderTemplate {
+                part: HeaderPart::Declarations,
+                includes: iter::once(RUNTIME_HEADER_NAME.to_owned())
+                    .chain(
+                        early_deps
+                            .iter()
+                            .map(|x| module_header_name(x, HeaderPart::Declarations)),
+                    )
+                    .collect(),
+                panic_to_exception: &self.panic_to_exception,
+                fn_deps: module.fn_defs.clone(),
+                type_defs: module.type_defs.clone(),
+                forward_decls,
+                trait_defs: &self.trait_defs,
+                normal_trait_defs: module.normal_trait_defs.clone(),
+                exported_impls,
+                exported_fn_defs,
+            };
+            let definitions = CppHeaderTemplate {
+                part: HeaderPart::Definitions,
+                includes: iter::once(module_header_name(namespace, HeaderPart::Declarations))
+                    .chain(
+                        all_deps
+                            .iter()
+                            .map(|x| module_header_name(x, HeaderPart::Definitions)),
+                    )
+                    .collect(),
+                panic_to_exception: &self.panic_to_exception,
+                fn_deps: module.fn_defs.clone(),
+                type_defs: module.type_defs.clone(),
+                forward_decls: vec![],
+                trait_defs: &self.trait_defs,
+                normal_trait_defs: vec![],
+                exported_impls: vec![],
+                exported_fn_defs: vec![],
+            };
+            for template in [declarations, definitions] {
+                headers.push((
+                    module_header_name(namespace, template.part),
+                    format!("#pragma once\n\n{}", template.render().unwrap()),
+                ));
+            }
+        }
+        if headers.iter().any(|x| x.0 == self.header_file_name) {
+            panic!(
+                "The header name `{}` is also used for the header of a module. Use another name.",
+                self.header_file_name
+            );
+        }
+        let mut main_header = "#pragma once\n\n".to_owned();
+        for name in iter::once(RUNTIME_HEADER_NAME.to_owned()).chain(
+            modules
+                .keys()
+                .map(|x| module_header_name(x, HeaderPart::Definitions)),
+        ) {
+            writeln!(main_header, "#include \"{name}\"").unwrap();
+        }
+        headers.push((self.header_file_name.clone(), main_header));
+
+        let headers = headers
+            .into_iter()
+            .map(|(name, text)| {
+                let mut state = State {
+                    text,
+                    panic_to_exception: self.panic_to_exception.clone(),
+                };
+                state.remove_no_except_in_panic();
+                (name, state.text)
+            })
+            .collect();
+        (headers, self.render_cpp_file())
     }
 }
 
@@ -407,7 +902,10 @@ pub fn cpp_handle_keyword(name: &str) -> &str {
 }
 
 pub fn cpp_handle_field_name(name: &str) -> String {
-    if name.parse::<u32>().is_ok() {
+    if name.contains('.') {
+        return cpp_handle_field_name(&name.replace('.', "_"));
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
         return format!("f{name}");
     }
     cpp_handle_keyword(name).to_owned()
@@ -1,15 +1,22 @@
+use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 
 use cpp::CppExportedFnDefinition;
 use cpp::CppExportedImplDefinition;
+use cpp::CppField;
+use cpp::CppFieldAccessor;
 use cpp::CppFile;
+use cpp::CppFnCall;
 use cpp::CppFnDefinition;
 use cpp::CppFnSig;
 use cpp::CppMethod;
 use cpp::CppPath;
+use cpp::CppResultWrapper;
+use cpp::CppSharedDyn;
 use cpp::CppTraitDefinition;
 use cpp::CppType;
 use cpp::CppTypeDefinition;
+use cpp::cpp_handle_field_name;
 use cpp::cpp_handle_keyword;
 use itertools::Itertools;
 use rust::IntoCpp;
@@ -25,12 +32,44 @@ pub use zngur_def::*;
 
 pub struct ZngurGenerator(pub ZngurSpec);
 
+/// An error which the parser reports for a zng file, found by the generator in a spec which
+/// didn't come from one, like a spec built in Rust or loaded from JSON.
+#[derive(Debug)]
+pub struct InvalidSpec(pub String);
+
+impl std::fmt::Display for InvalidSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidSpec {}
+
+/// The Rust file, the headers with their names and the C++ file, if it's needed, of
+/// [`ZngurGenerator::render_split`].
+pub type SplitFiles = (String, Vec<(String, String)>, Option<String>);
+
 impl ZngurGenerator {
     pub fn build_from_zng(zng: ZngurSpec) -> Self {
         ZngurGenerator(zng)
     }
 
-    pub fn render(self) -> (String, String, Option<String>) {
+    pub fn render(self) -> Result<(String, String, Option<String>), InvalidSpec> {
+        let (rust_file, cpp_file) = self.build()?;
+        let (h, cpp) = cpp_file.render();
+        Ok((rust_file.text, h, cpp))
+    }
+
+    /// Like [`ZngurGenerator::render`], but with the header split into the runtime and the
+    /// headers of each module, which are returned with their names. See
+    /// [`CppFile::render_split`].
+    pub fn render_split(self) -> Result<SplitFiles, InvalidSpec> {
+        let (rust_file, cpp_file) = self.build()?;
+        let (headers, cpp) = cpp_file.render_split();
+        Ok((rust_file.text, headers, cpp))
+    }
+
+    fn build(self) -> Result<(RustFile, CppFile), InvalidSpec> {
         let mut zng = self.0;
 
         // Unit type is a bit special, and almost everyone needs it, so we add it ourself.
@@ -43,7 +82,11 @@ impl ZngurGenerator {
             fields: vec![],
             cpp_value: None,
             cpp_ref: None,
+            cpp_std_bridge: false,
+            result_policy: None,
+            is_enum: false,
         });
+        declare_closure_types(&mut zng);
         let mut cpp_file = CppFile::default();
         cpp_file.header_file_name = zng.cpp_include_header_name.clone();
         cpp_file.additional_includes = zng.additional_includes.0;
@@ -51,11 +94,32 @@ impl ZngurGenerator {
         cpp_file.trait_defs = zng
             .traits
             .iter()
-            .map(|(key, value)| (key.clone(), rust_file.add_builder_for_dyn_trait(value)))
-            .collect();
+            .map(|(key, value)| {
+                Ok((
+                    key.clone(),
+                    rust_file.add_builder_for_dyn_trait(
+                        value,
+                        &zng.traits,
+                        zng.types
+                            .iter()
+                            .any(|x| shared_dyn_trait(&x.ty).is_some_and(|(_, _, tr)| tr == key)),
+                    )?,
+                ))
+            })
+            .collect::<Result<_, InvalidSpec>>()?;
         if zng.convert_panic_to_exception.0 {
             cpp_file.panic_to_exception = Some(rust_file.enable_panic_to_exception());
         }
+        let mut results = ResultBridges {
+            declared: zng.types.iter().map(|x| x.ty.clone()).collect(),
+            policies: zng
+                .types
+                .iter()
+                .filter_map(|x| Some((x.ty.clone(), x.result_policy?)))
+                .collect(),
+            symbols: HashMap::new(),
+        };
+        let heap_allocator = zng.heap_allocator;
         for ty_def in zng.types {
             let ty = &ty_def.ty;
             let is_copy = ty_def.wellknown_traits.contains(&ZngurWellknownTrait::Copy);
@@ -64,7 +128,7 @@ impl ZngurGenerator {
                     rust_file.add_static_size_assert(&ty, size);
                     rust_file.add_static_align_assert(&ty, align);
                 }
-                LayoutPolicy::HeapAllocated => (),
+                LayoutPolicy::HeapAllocated { .. } => (),
                 LayoutPolicy::OnlyByRef => (),
             }
             if is_copy {
@@ -73,7 +137,11 @@ impl ZngurGenerator {
             let mut cpp_methods = vec![];
             let mut constructors = vec![];
             let mut fields = vec![];
+            let mut field_accessors = vec![];
             let mut wellknown_traits = vec![];
+            let enum_def = ty_def
+                .is_enum
+                .then(|| rust_file.add_enum_shims(&ty, &ty_def.constructors.iter().collect_vec()));
             for constructor in ty_def.constructors {
                 match constructor.name {
                     Some(name) => {
@@ -87,6 +155,7 @@ impl ZngurGenerator {
                                 inputs: constructor.inputs.iter().map(|x| x.1.into_cpp()).collect(),
                                 output: ty.into_cpp(),
                             },
+                            result: None,
                         });
                         cpp_methods.push(CppMethod {
                             name: format!("matches_{}", name),
@@ -96,6 +165,7 @@ impl ZngurGenerator {
                                 inputs: vec![ty.into_cpp().into_ref()],
                                 output: CppType::from("uint8_t"),
                             },
+                            result: None,
                         });
                     }
                     None => {
@@ -110,9 +180,23 @@ impl ZngurGenerator {
                     }
                 }
             }
+            // C++ addresses the fields of inline values with a constant offset. Everything else
+            // goes through accessors.
+            let is_inline = matches!(ty_def.layout, LayoutPolicy::StackAllocated { .. });
             for field in ty_def.fields {
                 rust_file.add_field_assertions(&field, &ty_def.ty);
-                fields.push(field);
+                match field.offset {
+                    Some(offset) if is_inline => fields.push(CppField {
+                        name: cpp_handle_field_name(&field.name),
+                        ty: field.ty.into_cpp(),
+                        offset,
+                    }),
+                    _ => field_accessors.push(CppFieldAccessor {
+                        name: cpp_handle_field_name(&field.name),
+                        ty: field.ty.into_cpp(),
+                        offset_fn: rust_file.add_field_offset(&field, &ty_def.ty),
+                    }),
+                }
             }
             if let RustType::Tuple(fields) = &ty_def.ty {
                 if !fields.is_empty() {
@@ -136,6 +220,7 @@ impl ZngurGenerator {
                     data: method,
                     use_path,
                     deref,
+                    result_policy,
                 } = method_details;
                 let (rusty_inputs, inputs) = real_inputs_of_method(&method, &ty);
                 let rust_link_name = rust_file.add_function(
@@ -150,21 +235,25 @@ impl ZngurGenerator {
                     use_path,
                     deref.map(|x| x.1),
                 );
+                let (output, result) =
+                    results.wrap(&mut rust_file, &method.output, result_policy)?;
                 cpp_methods.push(CppMethod {
                     name: cpp_handle_keyword(&method.name).to_owned(),
                     kind: method.receiver,
                     sig: CppFnSig {
                         rust_link_name,
                         inputs,
-                        output: method.output.into_cpp(),
+                        output,
                     },
+                    result,
                 });
             }
             cpp_file.type_defs.push(CppTypeDefinition {
                 ty: ty.into_cpp(),
-                layout: rust_file.add_layout_policy_shim(&ty, ty_def.layout),
+                layout: rust_file.add_layout_policy_shim(&ty, ty_def.layout, &heap_allocator),
                 constructors,
                 fields,
+                field_accessors,
                 methods: cpp_methods,
                 wellknown_traits,
                 cpp_value: ty_def.cpp_value.map(|mut cpp_value| {
@@ -172,6 +261,10 @@ impl ZngurGenerator {
                     cpp_value
                 }),
                 cpp_ref: ty_def.cpp_ref,
+                cpp_std_bridge: ty_def
+                    .cpp_std_bridge
+                    .then(|| rust_file.add_cpp_std_bridge(ty))
+                    .transpose()?,
                 from_trait: if let RustType::Boxed(b) = &ty {
                     if let RustType::Dyn(tr, _) = b.as_ref() {
                         if let RustTrait::Fn {
@@ -199,11 +292,67 @@ impl ZngurGenerator {
                 } else {
                     None
                 },
+                needs_thread_safe: match &ty {
+                    RustType::Boxed(b) => matches!(
+                        b.as_ref(),
+                        RustType::Dyn(_, markers)
+                            if markers.iter().any(|x| x == "Send" || x == "Sync")
+                    ),
+                    RustType::Adt(pg) if shared_dyn_trait(ty).is_some() => matches!(
+                        &pg.generics[..],
+                        [RustType::Dyn(_, markers)]
+                            if markers.iter().any(|x| x == "Send" || x == "Sync")
+                    ),
+                    RustType::Dyn(_, markers) => markers.iter().any(|x| x == "Send" || x == "Sync"),
+                    _ => false,
+                },
+                enum_def,
+                shared_dyn: shared_dyn_trait(ty).and_then(|(pointer, make_fn, tr)| {
+                    let Some(CppTraitDefinition::Normal {
+                        link_name_shared: Some(builder),
+                        ..
+                    }) = cpp_file.trait_defs.get(tr)
+                    else {
+                        return None;
+                    };
+                    Some(CppSharedDyn {
+                        tr: tr.clone(),
+                        make_fn,
+                        rust_link_name: rust_file
+                            .add_builder_for_shared_dyn(ty, &pointer, tr, builder),
+                    })
+                }),
                 from_trait_ref: if let RustType::Dyn(tr, _) = &ty {
                     Some(tr.clone())
                 } else {
                     None
                 },
+                fn_call: closure_signature(ty)
+                    .filter(|(kind, _, _)| matches!(ty, RustType::Boxed(_)) || *kind != "FnOnce")
+                    .map(|(kind, inputs, output)| CppFnCall {
+                        kind: kind.to_owned(),
+                        sig: CppFnSig {
+                            rust_link_name: rust_file.add_dyn_fn_call(ty, kind, inputs, output),
+                            inputs: inputs.iter().map(|x| x.into_cpp()).collect(),
+                            output: output.into_cpp(),
+                        },
+                    }),
+                slice_elem: match ty {
+                    RustType::Slice(elem)
+                        if matches!(
+                            **elem,
+                            RustType::Primitive(
+                                PrimitiveRustType::Int(_)
+                                    | PrimitiveRustType::Uint(_)
+                                    | PrimitiveRustType::Float(_)
+                                    | PrimitiveRustType::Usize
+                            )
+                        ) =>
+                    {
+                        Some(elem.into_cpp())
+                    }
+                    _ => None,
+                },
             });
         }
         for func in zng.funcs {
@@ -214,18 +363,25 @@ impl ZngurGenerator {
                 None,
                 None,
             );
+            let (output, result) =
+                results.wrap(&mut rust_file, &func.output, func.result_policy)?;
             cpp_file.fn_defs.push(CppFnDefinition {
                 name: CppPath::from_rust_path(&func.path.path),
                 sig: CppFnSig {
                     rust_link_name,
                     inputs: func.inputs.into_iter().map(|x| x.into_cpp()).collect(),
-                    output: func.output.into_cpp(),
+                    output,
                 },
+                result,
             });
         }
         for func in zng.extern_cpp_funcs {
-            let rust_link_name =
-                rust_file.add_extern_cpp_function(&func.name, &func.inputs, &func.output);
+            let rust_link_name = rust_file.add_extern_cpp_function(
+                &func.name,
+                &func.inputs,
+                &func.output,
+                func.exception_policy,
+            );
             cpp_file.exported_fn_defs.push(CppExportedFnDefinition {
                 name: func.name.clone(),
                 sig: CppFnSig {
@@ -233,6 +389,7 @@ impl ZngurGenerator {
                     inputs: func.inputs.into_iter().map(|x| x.into_cpp()).collect(),
                     output: func.output.into_cpp(),
                 },
+                exception_policy: func.exception_policy,
             });
         }
         for impl_block in zng.extern_cpp_impls {
@@ -249,6 +406,10 @@ impl ZngurGenerator {
                     .iter()
                     .zip(&rust_link_names)
                     .map(|(method, link_name)| {
+                        let ZngurExternCppMethod {
+                            data: method,
+                            exception_policy,
+                        } = method;
                         let (_, inputs) = real_inputs_of_method(method, &impl_block.ty);
                         (
                             cpp_handle_keyword(&method.name).to_owned(),
@@ -257,13 +418,220 @@ impl ZngurGenerator {
                                 inputs,
                                 output: method.output.into_cpp(),
                             },
+                            *exception_policy,
                         )
                     })
                     .collect(),
             });
         }
-        let (h, cpp) = cpp_file.render();
-        (rust_file.text, h, cpp)
+        Ok((rust_file, cpp_file))
+    }
+}
+
+/// Returns the path of the pointer, the name of the C++ builder and the trait of an
+/// `Arc<dyn Trait>` or `Rc<dyn Trait>` type.
+fn shared_dyn_trait(ty: &RustType) -> Option<(String, &'static str, &RustTrait)> {
+    let RustType::Adt(pg) = ty else {
+        return None;
+    };
+    let make_fn = match pg.path.iter().map(|x| x.as_str()).collect_vec()[..] {
+        ["std" | "alloc", "sync", "Arc"] => "make_arc",
+        ["std" | "alloc", "rc", "Rc"] => "make_rc",
+        _ => return None,
+    };
+    let [RustType::Dyn(tr @ RustTrait::Normal(_), _)] = &pg.generics[..] else {
+        return None;
+    };
+    Some((format!("::{}", pg.path.join("::")), make_fn, tr))
+}
+
+/// Returns the trait name, inputs and output of a `Box<dyn Fn*>` or a `dyn Fn*` type.
+fn closure_signature(ty: &RustType) -> Option<(&str, &[RustType], &RustType)> {
+    let ty = match ty {
+        RustType::Boxed(b) => b.as_ref(),
+        _ => ty,
+    };
+    match ty {
+        RustType::Dyn(
+            RustTrait::Fn {
+                name,
+                inputs,
+                output,
+            },
+            _,
+        ) => Some((name, inputs, output)),
+        _ => None,
+    }
+}
+
+/// Declares the closure types which appear in a signature without being declared in the
+/// zng file, so that C++ can call them. A `Box<dyn Fn*>` is a fat pointer stored inline
+/// in the C++ object, and a `dyn Fn*` is only usable by reference.
+fn declare_closure_types(zng: &mut ZngurSpec) {
+    let mut used: Vec<RustType> = vec![];
+    let mut visit = |ty: &RustType| collect_closure_types(ty, &mut used);
+    for func in &zng.funcs {
+        func.inputs
+            .iter()
+            .chain([&func.output])
+            .for_each(&mut visit);
+    }
+    for method in zng.types.iter().flat_map(|x| &x.methods) {
+        let method = &method.data;
+        method
+            .inputs
+            .iter()
+            .chain([&method.output])
+            .for_each(&mut visit);
+    }
+    for tr in zng.traits.values().sorted_by_key(|x| x.tr.to_string()) {
+        for method in &tr.methods {
+            method
+                .inputs
+                .iter()
+                .chain([&method.output])
+                .for_each(&mut visit);
+        }
+    }
+    for func in &zng.extern_cpp_funcs {
+        func.inputs
+            .iter()
+            .chain([&func.output])
+            .for_each(&mut visit);
+    }
+    for method in zng.extern_cpp_impls.iter().flat_map(|x| &x.methods) {
+        let method = &method.data;
+        method
+            .inputs
+            .iter()
+            .chain([&method.output])
+            .for_each(&mut visit);
+    }
+    for ty in used {
+        if zng.types.iter().any(|x| x.ty == ty) {
+            continue;
+        }
+        let (layout, wellknown_traits) = match ty {
+            RustType::Boxed(_) => (
+                LayoutPolicy::HeapAllocated {
+                    inline_size: Some(BOXED_CLOSURE_INLINE_SIZE),
+                },
+                vec![ZngurWellknownTrait::Drop],
+            ),
+            _ => (LayoutPolicy::OnlyByRef, vec![ZngurWellknownTrait::Unsized]),
+        };
+        zng.types.push(ZngurType {
+            ty,
+            layout,
+            wellknown_traits,
+            methods: vec![],
+            constructors: vec![],
+            fields: vec![],
+            cpp_value: None,
+            cpp_ref: None,
+            cpp_std_bridge: false,
+            result_policy: None,
+            is_enum: false,
+        });
+    }
+}
+
+/// The inline storage of an auto-declared `Box<dyn Fn*>`, which is a data pointer and a vtable
+/// pointer. The generator may not run on the target, so this is the size on 64-bit targets
+/// instead of the size of the host. It's only an upper bound, since the C++ object checks the
+/// real size at runtime, so the box is still stored inline on 32-bit targets, and would be heap
+/// allocated on a target with bigger pointers.
+const BOXED_CLOSURE_INLINE_SIZE: usize = 2 * 8;
+
+/// Adds the closure types in `ty` to `used`, including the ones nested in generic arguments,
+/// like the box in `Option<Box<dyn Fn()>>`, and in the signatures of other closures. A
+/// reference to a closure adds the closure type, since references aren't declared.
+fn collect_closure_types(ty: &RustType, used: &mut Vec<RustType>) {
+    if let Some((_, inputs, output)) = closure_signature(ty) {
+        if !used.contains(ty) {
+            used.push(ty.clone());
+        }
+        for ty in inputs.iter().chain([output]) {
+            collect_closure_types(ty, used);
+        }
+        return;
+    }
+    match ty {
+        RustType::Ref(_, inner)
+        | RustType::Raw(_, inner)
+        | RustType::Boxed(inner)
+        | RustType::Slice(inner) => collect_closure_types(inner, used),
+        RustType::Tuple(items) => {
+            for ty in items {
+                collect_closure_types(ty, used);
+            }
+        }
+        RustType::Adt(pg) | RustType::Dyn(RustTrait::Normal(pg), _) => {
+            for ty in pg
+                .generics
+                .iter()
+                .chain(pg.named_generics.iter().map(|x| &x.1))
+            {
+                collect_closure_types(ty, used);
+            }
+        }
+        RustType::Dyn(RustTrait::Fn { .. }, _) | RustType::Primitive(_) => {}
+    }
+}
+
+/// Tracks the `Result` types which are unpacked on the C++ side, so that the
+/// Rust shims for each of them are generated only once.
+struct ResultBridges {
+    declared: Vec<RustType>,
+    policies: HashMap<RustType, ResultPolicy>,
+    symbols: HashMap<RustType, cpp::ResultBridgeSymbols>,
+}
+
+impl ResultBridges {
+    /// Returns the C++ output type of a function returning `output`, and how to unpack
+    /// it if a result policy applies to it.
+    fn wrap(
+        &mut self,
+        rust_file: &mut RustFile,
+        output: &RustType,
+        policy: Option<ResultPolicy>,
+    ) -> Result<(CppType, Option<CppResultWrapper>), InvalidSpec> {
+        let Some(policy) = policy.or_else(|| self.policies.get(output).copied()) else {
+            return Ok((output.into_cpp(), None));
+        };
+        let Some((ok, err)) = output.as_result() else {
+            return Err(InvalidSpec(format!(
+                "Result policy is only supported for functions returning `Result`, found {output}"
+            )));
+        };
+        if !self.declared.contains(output) {
+            return Err(InvalidSpec(format!(
+                "Type {output} is used with a result policy, but it is not declared."
+            )));
+        }
+        let symbols = match self.symbols.get(output) {
+            Some(symbols) => symbols.clone(),
+            None => {
+                let symbols = rust_file.add_result_bridge(output)?;
+                self.symbols.insert(output.clone(), symbols.clone());
+                symbols
+            }
+        };
+        let cpp_output = match policy {
+            ResultPolicy::AsException => ok.into_cpp(),
+            ResultPolicy::AsExpected => CppType {
+                path: CppPath::from("rust::Expected"),
+                generic_args: vec![ok.into_cpp(), err.into_cpp()],
+            },
+        };
+        let wrapper = CppResultWrapper {
+            policy,
+            result_ty: output.into_cpp(),
+            ok: ok.into_cpp(),
+            err: err.into_cpp(),
+            symbols,
+        };
+        Ok((cpp_output, Some(wrapper)))
     }
 }
 
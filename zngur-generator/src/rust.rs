@@ -1,12 +1,14 @@
+use std::collections::HashMap;
 use std::fmt::Write;
 
 use itertools::Itertools;
 
 use crate::{
-    ZngurTrait, ZngurWellknownTrait, ZngurWellknownTraitData,
+    InvalidSpec, ZngurTrait, ZngurWellknownTrait, ZngurWellknownTraitData,
     cpp::{
-        CppLayoutPolicy, CppPath, CppTraitDefinition, CppTraitMethod, CppType,
-        PanicToExceptionSymbols,
+        CppEnum, CppEnumVariant, CppHeapAllocator, CppLayoutPolicy, CppPath, CppStdBridge,
+        CppTraitDefinition, CppTraitMethod, CppType, PanicToExceptionSymbols, ResultBridgeSymbols,
+        cpp_handle_field_name, cpp_handle_keyword,
     },
 };
 
@@ -179,12 +181,37 @@ mod zngur_types {
             (self.destructor)(self.data)
         }
     }
+
+    #[derive(Debug, Clone)]
+    pub struct ZngurCppException {
+        what: String,
+    }
+
+    impl ZngurCppException {
+        pub fn new(what: String) -> Self {
+            Self { what }
+        }
+
+        pub fn what(&self) -> &str {
+            &self.what
+        }
+    }
+
+    impl ::std::fmt::Display for ZngurCppException {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+            write!(f, "C++ exception: {}", self.what)
+        }
+    }
+
+    impl ::std::error::Error for ZngurCppException {}
 }
 
 #[allow(unused_imports)]
 pub use zngur_types::ZngurCppOpaqueOwnedObject;
 #[allow(unused_imports)]
 pub use zngur_types::ZngurCppOpaqueBorrowedObject;
+#[allow(unused_imports)]
+pub use zngur_types::ZngurCppException;
 "#
             .to_owned(),
             panic_to_exception: false,
@@ -234,6 +261,7 @@ fn mangle_name(name: &str, mangling_base: &str) -> String {
         (2, "(", 'p'),
         (2, ")", 'q'),
         (2, "@", 'z'),
+        (2, ".", 'd'),
     ];
     while let Some((pos, which)) = bads.iter().filter_map(|x| Some((name.find(x.1)?, x))).min() {
         name.replace_range(pos..pos + which.1.len(), "_");
@@ -265,6 +293,71 @@ impl RustFile {
         wln!(self, "r.assume_init()");
     }
 
+    /// Like `call_cpp_function`, but for functions which report C++ exceptions back
+    /// according to `policy`.
+    fn call_extern_cpp_function(&mut self, name: &str, inputs: usize, policy: CppExceptionPolicy) {
+        if policy == CppExceptionPolicy::Abort {
+            return self.call_cpp_function(name, inputs);
+        }
+        for n in 0..inputs {
+            wln!(self, "let mut i{n} = ::core::mem::MaybeUninit::new(i{n});")
+        }
+        wln!(self, "let mut r = ::core::mem::MaybeUninit::uninit();");
+        wln!(self, "let mut e: *const u8 = ::core::ptr::null();");
+        wln!(self, "let mut e_len: usize = 0;");
+        w!(self, "if {name}");
+        for n in 0..inputs {
+            w!(self, "i{n}.as_mut_ptr() as *mut u8, ");
+        }
+        wln!(
+            self,
+            "r.as_mut_ptr() as *mut u8, &mut e, &mut e_len) != 0 {{"
+        );
+        wln!(
+            self,
+            "let what = ::std::string::String::from_utf8_lossy(::core::slice::from_raw_parts(e, e_len)).into_owned();"
+        );
+        match policy {
+            CppExceptionPolicy::Abort => unreachable!(),
+            CppExceptionPolicy::Panic => {
+                wln!(
+                    self,
+                    "::std::panic!(\"{{}}\", ZngurCppException::new(what));"
+                );
+                wln!(self, "}}");
+                wln!(self, "r.assume_init()");
+            }
+            CppExceptionPolicy::Result => {
+                wln!(
+                    self,
+                    "return ::std::result::Result::Err(ZngurCppException::new(what));"
+                );
+                wln!(self, "}}");
+                wln!(self, "::std::result::Result::Ok(r.assume_init())");
+            }
+        }
+    }
+
+    fn extern_cpp_function_tail(policy: CppExceptionPolicy) -> &'static str {
+        match policy {
+            CppExceptionPolicy::Abort => "o: *mut u8)",
+            CppExceptionPolicy::Panic | CppExceptionPolicy::Result => {
+                "o: *mut u8, e: *mut *const u8, e_len: *mut usize) -> u8"
+            }
+        }
+    }
+
+    /// The return type of the Rust side of an `extern "C++"` function. With
+    /// `CppExceptionPolicy::Result`, the C++ function returns `output` and Rust wraps it.
+    fn extern_cpp_rust_output(output: &RustType, policy: CppExceptionPolicy) -> String {
+        match policy {
+            CppExceptionPolicy::Abort | CppExceptionPolicy::Panic => output.to_string(),
+            CppExceptionPolicy::Result => {
+                format!("::std::result::Result<{output}, ZngurCppException>")
+            }
+        }
+    }
+
     pub fn add_static_is_copy_assert(&mut self, ty: &RustType) {
         wln!(
             self,
@@ -289,53 +382,121 @@ impl RustFile {
         );
     }
 
-    pub(crate) fn add_builder_for_dyn_trait(&mut self, tr: &ZngurTrait) -> CppTraitDefinition {
+    pub(crate) fn add_builder_for_dyn_trait(
+        &mut self,
+        tr: &ZngurTrait,
+        traits: &HashMap<RustTrait, ZngurTrait>,
+        shared: bool,
+    ) -> Result<CppTraitDefinition, InvalidSpec> {
         assert!(matches!(tr.tr, RustTrait::Normal { .. }));
-        let mut method_mangled_name = vec![];
+        // The trait itself, followed by all of its supertraits, transitively. The wrapper
+        // struct implements each of them by calling into the C++ virtuals.
+        let mut impls = vec![tr];
+        let mut i = 0;
+        while i < impls.len() {
+            for supertrait in &impls[i].supertraits {
+                let Some(supertrait) = traits.get(supertrait) else {
+                    return Err(InvalidSpec(format!(
+                        "Supertrait `{supertrait}` of `{}` is not declared. Declare it with a `trait` block.",
+                        tr.tr
+                    )));
+                };
+                if !impls.iter().any(|x| x.tr == supertrait.tr) {
+                    impls.push(supertrait);
+                }
+            }
+            i += 1;
+        }
+        let mut impls_with_names = vec![];
         wln!(self, r#"unsafe extern "C" {{"#);
-        for method in &tr.methods {
-            let name = self.mangle_name(&tr.tr.to_string()) + "_" + &method.name;
-            wln!(
-                self,
-                r#"fn {name}(data: *mut u8, {} o: *mut u8);"#,
-                method
-                    .inputs
-                    .iter()
-                    .enumerate()
-                    .map(|(n, _)| format!("i{n}: *mut u8,"))
-                    .join(" ")
-            );
-            method_mangled_name.push(name);
+        for (i, imp) in impls.iter().enumerate() {
+            let prefix = if i == 0 {
+                self.mangle_name(&tr.tr.to_string())
+            } else {
+                self.mangle_name(&format!("{}_{}", tr.tr, imp.tr))
+            };
+            let mut method_mangled_name = vec![];
+            for method in &imp.methods {
+                let name = prefix.clone() + "_" + &method.name;
+                wln!(
+                    self,
+                    r#"fn {name}(data: *mut u8, {} o: *mut u8);"#,
+                    method
+                        .inputs
+                        .iter()
+                        .enumerate()
+                        .map(|(n, _)| format!("i{n}: *mut u8,"))
+                        .join(" ")
+                );
+                method_mangled_name.push(name);
+            }
+            impls_with_names.push((*imp, method_mangled_name));
         }
         wln!(self, "}}");
-        let link_name = self.add_builder_for_dyn_trait_owned(tr, &method_mangled_name);
-        let link_name_ref = self.add_builder_for_dyn_trait_borrowed(tr, &method_mangled_name);
-        CppTraitDefinition::Normal {
-            as_ty: tr.tr.into_cpp(),
-            methods: tr
-                .methods
-                .clone()
-                .into_iter()
-                .zip(method_mangled_name)
+        let link_name = self.add_builder_for_dyn_trait_owned(tr, &impls_with_names);
+        let link_name_ref = self.add_builder_for_dyn_trait_borrowed(tr, &impls_with_names);
+        let link_name_shared =
+            shared.then(|| self.add_builder_for_dyn_trait_shared(tr, &impls_with_names));
+        let mut cpp_methods = impls_with_names.into_iter().map(|(imp, names)| {
+            imp.methods
+                .iter()
+                .zip(names)
                 .map(|(x, rust_link_name)| CppTraitMethod {
-                    name: x.name,
+                    name: x.name.clone(),
                     rust_link_name,
-                    inputs: x.inputs.into_iter().map(|x| x.into_cpp()).collect(),
+                    inputs: x.inputs.iter().map(|x| x.into_cpp()).collect(),
                     output: x.output.into_cpp(),
                 })
-                .collect(),
+                .collect_vec()
+        });
+        Ok(CppTraitDefinition::Normal {
+            as_ty: tr.tr.into_cpp(),
+            supertraits: tr.supertraits.clone(),
+            methods: cpp_methods.next().unwrap(),
+            inherited_methods: cpp_methods.flatten().collect(),
             link_name,
             link_name_ref,
+            link_name_shared,
+        })
+    }
+
+    /// Writes `impl Trait for Wrapper` for the trait and each of its supertraits. `data` is the
+    /// expression giving the pointer to the C++ object inside the methods.
+    fn add_dyn_trait_wrapper_impls(&mut self, impls: &[(&ZngurTrait, Vec<String>)], data: &str) {
+        for (imp, method_mangled_name) in impls {
+            let (trait_without_assocs, assocs) = imp.tr.clone().take_assocs();
+            wln!(self, "    impl {trait_without_assocs} for Wrapper {{");
+            for (name, ty) in assocs {
+                wln!(self, "        type {name} = {ty};");
+            }
+            for (method, rust_link_name) in imp.methods.iter().zip(method_mangled_name) {
+                w!(self, "        fn {}(", method.name);
+                match method.receiver {
+                    crate::ZngurMethodReceiver::Static => {
+                        panic!("traits with static methods are not object safe");
+                    }
+                    crate::ZngurMethodReceiver::Ref(Mutability::Not) => w!(self, "&self"),
+                    crate::ZngurMethodReceiver::Ref(Mutability::Mut) => w!(self, "&mut self"),
+                    crate::ZngurMethodReceiver::Move => w!(self, "self"),
+                }
+                for (i, ty) in method.inputs.iter().enumerate() {
+                    w!(self, ", i{i}: {ty}");
+                }
+                wln!(self, ") -> {} {{ unsafe {{", method.output);
+                wln!(self, "            let data = {data};");
+                self.call_cpp_function(&format!("{rust_link_name}(data, "), method.inputs.len());
+                wln!(self, "        }} }}");
+            }
+            wln!(self, "    }}");
         }
     }
 
     fn add_builder_for_dyn_trait_owned(
         &mut self,
         tr: &ZngurTrait,
-        method_mangled_name: &[String],
+        impls: &[(&ZngurTrait, Vec<String>)],
     ) -> String {
         let trait_name = tr.tr.to_string();
-        let (trait_without_assocs, assocs) = tr.tr.clone().take_assocs();
         let mangled_name = self.mangle_name(&trait_name);
         wln!(
             self,
@@ -349,36 +510,12 @@ pub extern "C" fn {mangled_name}(
 ) {{
     struct Wrapper {{ 
         value: ZngurCppOpaqueOwnedObject,
-    }}
-    impl {trait_without_assocs} for Wrapper {{
-"#
+    }}"#
         );
-        for (name, ty) in assocs {
-            wln!(self, "        type {name} = {ty};");
-        }
-        for (method, rust_link_name) in tr.methods.iter().zip(method_mangled_name) {
-            w!(self, "        fn {}(", method.name);
-            match method.receiver {
-                crate::ZngurMethodReceiver::Static => {
-                    panic!("traits with static methods are not object safe");
-                }
-                crate::ZngurMethodReceiver::Ref(Mutability::Not) => w!(self, "&self"),
-                crate::ZngurMethodReceiver::Ref(Mutability::Mut) => w!(self, "&mut self"),
-                crate::ZngurMethodReceiver::Move => w!(self, "self"),
-            }
-            for (i, ty) in method.inputs.iter().enumerate() {
-                w!(self, ", i{i}: {ty}");
-            }
-            wln!(self, ") -> {} {{ unsafe {{", method.output);
-            wln!(self, "            let data = self.value.ptr();");
-            self.call_cpp_function(&format!("{rust_link_name}(data, "), method.inputs.len());
-            wln!(self, "        }} }}");
-        }
+        self.add_dyn_trait_wrapper_impls(impls, "self.value.ptr()");
         wln!(
             self,
-            r#"
-    }}
-    unsafe {{ 
+            r#"    unsafe {{ 
         let this = Wrapper {{
             value: ZngurCppOpaqueOwnedObject::new(data, destructor),
         }};
@@ -393,10 +530,9 @@ pub extern "C" fn {mangled_name}(
     fn add_builder_for_dyn_trait_borrowed(
         &mut self,
         tr: &ZngurTrait,
-        method_mangled_name: &[String],
+        impls: &[(&ZngurTrait, Vec<String>)],
     ) -> String {
         let trait_name = tr.tr.to_string();
-        let (trait_without_assocs, assocs) = tr.tr.clone().take_assocs();
         let mangled_name = self.mangle_name(&trait_name) + "_borrowed";
         wln!(
             self,
@@ -407,39 +543,12 @@ pub extern "C" fn {mangled_name}(
     data: *mut u8,
     o: *mut u8,
 ) {{
-    struct Wrapper(ZngurCppOpaqueBorrowedObject);
-    impl {trait_without_assocs} for Wrapper {{
-"#
+    struct Wrapper(ZngurCppOpaqueBorrowedObject);"#
         );
-        for (name, ty) in assocs {
-            wln!(self, "        type {name} = {ty};");
-        }
-        for (method, rust_link_name) in tr.methods.iter().zip(method_mangled_name) {
-            w!(self, "        fn {}(", method.name);
-            match method.receiver {
-                crate::ZngurMethodReceiver::Static => {
-                    panic!("traits with static methods are not object safe");
-                }
-                crate::ZngurMethodReceiver::Ref(Mutability::Not) => w!(self, "&self"),
-                crate::ZngurMethodReceiver::Ref(Mutability::Mut) => w!(self, "&mut self"),
-                crate::ZngurMethodReceiver::Move => w!(self, "self"),
-            }
-            for (i, ty) in method.inputs.iter().enumerate() {
-                w!(self, ", i{i}: {ty}");
-            }
-            wln!(self, ") -> {} {{ unsafe {{", method.output);
-            wln!(
-                self,
-                "            let data = ::std::mem::transmute::<_, *mut u8>(self);"
-            );
-            self.call_cpp_function(&format!("{rust_link_name}(data, "), method.inputs.len());
-            wln!(self, "        }} }}");
-        }
+        self.add_dyn_trait_wrapper_impls(impls, "::std::mem::transmute::<_, *mut u8>(self)");
         wln!(
             self,
-            r#"
-    }}
-    unsafe {{ 
+            r#"    unsafe {{ 
         let this = data as *mut Wrapper;
         let r: &dyn {trait_name} = &*this;
         std::ptr::write(o as *mut _, r)
@@ -449,13 +558,84 @@ pub extern "C" fn {mangled_name}(
         mangled_name
     }
 
+    /// Unlike the other builders, this one is only called from Rust, by the shims which build
+    /// an `Arc<dyn Trait>` or `Rc<dyn Trait>`. The C++ object behind `data` is kept alive by
+    /// `owner`, which is not necessarily the same object, e.g. a `std::shared_ptr`.
+    fn add_builder_for_dyn_trait_shared(
+        &mut self,
+        tr: &ZngurTrait,
+        impls: &[(&ZngurTrait, Vec<String>)],
+    ) -> String {
+        let trait_name = tr.tr.to_string();
+        let mangled_name = self.mangle_name(&trait_name) + "_shared";
+        wln!(
+            self,
+            r#"
+#[allow(non_snake_case)]
+unsafe fn {mangled_name}(
+    data: *mut u8,
+    owner: *mut u8,
+    destructor: extern "C" fn(*mut u8),
+) -> Box<dyn {trait_name}> {{
+    struct Wrapper {{
+        data: *mut u8,
+        _owner: ZngurCppOpaqueOwnedObject,
+    }}"#
+        );
+        self.add_dyn_trait_wrapper_impls(impls, "self.data");
+        wln!(
+            self,
+            r#"    unsafe {{
+        Box::new(Wrapper {{
+            data,
+            _owner: ZngurCppOpaqueOwnedObject::new(owner, destructor),
+        }})
+    }}
+}}"#
+        );
+        mangled_name
+    }
+
+    /// Adds the shim behind `make_arc`/`make_rc` and `from_shared_ptr` of `ty`, which is
+    /// `pointer<dyn tr>` for `Arc` or `Rc` as `pointer`.
+    pub fn add_builder_for_shared_dyn(
+        &mut self,
+        ty: &RustType,
+        pointer: &str,
+        tr: &RustTrait,
+        shared_builder: &str,
+    ) -> String {
+        let mangled_name = self.mangle_name(&format!("{ty}=from_cpp"));
+        wln!(
+            self,
+            r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {mangled_name}(
+    data: *mut u8,
+    owner: *mut u8,
+    destructor: extern "C" fn(*mut u8),
+    o: *mut u8,
+) {{
+    unsafe {{
+        let r: {pointer}<dyn {tr}> = {pointer}::from({shared_builder}(data, owner, destructor));
+        ::std::ptr::write(o as *mut _, r)
+    }}
+}}"#
+        );
+        mangled_name
+    }
+
     pub fn add_builder_for_dyn_fn(
         &mut self,
         name: &str,
         inputs: &[RustType],
         output: &RustType,
     ) -> String {
-        let mangled_name = self.mangle_name(&inputs.iter().chain(Some(output)).join(", "));
+        let mangled_name = self.mangle_name(&format!(
+            "{name}_{}",
+            inputs.iter().chain(Some(output)).join(", ")
+        ));
         let trait_str = format!("{name}({}) -> {output}", inputs.iter().join(", "));
         wln!(
             self,
@@ -495,6 +675,49 @@ pub extern "C" fn {mangled_name}(
         mangled_name
     }
 
+    /// Adds the shim behind the `operator()` of a closure type `ty`, which is either a
+    /// `Box<dyn Fn*>`, or a `dyn Fn*` that C++ holds by reference.
+    pub fn add_dyn_fn_call(
+        &mut self,
+        ty: &RustType,
+        kind: &str,
+        inputs: &[RustType],
+        output: &RustType,
+    ) -> String {
+        let mangled_name = self.mangle_name(&format!("{ty}=call"));
+        let callee = match (ty, kind) {
+            (RustType::Boxed(_), "FnOnce") => format!("::std::ptr::read(f as *mut {ty})"),
+            (RustType::Boxed(_), "FnMut") => format!("&mut *(f as *mut {ty})"),
+            (RustType::Boxed(_), _) => format!("&*(f as *const {ty})"),
+            (_, "FnMut") => format!("::std::ptr::read(f as *mut &mut ({ty}))"),
+            _ => format!("::std::ptr::read(f as *mut &({ty}))"),
+        };
+        w!(
+            self,
+            r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+#[allow(unused_parens)]
+pub extern "C" fn {mangled_name}(f: *mut u8, "#
+        );
+        for n in 0..inputs.len() {
+            w!(self, "i{n}: *mut u8, ");
+        }
+        wln!(self, "o: *mut u8) {{ unsafe {{");
+        self.wrap_in_catch_unwind(|this| {
+            w!(
+                this,
+                "    ::std::ptr::write(o as *mut {output}, ({callee})("
+            );
+            for (n, ty) in inputs.iter().enumerate() {
+                w!(this, "::std::ptr::read(i{n} as *mut {ty}), ");
+            }
+            wln!(this, "));");
+        });
+        wln!(self, " }} }}");
+        mangled_name
+    }
+
     pub fn add_tuple_constructor(&mut self, fields: &[RustType]) -> String {
         let constructor = self.mangle_name(&fields.iter().join("&"));
         w!(
@@ -560,31 +783,126 @@ pub extern "C" fn {match_check}(i: *mut u8, o: *mut u8) {{ unsafe {{
         }
     }
 
+    /// Adds the shims behind `tag()` and the variant accessors of an enum. The match in the
+    /// tag shim is exhaustive, so a missing variant fails the Rust build.
+    pub(crate) fn add_enum_shims(
+        &mut self,
+        ty: &RustType,
+        variants: &[&ZngurConstructor],
+    ) -> CppEnum {
+        let tag_fn = self.mangle_name(&format!("{ty}=tag"));
+        wln!(
+            self,
+            r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {tag_fn}(i: *mut u8) -> u32 {{ unsafe {{
+    match &*(i as *const {ty}) {{"#
+        );
+        for (n, variant) in variants.iter().enumerate() {
+            let name = variant.name.as_ref().unwrap();
+            wln!(self, "        {ty}::{name} {{ .. }} => {n},");
+        }
+        wln!(self, "    }}\n}} }}");
+        let variants = variants
+            .iter()
+            .map(|variant| {
+                let name = variant.name.clone().unwrap();
+                let accessor = (!variant.inputs.is_empty()).then(|| {
+                    let accessor = self.mangle_name(&format!("{ty}=as_{name}"));
+                    w!(
+                        self,
+                        r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {accessor}(i: *mut u8, "#
+                    );
+                    for (field, _) in &variant.inputs {
+                        w!(self, "o_{field}: *mut u8, ");
+                    }
+                    w!(self, ") {{ unsafe {{\n    let {ty}::{name} {{ ");
+                    for (field, _) in &variant.inputs {
+                        w!(self, "{field}: f_{field}, ");
+                    }
+                    // The C++ side checks the variant, and a panic can't unwind out of the shim.
+                    wln!(
+                        self,
+                        "}} = &*(i as *const {ty}) else {{ ::std::process::abort() }};"
+                    );
+                    for (field, field_ty) in &variant.inputs {
+                        wln!(
+                            self,
+                            "    ::std::ptr::write(o_{field} as *mut &{field_ty}, f_{field});"
+                        );
+                    }
+                    wln!(self, "}} }}");
+                    accessor
+                });
+                CppEnumVariant {
+                    name: cpp_handle_keyword(&name).to_owned(),
+                    fields: variant
+                        .inputs
+                        .iter()
+                        .map(|(field, ty)| (cpp_handle_field_name(field), ty.into_cpp()))
+                        .collect(),
+                    accessor,
+                }
+            })
+            .collect();
+        CppEnum { tag_fn, variants }
+    }
+
     pub(crate) fn add_field_assertions(&mut self, field: &ZngurField, owner: &RustType) {
         let ZngurField { name, ty, offset } = field;
+        if let Some(offset) = offset {
+            wln!(
+                self,
+                "const _: [(); {offset}] = [(); ::std::mem::offset_of!({owner}, {name})];"
+            );
+        }
         wln!(
             self,
             r#"
-            const _: [(); {offset}] = [(); ::std::mem::offset_of!({owner}, {name})];
             const _: () = {{
                 #[allow(dead_code)]
-                fn check_field(value: {owner}) -> {ty} {{
-                    value.{name}
+                fn check_field(value: &{owner}) -> &{ty} {{
+                    &value.{name}
                 }}
             }};
             "#
         );
     }
 
+    /// Adds a shim returning the offset of a field, for fields that C++ can't address with a
+    /// constant offset.
+    pub(crate) fn add_field_offset(&mut self, field: &ZngurField, owner: &RustType) -> String {
+        let name = &field.name;
+        let mangled_name = self.mangle_name(&format!("{owner}=offset_of_{name}"));
+        wln!(
+            self,
+            r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {mangled_name}() -> usize {{
+    ::std::mem::offset_of!({owner}, {name})
+}}"#
+        );
+        mangled_name
+    }
+
     pub fn add_extern_cpp_impl(
         &mut self,
         owner: &RustType,
         tr: Option<&RustTrait>,
-        methods: &[ZngurMethod],
+        methods: &[ZngurExternCppMethod],
     ) -> Vec<String> {
         let mut mangled_names = vec![];
         w!(self, r#"unsafe extern "C" {{"#);
-        for method in methods {
+        for ZngurExternCppMethod {
+            data: method,
+            exception_policy,
+        } in methods
+        {
             let mn = self.mangle_name(&format!("{}_extern_method_{}", owner, method.name));
             w!(
                 self,
@@ -599,7 +917,11 @@ pub extern "C" fn {match_check}(i: *mut u8, o: *mut u8) {{ unsafe {{
             for n in 0..method.inputs.len() + input_offset {
                 w!(self, "i{n}: *mut u8, ");
             }
-            wln!(self, r#"o: *mut u8);"#);
+            wln!(
+                self,
+                "{};",
+                Self::extern_cpp_function_tail(*exception_policy)
+            );
             mangled_names.push(mn);
         }
         w!(self, r#"}}"#);
@@ -613,7 +935,14 @@ pub extern "C" fn {match_check}(i: *mut u8, o: *mut u8) {{ unsafe {{
             }
             None => w!(self, r#"impl {owner} {{"#),
         }
-        for (mn, method) in mangled_names.iter().zip(methods) {
+        for (
+            mn,
+            ZngurExternCppMethod {
+                data: method,
+                exception_policy,
+            },
+        ) in mangled_names.iter().zip(methods)
+        {
             if tr.is_none() {
                 w!(self, "pub ");
             }
@@ -632,11 +961,19 @@ pub extern "C" fn {match_check}(i: *mut u8, o: *mut u8) {{ unsafe {{
             for (ty, n) in method.inputs.iter().zip(input_offset..) {
                 w!(self, "i{n}: {ty}, ");
             }
-            wln!(self, ") -> {} {{ unsafe {{", method.output);
+            wln!(
+                self,
+                ") -> {} {{ unsafe {{",
+                Self::extern_cpp_rust_output(&method.output, *exception_policy)
+            );
             if method.receiver != ZngurMethodReceiver::Static {
                 wln!(self, "let i0 = self;");
             }
-            self.call_cpp_function(&format!("{mn}("), method.inputs.len() + input_offset);
+            self.call_extern_cpp_function(
+                &format!("{mn}("),
+                method.inputs.len() + input_offset,
+                *exception_policy,
+            );
             wln!(self, "}} }}");
         }
         w!(self, r#"}}"#);
@@ -648,6 +985,7 @@ pub extern "C" fn {match_check}(i: *mut u8, o: *mut u8) {{ unsafe {{
         rust_name: &str,
         inputs: &[RustType],
         output: &RustType,
+        exception_policy: CppExceptionPolicy,
     ) -> String {
         let mangled_name = self.mangle_name(rust_name);
         w!(
@@ -658,7 +996,11 @@ unsafe extern "C" {{ fn {mangled_name}("#
         for (n, _) in inputs.iter().enumerate() {
             w!(self, "i{n}: *mut u8, ");
         }
-        wln!(self, r#"o: *mut u8); }}"#);
+        wln!(
+            self,
+            "{}; }}",
+            Self::extern_cpp_function_tail(exception_policy)
+        );
         w!(
             self,
             r#"
@@ -667,8 +1009,12 @@ pub(crate) fn {rust_name}("#
         for (n, ty) in inputs.iter().enumerate() {
             w!(self, "i{n}: {ty}, ");
         }
-        wln!(self, ") -> {output} {{ unsafe {{");
-        self.call_cpp_function(&format!("{mangled_name}("), inputs.len());
+        wln!(
+            self,
+            ") -> {} {{ unsafe {{",
+            Self::extern_cpp_rust_output(output, exception_policy)
+        );
+        self.call_extern_cpp_function(&format!("{mangled_name}("), inputs.len(), exception_policy);
         wln!(self, "}} }}");
         mangled_name
     }
@@ -687,6 +1033,171 @@ pub extern "C" fn {mangled_name}(d: *mut u8) -> *mut ZngurCppOpaqueOwnedObject {
         mangled_name
     }
 
+    pub(crate) fn add_cpp_std_bridge(
+        &mut self,
+        ty: &RustType,
+    ) -> Result<CppStdBridge, InvalidSpec> {
+        let Some(kind) = ty.cpp_std_bridge() else {
+            return Err(InvalidSpec(format!(
+                "`#cpp_std_bridge` is only supported for `String`, `Vec<T>` with a numeric `T` and `Option<T>`, found {ty}"
+            )));
+        };
+        Ok(match kind {
+            CppStdBridgeKind::String => {
+                let from_std = self.mangle_name(&format!("{ty}_from_std"));
+                let as_bytes = self.mangle_name(&format!("{ty}_as_bytes"));
+                wln!(
+                    self,
+                    r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {from_std}(data: *const u8, len: usize, o: *mut u8) {{ unsafe {{
+    let bytes: &[u8] = if len == 0 {{ &[] }} else {{ ::std::slice::from_raw_parts(data, len) }};
+    ::std::ptr::write(o as *mut {ty}, ::std::string::String::from_utf8_lossy(bytes).into_owned());
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {as_bytes}(i: *mut u8, o: *mut *const u8) -> usize {{ unsafe {{
+    let v = &*(i as *const {ty});
+    *o = v.as_ptr();
+    v.len()
+}} }}"#
+                );
+                CppStdBridge::String { from_std, as_bytes }
+            }
+            CppStdBridgeKind::Vec(elem) => {
+                let from_span = self.mangle_name(&format!("{ty}_from_span"));
+                let as_slice = self.mangle_name(&format!("{ty}_as_slice"));
+                wln!(
+                    self,
+                    r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {from_span}(data: *const u8, len: usize, o: *mut u8) {{ unsafe {{
+    let elems: &[{elem}] = if len == 0 {{ &[] }} else {{ ::std::slice::from_raw_parts(data as *const {elem}, len) }};
+    ::std::ptr::write(o as *mut {ty}, elems.to_vec());
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {as_slice}(i: *mut u8, o: *mut *const u8) -> usize {{ unsafe {{
+    let v = &*(i as *const {ty});
+    *o = v.as_ptr() as *const u8;
+    v.len()
+}} }}"#
+                );
+                CppStdBridge::Vec {
+                    elem: elem.into_cpp(),
+                    from_span,
+                    as_slice,
+                }
+            }
+            CppStdBridgeKind::Option(elem) => {
+                let is_some = self.mangle_name(&format!("{ty}_is_some"));
+                let some = self.mangle_name(&format!("{ty}_some"));
+                let none = self.mangle_name(&format!("{ty}_none"));
+                let unwrap = self.mangle_name(&format!("{ty}_unwrap"));
+                let unwrap_or = self.mangle_name(&format!("{ty}_unwrap_or"));
+                wln!(
+                    self,
+                    r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {is_some}(i: *mut u8) -> u8 {{ unsafe {{
+    (&*(i as *const {ty})).is_some() as u8
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {some}(v: *mut u8, o: *mut u8) {{ unsafe {{
+    ::std::ptr::write(o as *mut {ty}, ::std::option::Option::Some(::std::ptr::read(v as *mut {elem})));
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {none}(o: *mut u8) {{ unsafe {{
+    ::std::ptr::write(o as *mut {ty}, ::std::option::Option::None);
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {unwrap}(i: *mut u8, o: *mut u8) {{ unsafe {{"#
+                );
+                self.wrap_in_catch_unwind(|this| {
+                    wln!(
+                        this,
+                        "    ::std::ptr::write(o as *mut {elem}, ::std::ptr::read(i as *mut {ty}).unwrap());"
+                    );
+                });
+                wln!(
+                    self,
+                    r#"}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {unwrap_or}(i: *mut u8, d: *mut u8, o: *mut u8) {{ unsafe {{
+    ::std::ptr::write(o as *mut {elem}, ::std::ptr::read(i as *mut {ty}).unwrap_or(::std::ptr::read(d as *mut {elem})));
+}} }}"#
+                );
+                CppStdBridge::Option {
+                    elem: elem.into_cpp(),
+                    is_some,
+                    some,
+                    none,
+                    unwrap,
+                    unwrap_or,
+                }
+            }
+        })
+    }
+
+    pub(crate) fn add_result_bridge(
+        &mut self,
+        ty: &RustType,
+    ) -> Result<ResultBridgeSymbols, InvalidSpec> {
+        let Some((ok, err)) = ty.as_result() else {
+            return Err(InvalidSpec(format!(
+                "Result policy is only supported for `Result` types, found {ty}"
+            )));
+        };
+        let is_ok = self.mangle_name(&format!("{ty}_is_ok"));
+        let unwrap = self.mangle_name(&format!("{ty}_unwrap"));
+        let unwrap_err = self.mangle_name(&format!("{ty}_unwrap_err"));
+        wln!(
+            self,
+            r#"
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {is_ok}(i: *mut u8) -> u8 {{ unsafe {{
+    (&*(i as *const {ty})).is_ok() as u8
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {unwrap}(i: *mut u8, o: *mut u8) {{ unsafe {{
+    match ::std::ptr::read(i as *mut {ty}) {{
+        ::std::result::Result::Ok(v) => ::std::ptr::write(o as *mut {ok}, v),
+        ::std::result::Result::Err(_) => ::std::process::abort(),
+    }}
+}} }}
+
+#[allow(non_snake_case)]
+#[unsafe(no_mangle)]
+pub extern "C" fn {unwrap_err}(i: *mut u8, o: *mut u8) {{ unsafe {{
+    match ::std::ptr::read(i as *mut {ty}) {{
+        ::std::result::Result::Ok(_) => ::std::process::abort(),
+        ::std::result::Result::Err(e) => ::std::ptr::write(o as *mut {err}, e),
+    }}
+}} }}"#
+        );
+        Ok(ResultBridgeSymbols {
+            is_ok,
+            unwrap,
+            unwrap_err,
+        })
+    }
+
     pub fn add_function(
         &mut self,
         rust_name: &str,
@@ -773,8 +1284,9 @@ pub extern "C" fn {drop_in_place}(v: *mut u8) {{ unsafe {{
                     r#"
 #[allow(non_snake_case)]
 #[unsafe(no_mangle)]
-pub extern "C" fn {pretty_print}(v: *mut u8) {{
-    eprintln!("{{:#?}}", unsafe {{ &*(v as *mut {dbg_ty}) }});
+pub extern "C" fn {pretty_print}(v: *mut u8, out: *mut u8, append: extern "C" fn(*mut u8, *const u8, usize)) {{
+    let s = format!("{{:#?}}", unsafe {{ &*(v as *mut {dbg_ty}) }});
+    append(out, s.as_ptr(), s.len());
 }}"#
                 );
                 wln!(
@@ -782,8 +1294,9 @@ pub extern "C" fn {pretty_print}(v: *mut u8) {{
                     r#"
 #[allow(non_snake_case)]
 #[unsafe(no_mangle)]
-pub extern "C" fn {debug_print}(v: *mut u8) {{
-    eprintln!("{{:?}}", unsafe {{ &*(v as *mut {dbg_ty}) }});
+pub extern "C" fn {debug_print}(v: *mut u8, out: *mut u8, append: extern "C" fn(*mut u8, *const u8, usize)) {{
+    let s = format!("{{:?}}", unsafe {{ &*(v as *mut {dbg_ty}) }});
+    append(out, s.as_ptr(), s.len());
 }}"#
                 );
                 ZngurWellknownTraitData::Debug {
@@ -797,27 +1310,101 @@ pub extern "C" fn {debug_print}(v: *mut u8) {{
     pub(crate) fn enable_panic_to_exception(&mut self) -> PanicToExceptionSymbols {
         let detect_panic = self.mangle_name("@detect_panic");
         let take_panic = self.mangle_name("@take_panic");
+        let panic_payload = self.mangle_name("@panic_payload");
         wln!(
             self,
-            r#"thread_local! {{
-            pub static PANIC_PAYLOAD: ::std::cell::Cell<Option<()>> = ::std::cell::Cell::new(None);
+            r#"pub struct ZngurPanic {{
+            message: ::std::string::String,
+            file: ::std::string::String,
+            line: u32,
+            backtrace: Option<::std::string::String>,
         }}
+
+        thread_local! {{
+            pub static PANIC_PAYLOAD: ::std::cell::RefCell<Option<ZngurPanic>> = ::std::cell::RefCell::new(None);
+            // Filled by the panic hook, since the payload of `catch_unwind` has no location.
+            static PANIC_LOCATION: ::std::cell::RefCell<Option<(::std::string::String, u32, Option<::std::string::String>)>> = ::std::cell::RefCell::new(None);
+        }}
+
+        // Called before each `catch_unwind`. The location of a panic which was caught elsewhere,
+        // or by a previous call, must not be reported for the next one.
+        fn zngur_before_catch_unwind() {{
+            let _ = PANIC_LOCATION.try_with(|p| p.borrow_mut().take());
+            static INSTALL: ::std::sync::Once = ::std::sync::Once::new();
+            INSTALL.call_once(|| {{
+                let previous = ::std::panic::take_hook();
+                ::std::panic::set_hook(Box::new(move |info| {{
+                    let (file, line) = info
+                        .location()
+                        .map(|l| (l.file().to_owned(), l.line()))
+                        .unwrap_or_default();
+                    let backtrace = ::std::backtrace::Backtrace::capture();
+                    let backtrace = (backtrace.status() == ::std::backtrace::BacktraceStatus::Captured)
+                        .then(|| backtrace.to_string());
+                    let _ = PANIC_LOCATION.try_with(|p| *p.borrow_mut() = Some((file, line, backtrace)));
+                    previous(info);
+                }}));
+            }});
+        }}
+
+        fn zngur_set_panic(payload: Box<dyn ::std::any::Any + Send>) {{
+            let message = if let Some(s) = payload.downcast_ref::<&str>() {{
+                s.to_string()
+            }} else if let Some(s) = payload.downcast_ref::<::std::string::String>() {{
+                s.clone()
+            }} else {{
+                "Box<dyn Any>".to_owned()
+            }};
+            let (file, line, backtrace) = PANIC_LOCATION.with(|p| p.borrow_mut().take()).unwrap_or_default();
+            PANIC_PAYLOAD.with(|p| {{
+                *p.borrow_mut() = Some(ZngurPanic {{
+                    message,
+                    file,
+                    line,
+                    backtrace,
+                }})
+            }});
+        }}
+
         #[allow(non_snake_case)]
         #[unsafe(no_mangle)]
         pub fn {detect_panic}() -> u8 {{
-            PANIC_PAYLOAD.with(|p| {{
-                let pp = p.take();
-                let r = if pp.is_some() {{ 1 }} else {{ 0 }};
-                p.set(pp);
-                r
-            }})
+            PANIC_PAYLOAD.with(|p| p.borrow().is_some() as u8)
         }}
 
         #[allow(non_snake_case)]
         #[unsafe(no_mangle)]
         pub fn {take_panic}() {{
             PANIC_PAYLOAD.with(|p| {{
-                p.take();
+                p.borrow_mut().take();
+            }})
+        }}
+
+        #[allow(non_snake_case)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn {panic_payload}(
+            message: *mut *const u8,
+            message_len: *mut usize,
+            file: *mut *const u8,
+            file_len: *mut usize,
+            line: *mut u32,
+            backtrace: *mut *const u8,
+            backtrace_len: *mut usize,
+        ) -> u8 {{
+            PANIC_PAYLOAD.with(|p| unsafe {{
+                let p = p.borrow();
+                let p = p.as_ref().unwrap();
+                *message = p.message.as_ptr();
+                *message_len = p.message.len();
+                *file = p.file.as_ptr();
+                *file_len = p.file.len();
+                *line = p.line;
+                let Some(b) = &p.backtrace else {{
+                    return 0;
+                }};
+                *backtrace = b.as_ptr();
+                *backtrace_len = b.len();
+                1
             }})
         }}
         "#,
@@ -826,6 +1413,7 @@ pub extern "C" fn {debug_print}(v: *mut u8) {{
         PanicToExceptionSymbols {
             detect_panic,
             take_panic,
+            panic_payload,
         }
     }
 
@@ -833,13 +1421,11 @@ pub extern "C" fn {debug_print}(v: *mut u8) {{
         if !self.panic_to_exception {
             f(self);
         } else {
+            wln!(self, "zngur_before_catch_unwind();");
             wln!(self, "let e = ::std::panic::catch_unwind(|| {{");
             f(self);
             wln!(self, "}});");
-            wln!(
-                self,
-                "if let Err(_) = e {{ PANIC_PAYLOAD.with(|p| p.set(Some(()))) }}"
-            );
+            wln!(self, "if let Err(e) = e {{ zngur_set_panic(e) }}");
         }
     }
 
@@ -847,41 +1433,86 @@ pub extern "C" fn {debug_print}(v: *mut u8) {{
         &mut self,
         ty: &RustType,
         layout: LayoutPolicy,
+        allocator: &HeapAllocator,
     ) -> CppLayoutPolicy {
         match layout {
             LayoutPolicy::StackAllocated { size, align } => {
                 CppLayoutPolicy::StackAllocated { size, align }
             }
-            LayoutPolicy::HeapAllocated => {
+            LayoutPolicy::HeapAllocated { inline_size } => {
                 let size_fn = self.mangle_name(&format!("{ty}_size_fn"));
-                let alloc_fn = self.mangle_name(&format!("{ty}_alloc_fn"));
-                let free_fn = self.mangle_name(&format!("{ty}_free_fn"));
+                let align_fn = self.mangle_name(&format!("{ty}_align_fn"));
                 wln!(
                     self,
                     r#"
                 #[allow(non_snake_case)]
                 #[unsafe(no_mangle)]
-                pub fn {size_fn}() -> usize {{
+                pub extern "C" fn {size_fn}() -> usize {{
                     ::std::mem::size_of::<{ty}>()
                 }}
-        
+
                 #[allow(non_snake_case)]
                 #[unsafe(no_mangle)]
-                pub fn {alloc_fn}() -> *mut u8 {{
-                    unsafe {{ ::std::alloc::alloc(::std::alloc::Layout::new::<{ty}>()) }}
+                pub extern "C" fn {align_fn}() -> usize {{
+                    ::std::mem::align_of::<{ty}>()
+                }}
+                "#
+                );
+                let allocator = match allocator {
+                    HeapAllocator::Cpp { alloc, free } => CppHeapAllocator::Cpp {
+                        alloc: alloc.clone(),
+                        free: free.clone(),
+                    },
+                    HeapAllocator::Global | HeapAllocator::Rust(_) => {
+                        let (alloc, dealloc) = match allocator {
+                            HeapAllocator::Rust(path) => (
+                                format!("::std::alloc::GlobalAlloc::alloc(&{path}, layout)"),
+                                format!("::std::alloc::GlobalAlloc::dealloc(&{path}, p, layout)"),
+                            ),
+                            _ => (
+                                "::std::alloc::alloc(layout)".to_owned(),
+                                "::std::alloc::dealloc(p, layout)".to_owned(),
+                            ),
+                        };
+                        let alloc_fn = self.mangle_name(&format!("{ty}_alloc_fn"));
+                        let free_fn = self.mangle_name(&format!("{ty}_free_fn"));
+                        // Zero sized types never touch the allocator, since allocating
+                        // a zero sized layout is undefined behavior.
+                        wln!(
+                            self,
+                            r#"
+                #[allow(non_snake_case)]
+                #[unsafe(no_mangle)]
+                pub extern "C" fn {alloc_fn}() -> *mut u8 {{
+                    let layout = ::std::alloc::Layout::new::<{ty}>();
+                    if layout.size() == 0 {{
+                        return ::std::ptr::NonNull::<{ty}>::dangling().as_ptr() as *mut u8;
+                    }}
+                    let p = unsafe {{ {alloc} }};
+                    if p.is_null() {{
+                        ::std::alloc::handle_alloc_error(layout);
+                    }}
+                    p
                 }}
 
                 #[allow(non_snake_case)]
                 #[unsafe(no_mangle)]
-                pub fn {free_fn}(p: *mut u8) {{
-                    unsafe {{ ::std::alloc::dealloc(p, ::std::alloc::Layout::new::<{ty}>()) }}
+                pub extern "C" fn {free_fn}(p: *mut u8) {{
+                    let layout = ::std::alloc::Layout::new::<{ty}>();
+                    if layout.size() != 0 {{
+                        unsafe {{ {dealloc} }}
+                    }}
                 }}
                 "#
-                );
+                        );
+                        CppHeapAllocator::Rust { alloc_fn, free_fn }
+                    }
+                };
                 CppLayoutPolicy::HeapAllocated {
                     size_fn,
-                    alloc_fn,
-                    free_fn,
+                    align_fn,
+                    inline_size,
+                    allocator,
                 }
             }
             LayoutPolicy::OnlyByRef => CppLayoutPolicy::OnlyByRef,
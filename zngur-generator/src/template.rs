@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 
 use crate::cpp::{
-    CppExportedFnDefinition, CppExportedImplDefinition, CppFnDefinition, CppFnSig, CppLayoutPolicy,
-    CppTraitDefinition, CppTypeDefinition, PanicToExceptionSymbols, cpp_handle_field_name,
+    CppEnum, CppExportedFnDefinition, CppExportedImplDefinition, CppFnCall, CppFnDefinition,
+    CppFnSig, CppHeapAllocator, CppLayoutPolicy, CppResultWrapper, CppStdBridge,
+    CppTraitDefinition, CppType, CppTypeDefinition, PanicToExceptionSymbols,
 };
 use sailfish::Template;
 use zngur_def::*;
@@ -44,18 +45,13 @@ macro_rules! splat {
 }
 
 #[derive(Template)]
-#[template(path = "cpp_header.sptl", escape = false)]
-pub(crate) struct CppHeaderTemplate<'a> {
+#[template(path = "cpp_runtime.sptl", escape = false)]
+pub(crate) struct CppRuntimeTemplate<'a> {
     pub(crate) panic_to_exception: &'a Option<PanicToExceptionSymbols>,
     pub(crate) additional_includes: &'a String,
-    pub(crate) fn_deps: &'a Vec<CppFnDefinition>,
-    pub(crate) type_defs: &'a Vec<CppTypeDefinition>,
-    pub(crate) trait_defs: &'a HashMap<RustTrait, CppTraitDefinition>,
-    pub(crate) exported_impls: &'a Vec<CppExportedImplDefinition>,
-    pub(crate) exported_fn_defs: &'a Vec<CppExportedFnDefinition>,
 }
 
-impl<'a> CppHeaderTemplate<'a> {
+impl CppRuntimeTemplate<'_> {
     // TODO: Docs - what do these represent? When will we change this list?
     fn builtin_types(&self) -> Vec<String> {
         let builtins = [8, 16, 32, 64]
@@ -76,17 +72,140 @@ impl<'a> CppHeaderTemplate<'a> {
             ])
             .collect()
     }
+}
+
+/// The parts of the items in a header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HeaderPart {
+    All,
+    /// The C++ classes, with the declarations of their methods.
+    Declarations,
+    /// The inline definitions of the methods and functions.
+    Definitions,
+}
+
+/// The items, which follow the runtime. It is used both for the single header, with all of the
+/// items, and for the headers of a module, with the items of that module.
+#[derive(Template)]
+#[template(path = "cpp_header.sptl", escape = false)]
+pub(crate) struct CppHeaderTemplate<'a> {
+    pub(crate) part: HeaderPart,
+    /// Headers included before the items.
+    pub(crate) includes: Vec<String>,
+    pub(crate) panic_to_exception: &'a Option<PanicToExceptionSymbols>,
+    pub(crate) fn_deps: Vec<&'a CppFnDefinition>,
+    pub(crate) type_defs: Vec<&'a CppTypeDefinition>,
+    pub(crate) forward_decls: Vec<&'a CppType>,
+    pub(crate) trait_defs: &'a HashMap<RustTrait, CppTraitDefinition>,
+    /// The non-`Fn` traits to define, with every trait after its supertraits.
+    pub(crate) normal_trait_defs: Vec<&'a CppTraitDefinition>,
+    pub(crate) exported_impls: Vec<&'a CppExportedImplDefinition>,
+    pub(crate) exported_fn_defs: Vec<&'a CppExportedFnDefinition>,
+}
+
+impl<'a> CppHeaderTemplate<'a> {
+    /// Returns the base class list of the C++ abstract class of a trait.
+    fn trait_bases(&self, supertraits: &[RustTrait]) -> String {
+        if supertraits.is_empty() {
+            return String::new();
+        }
+        let bases = supertraits.iter().map(|x| match self.trait_defs.get(x) {
+            Some(CppTraitDefinition::Normal { as_ty, .. }) => format!("public virtual {as_ty}"),
+            _ => panic!("Supertrait `{x}` is not declared. Declare it with a `trait` block."),
+        });
+        format!(": {}", bases.format(", "))
+    }
+
+    /// Returns the qualifier of the `operator()` of an owned closure, which matches the
+    /// receiver of the Rust call: `&self` for `Fn`, `&mut self` for `FnMut` and `self`
+    /// for `FnOnce`.
+    fn fn_call_qualifier(&self, call: &CppFnCall) -> &'static str {
+        match call.kind.as_str() {
+            "FnOnce" => "&&",
+            "FnMut" => "",
+            _ => "const",
+        }
+    }
+
+    /// Returns the return type of `visit` of an enum, which is the result of the functions for
+    /// every variant, or a substitution failure if they return different types.
+    fn visit_result(&self, enum_def: &CppEnum) -> String {
+        let results = enum_def.variants.iter().map(|x| {
+            format!(
+                "decltype(::rust::overloaded(f...)(::std::declval< {}_t >()))",
+                x.name
+            )
+        });
+        format!(
+            "typename ::std::enable_if< ::rust::__zngur_internal_same_types< {} >::value, {} >::type",
+            results.clone().join(", "),
+            results.clone().next().unwrap(),
+        )
+    }
+
+    /// Functions which throw the `Err` of their `Result` as a C++ exception can't be `noexcept`.
+    fn noexcept(&self, result: &Option<CppResultWrapper>) -> &'static str {
+        match result {
+            Some(r) if r.policy == ResultPolicy::AsException => "",
+            _ => "noexcept",
+        }
+    }
+
+    /// Returns the statements which return `o` from a function, unpacking it first if
+    /// it is a `Result` with a result policy.
+    fn return_output(&self, result: &Option<CppResultWrapper>) -> String {
+        let Some(CppResultWrapper {
+            policy,
+            result_ty: _,
+            ok,
+            err,
+            symbols,
+        }) = result
+        else {
+            return "return o;".to_owned();
+        };
+        let (on_ok, on_err) = match policy {
+            ResultPolicy::AsException => (
+                "return v;".to_owned(),
+                format!("throw ::rust::Error< {err} >(::std::move(e));"),
+            ),
+            ResultPolicy::AsExpected => (
+                format!("return ::rust::Expected< {ok}, {err} >(::std::move(v));"),
+                format!(
+                    "return ::rust::Expected< {ok}, {err} >(::rust::Unexpected< {err} >(::std::move(e)));"
+                ),
+            ),
+        };
+        format!(
+            r#"
+            if ({is_ok}(::rust::__zngur_internal_data_ptr(o))) {{
+                {ok} v{{}};
+                {unwrap}(::rust::__zngur_internal_data_ptr(o), ::rust::__zngur_internal_data_ptr(v));
+                ::rust::__zngur_internal_assume_deinit(o);
+                ::rust::__zngur_internal_assume_init(v);
+                {on_ok}
+            }}
+            {err} e{{}};
+            {unwrap_err}(::rust::__zngur_internal_data_ptr(o), ::rust::__zngur_internal_data_ptr(e));
+            ::rust::__zngur_internal_assume_deinit(o);
+            ::rust::__zngur_internal_assume_init(e);
+            {on_err}
+            "#,
+            is_ok = symbols.is_ok,
+            unwrap = symbols.unwrap,
+            unwrap_err = symbols.unwrap_err,
+        )
+    }
 
     fn panic_handler(&self) -> String {
         if let Some(symbols) = &self.panic_to_exception {
             format!(
                 r#"
             if ({}()) {{
-                {}();
-                throw ::rust::Panic{{}};
+                ::rust::__zngur_throw_panic();
             }}
             "#,
-                symbols.detect_panic, symbols.take_panic,
+                symbols.detect_panic,
             )
         } else {
             "".to_owned()
@@ -102,3 +221,42 @@ pub(crate) struct CppSourceTemplate<'a> {
     pub(crate) exported_fn_defs: &'a Vec<CppExportedFnDefinition>,
     pub(crate) exported_impls: &'a Vec<CppExportedImplDefinition>,
 }
+
+impl CppSourceTemplate<'_> {
+    /// Returns the signature of the `extern "C"` shim of a C++ function, from its name on.
+    fn shim_signature(&self, sig: &CppFnSig, exception_policy: CppExceptionPolicy) -> String {
+        let inputs = (0..sig.inputs.len())
+            .map(|n| format!("uint8_t* i{n}, "))
+            .join("");
+        match exception_policy {
+            CppExceptionPolicy::Abort => format!("void {}({inputs}uint8_t* o)", sig.rust_link_name),
+            CppExceptionPolicy::Panic | CppExceptionPolicy::Result => format!(
+                "uint8_t {}({inputs}uint8_t* o, const char** e, size_t* e_len)",
+                sig.rust_link_name
+            ),
+        }
+    }
+
+    /// Returns the end of the `try` block around a call to `name`, and the handlers
+    /// which keep C++ exceptions from unwinding into Rust.
+    fn catch_exceptions(&self, name: &str, exception_policy: CppExceptionPolicy) -> String {
+        match exception_policy {
+            CppExceptionPolicy::Abort => format!(
+                r#"
+            }} catch (const ::std::exception& ex) {{
+                ::__zngur_abort_on_cpp_exception("{name}", ex.what());
+            }} catch (...) {{
+                ::__zngur_abort_on_cpp_exception("{name}", "unknown exception");
+            }}"#
+            ),
+            CppExceptionPolicy::Panic | CppExceptionPolicy::Result => r#"
+                return 0;
+            } catch (const ::std::exception& ex) {
+                return ::__zngur_store_cpp_exception(ex.what(), e, e_len);
+            } catch (...) {
+                return ::__zngur_store_cpp_exception("unknown exception", e, e_len);
+            }"#
+            .to_owned(),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, ops::Range, path::PathBuf};
+
+use crate::{FileRecord, ImportResolver, ParseContext, ParsedZngFile, SpecItem};
+
+/// A value found at a byte range of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Located<T> {
+    pub file: PathBuf,
+    pub range: Range<usize>,
+    pub value: T,
+}
+
+/// What editors need to know about a .zng file and the files it imports.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    /// The text of every parsed file, by its path.
+    pub sources: HashMap<PathBuf, String>,
+    /// The errors, which [`ParsedZngFile::parse`] would report before exiting.
+    pub diagnostics: Vec<Located<String>>,
+    /// The declared types, traits, functions, and the items of the types.
+    pub declarations: Vec<Located<SpecItem>>,
+    /// The Rust paths written in the files, resolved to full paths through the `use` aliases and
+    /// the `mod` blocks around them.
+    pub references: Vec<Located<Vec<String>>>,
+    /// The paths of the `import` items, as written.
+    pub imports: Vec<Located<PathBuf>>,
+}
+
+impl Analysis {
+    fn new(files: Vec<FileRecord>) -> Self {
+        let mut result = Analysis::default();
+        for file in files {
+            let located = |span: crate::Span| (file.path.clone(), span.into_range());
+            for (span, value) in file.diagnostics {
+                let (file, range) = located(span);
+                result.diagnostics.push(Located { file, range, value });
+            }
+            for (value, span) in file.locations {
+                let (file, range) = located(span);
+                result.declarations.push(Located { file, range, value });
+            }
+            for (span, value) in file.references {
+                let (file, range) = located(span);
+                result.references.push(Located { file, range, value });
+            }
+            for (span, value) in file.imports {
+                let (file, range) = located(span);
+                result.imports.push(Located { file, range, value });
+            }
+            result.sources.insert(file.path, file.text);
+        }
+        result
+    }
+}
+
+impl ParsedZngFile<'_> {
+    /// Parses `text` as the file at `path`, with its imports, and records the errors instead of
+    /// reporting them and exiting.
+    pub fn analyze(path: PathBuf, text: &str, resolver: &impl ImportResolver) -> Analysis {
+        let mut files = vec![];
+        let mut ctx = ParseContext::new(path, text);
+        Self::parse_into(&mut Default::default(), &mut ctx, resolver, &mut files);
+        Analysis::new(files)
+    }
+}
@@ -0,0 +1,437 @@
+//! Formatting of .zng files.
+//!
+//! The formatter works on the tokens of the file rather than on the parsed items, so that it can
+//! keep the comments, which the lexer drops, in the gaps between the tokens. Every item is printed
+//! on a single line with an indentation of four spaces per block. Comments which are inside an
+//! item are moved above it.
+
+use chumsky::prelude::*;
+
+use crate::{ParseContext, ParsedZngFile, ParserInput, Span, Token, file_parser, lexer};
+
+const INDENT: &str = "    ";
+/// The width above which function signatures are split into several lines.
+const MAX_WIDTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trivia<'a> {
+    Comment(&'a str),
+    Blank,
+}
+
+/// The comments and blank lines between two tokens.
+#[derive(Debug, Default)]
+struct Gap<'a> {
+    /// A comment on the same line as the previous token.
+    same_line: Option<&'a str>,
+    /// The comments and blank lines on the lines between the two tokens.
+    lines: Vec<Trivia<'a>>,
+}
+
+impl<'a> Gap<'a> {
+    fn new(gap: &'a str, at_start: bool, at_end: bool) -> Self {
+        let mut result = Gap::default();
+        let segments = gap.split('\n').collect::<Vec<_>>();
+        for (i, segment) in segments.iter().enumerate() {
+            let segment = segment.trim();
+            // The first and the last segment are on the lines of the tokens around the gap.
+            let full_line = (i > 0 || at_start) && (i + 1 < segments.len() || at_end);
+            if !full_line {
+                if i == 0 && segment.starts_with("//") {
+                    result.same_line = Some(segment);
+                }
+                continue;
+            }
+            if segment.starts_with("//") {
+                result.lines.push(Trivia::Comment(segment));
+            } else if result.lines.last() != Some(&Trivia::Blank) {
+                result.lines.push(Trivia::Blank);
+            }
+        }
+        result
+    }
+}
+
+/// An item, or a line in a block.
+#[derive(Debug)]
+struct Node<'a> {
+    leading: Vec<Trivia<'a>>,
+    tokens: Vec<Token<'a>>,
+    /// The comment after the item, or after the opening brace of its block.
+    trailing: Option<&'a str>,
+    block: Option<Block<'a>>,
+}
+
+#[derive(Debug, Default)]
+struct Block<'a> {
+    children: Vec<Node<'a>>,
+    /// The comments before the closing brace.
+    closing: Vec<Trivia<'a>>,
+    /// The comment after the closing brace.
+    trailing: Option<&'a str>,
+}
+
+struct Builder<'a> {
+    tokens: Vec<Token<'a>>,
+    gaps: Vec<Gap<'a>>,
+    pos: usize,
+}
+
+impl<'a> Builder<'a> {
+    fn new(text: &'a str, tokens: Vec<(Token<'a>, Span)>) -> Self {
+        let mut gaps = vec![];
+        let mut prev_end = 0;
+        for (i, (_, span)) in tokens.iter().enumerate() {
+            gaps.push(Gap::new(&text[prev_end..span.start], i == 0, false));
+            prev_end = span.end;
+        }
+        gaps.push(Gap::new(&text[prev_end..], tokens.is_empty(), true));
+        Self {
+            tokens: tokens.into_iter().map(|x| x.0).collect(),
+            gaps,
+            pos: 0,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    /// Consumes a token, and moves the comments before it to `comments`.
+    fn bump(&mut self, comments: &mut Vec<Trivia<'a>>) -> Token<'a> {
+        let gap = std::mem::take(&mut self.gaps[self.pos]);
+        comments.extend(gap.same_line.map(Trivia::Comment));
+        comments.extend(gap.lines.into_iter().filter(|x| *x != Trivia::Blank));
+        self.pos += 1;
+        self.tokens[self.pos - 1].clone()
+    }
+
+    fn take_same_line(&mut self) -> Option<&'a str> {
+        self.gaps[self.pos].same_line.take()
+    }
+
+    fn block(&mut self, nested: bool) -> Block<'a> {
+        let mut block = Block::default();
+        loop {
+            if self.pos == self.tokens.len() || (nested && self.peek(0) == Some(&Token::BraceClose))
+            {
+                block.closing = std::mem::take(&mut self.gaps[self.pos].lines);
+                return block;
+            }
+            block.children.push(self.node());
+        }
+    }
+
+    /// The number of tokens of a directive which isn't terminated by a semicolon, if the next
+    /// item is one.
+    fn directive_len(&self) -> Option<usize> {
+        if self.peek(0) != Some(&Token::Sharp) {
+            return None;
+        }
+        match self.peek(1)? {
+            Token::Ident("cpp_additional_includes") => Some(3),
+            Token::Ident("convert_panic_to_exception") => Some(2),
+            Token::Ident("heap_allocator") => match self.peek(2)? {
+                Token::Ident("cpp") => Some(5),
+                _ => Some(4),
+            },
+            _ => None,
+        }
+    }
+
+    fn node(&mut self) -> Node<'a> {
+        let mut leading = std::mem::take(&mut self.gaps[self.pos].lines);
+        let directive_len = self.directive_len();
+        let mut tokens = vec![self.bump(&mut vec![])];
+        let has_block = matches!(
+            tokens[0],
+            Token::KwType | Token::KwTrait | Token::KwExtern | Token::KwMod | Token::KwImpl
+        );
+        if let Some(len) = directive_len {
+            for _ in 1..len {
+                tokens.push(self.bump(&mut leading));
+            }
+        } else if has_block {
+            while self.peek(0) != Some(&Token::BraceOpen) {
+                tokens.push(self.bump(&mut leading));
+            }
+            self.bump(&mut leading);
+            let trailing = self.take_same_line();
+            let mut block = self.block(true);
+            self.bump(&mut vec![]);
+            block.trailing = self.take_same_line();
+            return Node {
+                leading,
+                tokens,
+                trailing,
+                block: Some(block),
+            };
+        } else {
+            let mut depth = 0;
+            while depth > 0 || tokens.last() != Some(&Token::Semicolon) {
+                match tokens.last() {
+                    Some(Token::BraceOpen) => depth += 1,
+                    Some(Token::BraceClose) => depth -= 1,
+                    _ => (),
+                }
+                tokens.push(self.bump(&mut leading));
+            }
+        }
+        Node {
+            leading,
+            tokens,
+            trailing: self.take_same_line(),
+            block: None,
+        }
+    }
+}
+
+/// Whether a space is printed between two tokens of an item. `field_name` is set before the
+/// parenthesis of `field name (offset = X, type = Y)`, which is separated by a space.
+fn space_between(prev: &Token<'_>, next: &Token<'_>, field_name: bool) -> bool {
+    use Token::*;
+    match (prev, next) {
+        (
+            _,
+            Comma | Semicolon | Colon | Dot | ParenClose | BracketClose | AngleOpen | AngleClose,
+        ) => false,
+        (Ident(_) | Number(_), ParenOpen) => field_name,
+        (AngleClose, ParenOpen) => false,
+        (Ident(_) | KwCrate, ColonColon) => false,
+        (BraceOpen, BraceClose) => false,
+        (
+            ParenOpen | BracketOpen | AngleOpen | And | Star | Sharp | Question | Dot | ColonColon,
+            _,
+        ) => false,
+        _ => true,
+    }
+}
+
+fn render_tokens(tokens: &[Token<'_>]) -> String {
+    let mut field_name = tokens.first() == Some(&Token::Ident("field"));
+    let mut out = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && space_between(&tokens[i - 1], token, field_name) {
+            out.push(' ');
+        }
+        if matches!(token, Token::Colon | Token::ParenOpen) {
+            field_name = false;
+        }
+        out += &token.to_string();
+    }
+    out
+}
+
+/// Renders an item as lines, without the indentation of the item. The attributes of a function
+/// are put on their own lines, and long signatures are split before `->` and `use`.
+fn render_lines(tokens: &[Token<'_>], indent: usize) -> Vec<String> {
+    let attribute_len = match tokens {
+        [Token::Sharp, Token::Ident("cpp_exception"), ..] => 5,
+        [
+            Token::Sharp,
+            Token::Ident("result_as_exception" | "result_as_expected"),
+            ..,
+        ] => 2,
+        _ => 0,
+    };
+    if attribute_len > 0 && tokens.get(attribute_len) == Some(&Token::KwFn) {
+        let mut lines = vec![render_tokens(&tokens[..attribute_len])];
+        lines.extend(render_lines(&tokens[attribute_len..], indent));
+        return lines;
+    }
+    let line = render_tokens(tokens);
+    if tokens.first() != Some(&Token::KwFn) || indent * INDENT.len() + line.len() <= MAX_WIDTH {
+        return vec![line];
+    }
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::ParenOpen | Token::AngleOpen | Token::BracketOpen => depth += 1,
+            Token::ParenClose | Token::AngleClose | Token::BracketClose => depth -= 1,
+            Token::Arrow | Token::KwUse if depth == 0 => {
+                lines.push(render_tokens(&tokens[start..i]));
+                start = i;
+            }
+            _ => (),
+        }
+    }
+    lines.push(render_tokens(&tokens[start..]));
+    for line in &mut lines[1..] {
+        line.insert_str(0, INDENT);
+    }
+    lines
+}
+
+fn starts_with(node: &Node<'_>, tokens: &[Token<'_>]) -> bool {
+    node.tokens.starts_with(tokens)
+}
+
+/// Sorts the runs of `use` aliases which are not separated by blank lines. The comments directly
+/// above an alias move with it, but the ones which start the file or are separated from the run by
+/// a blank line stay in place.
+fn sort_aliases(children: &mut [Node<'_>], is_root: bool) {
+    let mut start = 0;
+    while start < children.len() {
+        if !starts_with(&children[start], &[Token::KwUse]) {
+            start += 1;
+            continue;
+        }
+        let mut end = start + 1;
+        while end < children.len()
+            && starts_with(&children[end], &[Token::KwUse])
+            && !children[end].leading.contains(&Trivia::Blank)
+        {
+            end += 1;
+        }
+        let run = &mut children[start..end];
+        let detached = if is_root && start == 0 {
+            run[0].leading.len()
+        } else {
+            run[0]
+                .leading
+                .iter()
+                .rposition(|x| *x == Trivia::Blank)
+                .map_or(0, |x| x + 1)
+        };
+        let detached = run[0].leading.drain(..detached).collect::<Vec<_>>();
+        run.sort_by_cached_key(|x| render_tokens(&x.tokens));
+        run[0].leading.splice(0..0, detached);
+        start = end;
+    }
+}
+
+/// Moves the layout policy and the `wellknown_traits` of a type block to its top.
+fn sort_type_items(children: &mut [Node<'_>]) {
+    let rank = |node: &Node<'_>| match node.tokens.as_slice() {
+        [
+            Token::Sharp,
+            Token::Ident("layout" | "heap_allocated" | "only_by_ref"),
+            ..,
+        ] => 0,
+        [Token::Ident("wellknown_traits"), ..] => 1,
+        _ => 2,
+    };
+    children.sort_by_key(rank);
+    for node in children.iter_mut().filter(|x| rank(x) < 2) {
+        node.leading.retain(|x| *x != Trivia::Blank);
+    }
+}
+
+fn sort(children: &mut [Node<'_>], is_root: bool, is_type: bool) {
+    sort_aliases(children, is_root);
+    if is_type {
+        sort_type_items(children);
+    }
+    for node in children {
+        if let Some(block) = &mut node.block {
+            sort(&mut block.children, false, node.tokens[0] == Token::KwType);
+        }
+    }
+}
+
+fn print_trivia(out: &mut String, trivia: &[Trivia<'_>], indent: usize) {
+    for x in trivia {
+        match x {
+            Trivia::Comment(comment) => {
+                *out += &INDENT.repeat(indent);
+                *out += comment;
+            }
+            Trivia::Blank => (),
+        }
+        out.push('\n');
+    }
+}
+
+fn print_trailing(out: &mut String, trailing: Option<&str>) {
+    if let Some(comment) = trailing {
+        *out += " ";
+        *out += comment;
+    }
+    out.push('\n');
+}
+
+fn print_block(out: &mut String, block: &Block<'_>, indent: usize) {
+    for (i, node) in block.children.iter().enumerate() {
+        let mut leading = node.leading.as_slice();
+        if i == 0 {
+            leading = trim_blank_start(leading);
+        }
+        print_trivia(out, leading, indent);
+        let Some(inner) = &node.block else {
+            let lines = render_lines(&node.tokens, indent);
+            for (i, line) in lines.iter().enumerate() {
+                *out += &INDENT.repeat(indent);
+                *out += line;
+                if i + 1 < lines.len() {
+                    out.push('\n');
+                }
+            }
+            print_trailing(out, node.trailing);
+            continue;
+        };
+        *out += &INDENT.repeat(indent);
+        *out += &render_tokens(&node.tokens);
+        let closing = trim_blank_end(&inner.closing);
+        if inner.children.is_empty() && closing.is_empty() && node.trailing.is_none() {
+            *out += " {}";
+        } else {
+            *out += " {";
+            print_trailing(out, node.trailing);
+            print_block(out, inner, indent + 1);
+            print_trivia(out, trim_blank_start(closing), indent + 1);
+            *out += &INDENT.repeat(indent);
+            *out += "}";
+        }
+        print_trailing(out, inner.trailing);
+    }
+}
+
+fn trim_blank_start<'a, 'b>(mut trivia: &'b [Trivia<'a>]) -> &'b [Trivia<'a>] {
+    while let [Trivia::Blank, rest @ ..] = trivia {
+        trivia = rest;
+    }
+    trivia
+}
+
+fn trim_blank_end<'a, 'b>(mut trivia: &'b [Trivia<'a>]) -> &'b [Trivia<'a>] {
+    while let [rest @ .., Trivia::Blank] = trivia {
+        trivia = rest;
+    }
+    trivia
+}
+
+impl ParsedZngFile<'_> {
+    /// Formats the .zng file `text`, keeping its comments. Syntax errors are reported like in
+    /// [`ParsedZngFile::parse`], with `path` as the name of the file.
+    pub fn format(path: std::path::PathBuf, text: &str) -> String {
+        let mut ctx = ParseContext::new(path, text);
+        let (tokens, errs) = lexer().parse(text).into_output_errors();
+        let Some(tokens) = tokens.filter(|_| errs.is_empty()) else {
+            ctx.add_errors(errs.into_iter().map(|e| e.map_token(|c| c.to_string())));
+            ctx.emit_ariadne_errors();
+        };
+        let input: ParserInput<'_> = tokens
+            .as_slice()
+            .map((text.len()..text.len()).into(), Box::new(|(t, s)| (t, s)));
+        let errs = file_parser().parse(input).into_errors();
+        if !errs.is_empty() {
+            ctx.add_errors(errs.into_iter().map(|e| e.map_token(|c| c.to_string())));
+            ctx.emit_ariadne_errors();
+        }
+
+        let mut builder = Builder::new(text, tokens);
+        let mut root = builder.block(false);
+        sort(&mut root.children, true, false);
+        let mut out = String::new();
+        print_block(&mut out, &root, 0);
+        let closing = trim_blank_end(&root.closing);
+        if root.children.is_empty() {
+            print_trivia(&mut out, trim_blank_start(closing), 0);
+        } else {
+            print_trivia(&mut out, closing, 0);
+        }
+        out
+    }
+}
@@ -0,0 +1,108 @@
+//! Parsing of single types, traits, paths and methods written in .zng syntax, for building a
+//! [`zngur_def::ZngurSpec`] without a .zng file. There are no `use` aliases or `mod` blocks
+//! around them, so paths should be written in full, like `::std::vec::Vec<i32>`.
+
+use chumsky::prelude::*;
+use itertools::Itertools;
+use zngur_def::{RustTrait, RustType, ZngurMethodDetails, ZngurMethodReceiver};
+
+use crate::{
+    ParsedTypeMethod, ParsedZngFile, ParserInput, Scope, Span, Token, lexer, path, result_policy,
+    rust_trait, rust_type, type_method,
+};
+
+fn describe<T: std::fmt::Display>(text: &str, errors: Vec<Rich<'_, T, Span>>) -> String {
+    format!(
+        "Invalid `{text}`: {}",
+        errors.iter().map(|e| e.to_string()).join(", ")
+    )
+}
+
+fn lex(text: &str) -> Result<Vec<(Token<'_>, Span)>, String> {
+    lexer()
+        .parse(text)
+        .into_result()
+        .map_err(|e| describe(text, e))
+}
+
+fn input<'a>(text: &str, tokens: &'a [(Token<'a>, Span)]) -> ParserInput<'a> {
+    tokens.map((text.len()..text.len()).into(), Box::new(|(t, s)| (t, s)))
+}
+
+impl ParsedZngFile<'_> {
+    /// Parses a type, like `::std::vec::Vec<i32>`.
+    pub fn parse_type(text: &str) -> Result<RustType, String> {
+        let tokens = lex(text)?;
+        let ty = rust_type()
+            .then_ignore(end())
+            .parse(input(text, &tokens))
+            .into_result()
+            .map_err(|e| describe(text, e))?;
+        Ok(ty.to_zngur(&Scope::new_root(vec![])))
+    }
+
+    /// Parses a trait, like `::std::iter::Iterator<Item = i32>` or `Fn(i32) -> i32`.
+    pub fn parse_trait(text: &str) -> Result<RustTrait, String> {
+        let tokens = lex(text)?;
+        let tr = rust_trait(rust_type())
+            .then_ignore(end())
+            .parse(input(text, &tokens))
+            .into_result()
+            .map_err(|e| describe(text, e))?;
+        Ok(tr.to_zngur(&Scope::new_root(vec![])))
+    }
+
+    /// Parses a path, like `crate::utils`.
+    pub fn parse_path(text: &str) -> Result<Vec<String>, String> {
+        let tokens = lex(text)?;
+        let path = path()
+            .then_ignore(end())
+            .parse(input(text, &tokens))
+            .into_result()
+            .map_err(|e| describe(text, e))?;
+        Ok(Scope::new_root(vec![]).resolve_path(path))
+    }
+
+    /// Parses a method as written in a `type` block, without the semicolon, like
+    /// `#result_as_exception fn get(&self, usize) -> ::std::result::Result<i32, ()>` or
+    /// `fn len(&self) -> usize deref ::std::string::String`.
+    pub fn parse_method(text: &str) -> Result<ZngurMethodDetails, String> {
+        let tokens = lex(text)?;
+        let (
+            result_policy,
+            ParsedTypeMethod {
+                data,
+                use_path,
+                deref,
+            },
+        ) = result_policy()
+            .or_not()
+            .then(type_method())
+            .then_ignore(end())
+            .parse(input(text, &tokens))
+            .into_result()
+            .map_err(|e| describe(text, e))?;
+        let scope = Scope::new_root(vec![]);
+        let deref = match (deref, data.receiver) {
+            (None, _) => None,
+            (Some(ty), ZngurMethodReceiver::Ref(mutability)) => {
+                Some((ty.to_zngur(&scope), mutability))
+            }
+            (Some(_), _) => {
+                return Err(format!("Invalid `{text}`: Deref needs reference receiver"));
+            }
+        };
+        let data = data.to_zngur(&scope);
+        if result_policy.is_some() && data.output.as_result().is_none() {
+            return Err(format!(
+                "Invalid `{text}`: Result policy needs a method returning `Result`"
+            ));
+        }
+        Ok(ZngurMethodDetails {
+            data,
+            use_path: use_path.map(|x| scope.resolve_path(x)),
+            deref,
+            result_policy,
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Display, path::Component};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, path::Component, rc::Rc};
 
 #[cfg(not(test))]
 use std::process::exit;
@@ -8,17 +8,24 @@ use chumsky::prelude::*;
 use itertools::{Either, Itertools};
 
 use zngur_def::{
-    AdditionalIncludes, ConvertPanicToException, CppRef, CppValue, Import, LayoutPolicy, Merge,
-    MergeFailure, Mutability, PrimitiveRustType, RustPathAndGenerics, RustTrait, RustType,
-    ZngurConstructor, ZngurExternCppFn, ZngurExternCppImpl, ZngurField, ZngurFn, ZngurMethod,
-    ZngurMethodDetails, ZngurMethodReceiver, ZngurSpec, ZngurTrait, ZngurType, ZngurWellknownTrait,
+    AdditionalIncludes, ConvertPanicToException, CppExceptionPolicy, CppRef, CppValue,
+    HeapAllocator, Import, LayoutPolicy, Merge, MergeFailure, Mutability, PrimitiveRustType,
+    ResultPolicy, RustPathAndGenerics, RustTrait, RustType, ZngurConstructor, ZngurExternCppFn,
+    ZngurExternCppImpl, ZngurExternCppMethod, ZngurField, ZngurFn, ZngurMethod, ZngurMethodDetails,
+    ZngurMethodReceiver, ZngurSpec, ZngurTrait, ZngurType, ZngurWellknownTrait,
 };
 
 pub type Span = SimpleSpan<usize>;
 
+mod analysis;
+mod fmt;
+mod fragment;
+mod printer;
 #[cfg(test)]
 mod tests;
 
+pub use analysis::{Analysis, Located};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Spanned<T> {
     inner: T,
@@ -59,10 +66,15 @@ struct ParsedPath<'a> {
     span: Span,
 }
 
+/// The paths resolved while parsing, with the spans they were written at.
+type References = Rc<RefCell<Vec<(Span, Vec<String>)>>>;
+
 #[derive(Debug, Clone)]
 struct Scope<'a> {
     aliases: Vec<ParsedAlias<'a>>,
     base: Vec<String>,
+    /// The paths resolved in this scope and its sub scopes.
+    references: References,
 }
 
 impl<'a> Scope<'a> {
@@ -71,13 +83,15 @@ impl<'a> Scope<'a> {
         Scope {
             aliases,
             base: Vec::new(),
+            references: Rc::default(),
         }
     }
 
     /// Resolve a path according to the current scope.
     fn resolve_path(&self, path: ParsedPath<'a>) -> Vec<String> {
+        let span = path.span;
         // Check to see if the path refers to an alias:
-        if let Some(expanded_alias) = self
+        let resolved = if let Some(expanded_alias) = self
             .aliases
             .iter()
             .find_map(|alias| alias.expand(&path, &self.base))
@@ -85,7 +99,9 @@ impl<'a> Scope<'a> {
             expanded_alias
         } else {
             path.to_zngur(&self.base)
-        }
+        };
+        self.references.borrow_mut().push((span, resolved.clone()));
+        resolved
     }
 
     /// Create a fully-qualified path relative to this scope's base path.
@@ -98,13 +114,16 @@ impl<'a> Scope<'a> {
     }
 
     fn sub_scope(&self, new_aliases: &[ParsedAlias<'a>], nested_path: ParsedPath<'a>) -> Scope<'_> {
+        let span = nested_path.span;
         let base = nested_path.to_zngur(&self.base);
+        self.references.borrow_mut().push((span, base.clone()));
         let mut mod_aliases = new_aliases.to_vec();
         mod_aliases.extend_from_slice(&self.aliases);
 
         Scope {
             aliases: mod_aliases,
             base,
+            references: self.references.clone(),
         }
     }
 }
@@ -189,6 +208,7 @@ struct ParsedImportPath {
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ParsedItem<'a> {
     ConvertPanicToException(Span),
+    HeapAllocator(Span, HeapAllocator),
     CppAdditionalInclude(&'a str),
     Mod {
         path: ParsedPath<'a>,
@@ -200,9 +220,10 @@ enum ParsedItem<'a> {
     },
     Trait {
         tr: Spanned<ParsedRustTrait<'a>>,
-        methods: Vec<ParsedMethod<'a>>,
+        supertraits: Vec<Spanned<ParsedRustTrait<'a>>>,
+        methods: Vec<Spanned<ParsedMethod<'a>>>,
     },
-    Fn(Spanned<ParsedMethod<'a>>),
+    Fn(Spanned<ParsedMethod<'a>>, Option<ResultPolicy>),
     ExternCpp(Vec<ParsedExternCppItem<'a>>),
     Alias(ParsedAlias<'a>),
     Import(ParsedImportPath),
@@ -211,6 +232,7 @@ enum ParsedItem<'a> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ProcessedItem<'a> {
     ConvertPanicToException(Span),
+    HeapAllocator(Span, HeapAllocator),
     CppAdditionalInclude(&'a str),
     Mod {
         path: ParsedPath<'a>,
@@ -223,23 +245,72 @@ enum ProcessedItem<'a> {
     },
     Trait {
         tr: Spanned<ParsedRustTrait<'a>>,
-        methods: Vec<ParsedMethod<'a>>,
+        supertraits: Vec<Spanned<ParsedRustTrait<'a>>>,
+        methods: Vec<Spanned<ParsedMethod<'a>>>,
     },
-    Fn(Spanned<ParsedMethod<'a>>),
+    Fn(Spanned<ParsedMethod<'a>>, Option<ResultPolicy>),
     ExternCpp(Vec<ParsedExternCppItem<'a>>),
     Import(ParsedImportPath),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ParsedExternCppItem<'a> {
-    Function(Spanned<ParsedMethod<'a>>),
+    Function(Spanned<ParsedExternCppMethod<'a>>),
     Impl {
         tr: Option<ParsedRustTrait<'a>>,
         ty: Spanned<ParsedRustType<'a>>,
-        methods: Vec<ParsedMethod<'a>>,
+        methods: Vec<Spanned<ParsedExternCppMethod<'a>>>,
     },
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParsedExternCppMethod<'a> {
+    data: ParsedMethod<'a>,
+    exception_policy: CppExceptionPolicy,
+    /// The path of the `Result` in an output written as `Result<T, ZngurCppException>`, in which
+    /// case the output of `data` is `T`.
+    cpp_exception_result: Option<ParsedPath<'a>>,
+}
+
+impl ParsedExternCppMethod<'_> {
+    fn into_zngur(
+        self,
+        span: Span,
+        scope: &Scope<'_>,
+        ctx: &mut ParseContext,
+    ) -> ZngurExternCppMethod {
+        match self.cpp_exception_result {
+            Some(path) => {
+                let path = scope.resolve_path(path);
+                if !matches!(
+                    path.iter().map(|x| x.as_str()).collect_vec().as_slice(),
+                    ["std" | "core", "result", "Result"]
+                ) {
+                    ctx.add_error_str(CPP_EXCEPTION_MISUSE, span);
+                } else if self.exception_policy != CppExceptionPolicy::Result {
+                    ctx.add_error_str(
+                        "A function returning `Result<T, ZngurCppException>` needs `#cpp_exception(result)`",
+                        span,
+                    );
+                }
+            }
+            None if self.exception_policy == CppExceptionPolicy::Result => {
+                ctx.add_error_str(
+                    "`#cpp_exception(result)` needs a function returning `Result<T, ZngurCppException>`",
+                    span,
+                );
+            }
+            None => {}
+        }
+        ZngurExternCppMethod {
+            data: self.data.to_zngur(scope),
+            exception_policy: self.exception_policy,
+        }
+    }
+}
+
+const CPP_EXCEPTION_MISUSE: &str = "`ZngurCppException` can only be the error type of a `Result` returned from an `extern \"C++\"` function";
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ParsedConstructorArgs<'a> {
     Unit,
@@ -250,7 +321,7 @@ enum ParsedConstructorArgs<'a> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ParsedLayoutPolicy<'a> {
     StackAllocated(Vec<(Spanned<&'a str>, usize)>),
-    HeapAllocated,
+    HeapAllocated(Vec<(Spanned<&'a str>, usize)>),
     OnlyByRef,
 }
 
@@ -265,12 +336,13 @@ enum ParsedTypeItem<'a> {
     Field {
         name: String,
         ty: ParsedRustType<'a>,
-        offset: usize,
+        offset: Option<usize>,
     },
     Method {
         data: ParsedMethod<'a>,
         use_path: Option<ParsedPath<'a>>,
         deref: Option<ParsedRustType<'a>>,
+        result_policy: Option<ResultPolicy>,
     },
     CppValue {
         field: &'a str,
@@ -279,6 +351,9 @@ enum ParsedTypeItem<'a> {
     CppRef {
         cpp_type: &'a str,
     },
+    CppStdBridge,
+    Enum,
+    ResultPolicy(ResultPolicy),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -334,6 +409,7 @@ impl ProcessedItem<'_> {
                 }
             }
             ProcessedItem::Import(path) => {
+                ctx.imports.push((path.span, path.path.clone()));
                 if path.path.is_absolute() {
                     ctx.add_error_str("Absolute paths imports are not supported.", path.span)
                 }
@@ -364,6 +440,12 @@ impl ProcessedItem<'_> {
                 let mut layout_span = None;
                 let mut cpp_value = None;
                 let mut cpp_ref = None;
+                let mut cpp_std_bridge = false;
+                let mut result_policy = None;
+                let mut enum_span = None;
+                let zngur_ty = ty.inner.to_zngur(scope);
+                ctx.locations
+                    .push((SpecItem::Type(zngur_ty.clone()), ty.span));
                 for item in items {
                     let item_span = item.span;
                     let item = item.inner;
@@ -396,7 +478,20 @@ impl ProcessedItem<'_> {
                                     };
                                     LayoutPolicy::StackAllocated { size, align }
                                 }
-                                ParsedLayoutPolicy::HeapAllocated => LayoutPolicy::HeapAllocated,
+                                ParsedLayoutPolicy::HeapAllocated(p) => {
+                                    let mut inline_size = None;
+                                    for (key, value) in p {
+                                        match key.inner {
+                                            "inline" if value == 0 => ctx.add_error_str(
+                                                "Inline size should be greater than zero",
+                                                key.span,
+                                            ),
+                                            "inline" => inline_size = Some(value),
+                                            _ => ctx.add_error_str("Unknown property", key.span),
+                                        }
+                                    }
+                                    LayoutPolicy::HeapAllocated { inline_size }
+                                }
                                 ParsedLayoutPolicy::OnlyByRef => LayoutPolicy::OnlyByRef,
                             });
                             match layout_span {
@@ -410,6 +505,10 @@ impl ProcessedItem<'_> {
                             wellknown_traits.extend(tr);
                         }
                         ParsedTypeItem::Constructor { name, args } => {
+                            ctx.locations.push((
+                                SpecItem::Constructor(zngur_ty.clone(), name.map(|x| x.to_owned())),
+                                item_span,
+                            ));
                             constructors.push(ZngurConstructor {
                                 name: name.map(|x| x.to_owned()),
                                 inputs: match args {
@@ -427,6 +526,8 @@ impl ProcessedItem<'_> {
                             })
                         }
                         ParsedTypeItem::Field { name, ty, offset } => {
+                            ctx.locations
+                                .push((SpecItem::Field(zngur_ty.clone(), name.clone()), item_span));
                             fields.push(ZngurField {
                                 name: name.to_owned(),
                                 ty: ty.to_zngur(scope),
@@ -437,6 +538,7 @@ impl ProcessedItem<'_> {
                             data,
                             use_path,
                             deref,
+                            result_policy,
                         } => {
                             let deref = deref.and_then(|x| {
                                 let deref_type = x.to_zngur(scope);
@@ -452,10 +554,26 @@ impl ProcessedItem<'_> {
                                 };
                                 Some((deref_type, receiver_mutability))
                             });
+                            let data = data.to_zngur(scope);
+                            ctx.locations.push((
+                                SpecItem::Method(zngur_ty.clone(), data.name.clone()),
+                                item_span,
+                            ));
+                            if result_policy.is_some() {
+                                if data.output.as_result().is_none() {
+                                    ctx.add_error_str(
+                                        "Result policy needs a method returning `Result`",
+                                        item_span,
+                                    );
+                                } else {
+                                    ctx.results.push((item_span, data.output.clone()));
+                                }
+                            }
                             methods.push(ZngurMethodDetails {
-                                data: data.to_zngur(scope),
+                                data,
                                 use_path: use_path.map(|x| scope.resolve_path(x)),
                                 deref,
+                                result_policy,
                             });
                         }
                         ParsedTypeItem::CppValue { field, cpp_type } => {
@@ -474,6 +592,35 @@ impl ProcessedItem<'_> {
                             }
                             cpp_ref = Some(CppRef(cpp_type.to_owned()));
                         }
+                        ParsedTypeItem::CppStdBridge => {
+                            if zngur_ty.cpp_std_bridge().is_none() {
+                                ctx.add_error_str(
+                                    "`#cpp_std_bridge` is only supported for `String`, `Vec<T>` \
+with a numeric `T` and `Option<T>`",
+                                    item_span,
+                                );
+                            }
+                            cpp_std_bridge = true;
+                        }
+                        ParsedTypeItem::Enum => {
+                            enum_span = Some(item_span);
+                        }
+                        ParsedTypeItem::ResultPolicy(policy) => {
+                            if zngur_ty.as_result().is_none() {
+                                ctx.add_error_str(
+                                    "Result policy is only allowed on `Result` types",
+                                    item_span,
+                                );
+                            }
+                            result_policy = Some(policy);
+                        }
+                    }
+                }
+                if let Some(span) = enum_span {
+                    if constructors.iter().any(|x| x.name.is_none()) {
+                        ctx.add_error_str("Enums can only have named constructors", span);
+                    } else if constructors.is_empty() {
+                        ctx.add_error_str("Enums need a named constructor for each variant", span);
                     }
                 }
                 let is_unsized = wellknown_traits
@@ -493,13 +640,16 @@ impl ProcessedItem<'_> {
                 }
                 if let Some(is_unsized) = is_unsized {
                     if let Some(span) = layout_span {
+                        let message = "Duplicate layout policy found for unsized type.";
                         ctx.add_report(
+                            span,
+                            message,
                             Report::build(
                                 ReportKind::Error,
                                 ctx.filename().to_string(),
                                 span.start,
                             )
-                            .with_message("Duplicate layout policy found for unsized type.")
+                            .with_message(message)
                             .with_label(
                                 Label::new((ctx.filename().to_string(), span.start..span.end))
                                     .with_message(
@@ -523,7 +673,7 @@ impl ProcessedItem<'_> {
                 if let Some(layout) = layout {
                     checked_merge(
                         ZngurType {
-                            ty: ty.inner.to_zngur(scope),
+                            ty: zngur_ty,
                             layout,
                             methods,
                             wellknown_traits: wt,
@@ -531,6 +681,9 @@ impl ProcessedItem<'_> {
                             fields,
                             cpp_value,
                             cpp_ref,
+                            cpp_std_bridge,
+                            result_policy,
+                            is_enum: enum_span.is_some(),
                         },
                         r,
                         ty.span,
@@ -544,28 +697,69 @@ Use one of `#layout(size = X, align = Y)`, `#heap_allocated` or `#only_by_ref`."
                     );
                 };
             }
-            ProcessedItem::Trait { tr, methods } => {
+            ProcessedItem::Trait {
+                tr,
+                supertraits,
+                methods,
+            } => {
+                let zngur_tr = tr.inner.to_zngur(scope);
+                ctx.locations
+                    .push((SpecItem::Trait(zngur_tr.clone()), tr.span));
+                let supertraits = supertraits
+                    .into_iter()
+                    .map(|x| {
+                        let supertrait = x.inner.to_zngur(scope);
+                        ctx.supertraits.push((x.span, supertrait.clone()));
+                        supertrait
+                    })
+                    .collect();
+                for method in &methods {
+                    if !method.inner.generics.is_empty() {
+                        ctx.add_error_str(
+                            "Generic methods of traits can't be implemented in C++. \
+Give them a default body with `where Self: Sized` in Rust, and remove them from here.",
+                            method.span,
+                        );
+                    }
+                }
                 checked_merge(
                     ZngurTrait {
-                        tr: tr.inner.to_zngur(scope),
-                        methods: methods.into_iter().map(|m| m.to_zngur(scope)).collect(),
+                        tr: zngur_tr,
+                        supertraits,
+                        methods: methods
+                            .into_iter()
+                            .map(|m| m.inner.to_zngur(scope))
+                            .collect(),
                     },
                     r,
                     tr.span,
                     ctx,
                 );
             }
-            ProcessedItem::Fn(f) => {
+            ProcessedItem::Fn(f, result_policy) => {
                 let method = f.inner.to_zngur(scope);
+                if result_policy.is_some() {
+                    if method.output.as_result().is_none() {
+                        ctx.add_error_str(
+                            "Result policy needs a function returning `Result`",
+                            f.span,
+                        );
+                    } else {
+                        ctx.results.push((f.span, method.output.clone()));
+                    }
+                }
+                let path = scope.simple_relative_path(&method.name);
+                ctx.locations.push((SpecItem::Fn(path.clone()), f.span));
                 checked_merge(
                     ZngurFn {
                         path: RustPathAndGenerics {
-                            path: scope.simple_relative_path(&method.name),
+                            path,
                             generics: method.generics,
                             named_generics: vec![],
                         },
                         inputs: method.inputs,
                         output: method.output,
+                        result_policy,
                     },
                     r,
                     f.span,
@@ -577,12 +771,16 @@ Use one of `#layout(size = X, align = Y)`, `#heap_allocated` or `#only_by_ref`."
                     match item {
                         ParsedExternCppItem::Function(method) => {
                             let span = method.span;
-                            let method = method.inner.to_zngur(scope);
+                            let ZngurExternCppMethod {
+                                data: method,
+                                exception_policy,
+                            } = method.inner.into_zngur(span, scope, ctx);
                             checked_merge(
                                 ZngurExternCppFn {
                                     name: method.name.to_string(),
                                     inputs: method.inputs,
                                     output: method.output,
+                                    exception_policy,
                                 },
                                 r,
                                 span,
@@ -596,7 +794,9 @@ Use one of `#layout(size = X, align = Y)`, `#heap_allocated` or `#only_by_ref`."
                                     ty: ty.inner.to_zngur(scope),
                                     methods: methods
                                         .into_iter()
-                                        .map(|x| x.to_zngur(scope))
+                                        .map(|method| {
+                                            method.inner.into_zngur(method.span, scope, ctx)
+                                        })
                                         .collect(),
                                 },
                                 r,
@@ -630,6 +830,16 @@ Use one of `#layout(size = X, align = Y)`, `#heap_allocated` or `#only_by_ref`."
                     }
                 }
             }
+            ProcessedItem::HeapAllocator(span, allocator) => {
+                if ctx.depth > 0 {
+                    ctx.add_error_str(
+                        "Using `#heap_allocator` in imported zngur files is not supported. This directive can only be used in the main zngur file.",
+                        span,
+                    );
+                    return;
+                }
+                checked_merge(allocator, r, span, ctx);
+            }
         }
     }
 }
@@ -724,6 +934,16 @@ struct ParseContext<'a, 'b> {
     depth: usize,
     reports: Vec<Report<'b, (String, std::ops::Range<usize>)>>,
     source_cache: std::collections::HashMap<std::path::PathBuf, String>,
+    locations: Vec<(SpecItem, Span)>,
+    references: Vec<(Span, Vec<String>)>,
+    imports: Vec<(Span, std::path::PathBuf)>,
+    diagnostics: Vec<(Span, String)>,
+    /// The supertraits of the traits of the file, which need to be declared in it or in the
+    /// files parsed before the end of its imports.
+    supertraits: Vec<(Span, RustTrait)>,
+    /// The `Result` outputs of the functions with a result policy, which need to be declared
+    /// like the supertraits.
+    results: Vec<(Span, RustType)>,
 }
 
 impl<'a, 'b> ParseContext<'a, 'b> {
@@ -734,6 +954,12 @@ impl<'a, 'b> ParseContext<'a, 'b> {
             depth: 0,
             reports: Vec::new(),
             source_cache: HashMap::new(),
+            locations: Vec::new(),
+            references: Vec::new(),
+            imports: Vec::new(),
+            diagnostics: Vec::new(),
+            supertraits: Vec::new(),
+            results: Vec::new(),
         }
     }
 
@@ -744,6 +970,12 @@ impl<'a, 'b> ParseContext<'a, 'b> {
             depth,
             reports: Vec::new(),
             source_cache: HashMap::new(),
+            locations: Vec::new(),
+            references: Vec::new(),
+            imports: Vec::new(),
+            diagnostics: Vec::new(),
+            supertraits: Vec::new(),
+            results: Vec::new(),
         }
     }
 
@@ -751,12 +983,21 @@ impl<'a, 'b> ParseContext<'a, 'b> {
         self.path.file_name().unwrap().to_str().unwrap()
     }
 
-    fn add_report(&mut self, report: Report<'b, (String, std::ops::Range<usize>)>) {
+    /// Adds a report, with `span` and `message` as its summary for [`Analysis`].
+    fn add_report(
+        &mut self,
+        span: Span,
+        message: &str,
+        report: Report<'b, (String, std::ops::Range<usize>)>,
+    ) {
+        self.diagnostics.push((span, message.to_owned()));
         self.reports.push(report);
     }
     fn add_errors<'err_src>(&mut self, errs: impl Iterator<Item = Rich<'err_src, String>>) {
         let filename = self.filename().to_string();
+        let diagnostics = &mut self.diagnostics;
         self.reports.extend(errs.map(|e| {
+            diagnostics.push((*e.span(), e.to_string()));
             Report::build(ReportKind::Error, &filename, e.span().start)
                 .with_message(e.to_string())
                 .with_label(
@@ -789,6 +1030,18 @@ impl<'a, 'b> ParseContext<'a, 'b> {
         !self.reports.is_empty()
     }
 
+    /// Takes what was recorded about the file of this context.
+    fn take_record(&mut self) -> FileRecord {
+        FileRecord {
+            path: self.path.clone(),
+            text: self.text.to_owned(),
+            locations: std::mem::take(&mut self.locations),
+            references: std::mem::take(&mut self.references),
+            imports: std::mem::take(&mut self.imports),
+            diagnostics: std::mem::take(&mut self.diagnostics),
+        }
+    }
+
     #[cfg(test)]
     fn emit_ariadne_errors(&self) -> ! {
         let mut r = Vec::<u8>::new();
@@ -844,6 +1097,90 @@ impl<'a, 'b> ParseContext<'a, 'b> {
     }
 }
 
+/// An item of a [`ZngurSpec`], for finding where it is declared.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SpecItem {
+    Type(RustType),
+    Constructor(RustType, Option<String>),
+    Field(RustType, String),
+    Method(RustType, String),
+    Fn(Vec<String>),
+    Trait(RustTrait),
+}
+
+/// What parsing recorded about one of the files of a spec, besides its items.
+#[derive(Debug)]
+struct FileRecord {
+    path: std::path::PathBuf,
+    text: String,
+    locations: Vec<(SpecItem, Span)>,
+    references: Vec<(Span, Vec<String>)>,
+    imports: Vec<(Span, std::path::PathBuf)>,
+    diagnostics: Vec<(Span, String)>,
+}
+
+/// Where the items of a parsed [`ZngurSpec`] are declared, for reporting problems with them after
+/// parsing. An item declared in several blocks is located at its first declaration.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    locations: HashMap<SpecItem, (std::path::PathBuf, std::ops::Range<usize>)>,
+    sources: HashMap<std::path::PathBuf, String>,
+}
+
+impl SourceMap {
+    fn new(files: Vec<FileRecord>) -> Self {
+        let mut result = SourceMap::default();
+        for file in files {
+            for (item, span) in file.locations {
+                result
+                    .locations
+                    .entry(item)
+                    .or_insert_with(|| (file.path.clone(), span.into_range()));
+            }
+            result.sources.insert(file.path, file.text);
+        }
+        result
+    }
+
+    /// Returns the file and the byte range of the declaration of `item`.
+    pub fn location(&self, item: &SpecItem) -> Option<(&std::path::Path, std::ops::Range<usize>)> {
+        self.locations
+            .get(item)
+            .map(|(path, span)| (path.as_path(), span.clone()))
+    }
+
+    /// Writes an error about `item`, with `label` pointing at its declaration.
+    pub fn write_error(
+        &self,
+        item: &SpecItem,
+        message: &str,
+        label: &str,
+        out: impl std::io::Write,
+    ) -> std::io::Result<()> {
+        let Some((path, span)) = self.location(item) else {
+            let mut out = out;
+            return writeln!(out, "Error: {message}");
+        };
+        let filename = path.display().to_string();
+        Report::build(ReportKind::Error, filename.clone(), span.start)
+            .with_message(message)
+            .with_label(
+                Label::new((filename, span))
+                    .with_message(label)
+                    .with_color(Color::Red),
+            )
+            .finish()
+            .write(
+                sources(
+                    self.sources
+                        .iter()
+                        .map(|(path, text)| (path.display().to_string(), text.as_str())),
+                ),
+                out,
+            )
+    }
+}
+
 /// A trait for types which can resolve filesystem-like paths relative to a given directory.
 pub trait ImportResolver {
     fn resolve_import(
@@ -871,11 +1208,17 @@ impl ImportResolver for DefaultImportResolver {
 }
 
 impl<'a> ParsedZngFile<'a> {
-    fn parse_into(zngur: &mut ZngurSpec, ctx: &mut ParseContext, resolver: &impl ImportResolver) {
+    fn parse_into(
+        zngur: &mut ZngurSpec,
+        ctx: &mut ParseContext,
+        resolver: &impl ImportResolver,
+        files: &mut Vec<FileRecord>,
+    ) {
         let (tokens, errs) = lexer().parse(ctx.text).into_output_errors();
-        let Some(tokens) = tokens else {
+        let Some(tokens) = tokens.filter(|_| errs.is_empty()) else {
             ctx.add_errors(errs.into_iter().map(|e| e.map_token(|c| c.to_string())));
-            ctx.emit_ariadne_errors();
+            files.push(ctx.take_record());
+            return;
         };
         let tokens: ParserInput<'_> = tokens.as_slice().map(
             (ctx.text.len()..ctx.text.len()).into(),
@@ -885,13 +1228,17 @@ impl<'a> ParsedZngFile<'a> {
             .map_with(|ast, extra| (ast, extra.span()))
             .parse(tokens)
             .into_output_errors();
-        let Some(ast) = ast else {
+        // Errors emitted by `validate` still produce an output, which is not worth processing.
+        let Some(ast) = ast.filter(|_| errs.is_empty()) else {
             ctx.add_errors(errs.into_iter().map(|e| e.map_token(|c| c.to_string())));
-            ctx.emit_ariadne_errors();
+            files.push(ctx.take_record());
+            return;
         };
 
         let (aliases, items) = ast.0.0.into_iter().partition_map(partition_parsed_item_vec);
         ProcessedZngFile::new(aliases, items).into_zngur_spec(zngur, ctx);
+        let index = files.len();
+        files.push(ctx.take_record());
 
         if let Some(dirname) = ctx.path.to_owned().parent() {
             for import in std::mem::take(&mut zngur.imports) {
@@ -899,53 +1246,99 @@ impl<'a> ParsedZngFile<'a> {
                     Ok(text) => {
                         let mut nested_ctx =
                             ParseContext::with_depth(dirname.join(&import.0), &text, ctx.depth + 1);
-                        Self::parse_into(zngur, &mut nested_ctx, resolver);
+                        Self::parse_into(zngur, &mut nested_ctx, resolver, files);
                         ctx.consume_errors_from(nested_ctx);
                     }
                     Err(_) => {
                         // TODO: emit a better error. How should we get a span here?
                         // I'd like to avoid putting a ParsedImportPath in ZngurSpec, and
                         // also not have to pass a filename to add_to_zngur_spec.
+                        let message = format!("Import path not found: {}", import.0.display());
+                        let span = files[index]
+                            .imports
+                            .iter()
+                            .find(|x| x.1 == import.0)
+                            .map_or(Span::from(0..0), |x| x.0);
                         ctx.add_report(
+                            span,
+                            &message,
                             Report::build(ReportKind::Error, ctx.filename(), 0)
-                                .with_message(format!(
-                                    "Import path not found: {}",
-                                    import.0.display()
-                                ))
+                                .with_message(&message)
                                 .finish(),
                         );
                     }
                 }
             }
         }
+        for (span, supertrait) in std::mem::take(&mut ctx.supertraits) {
+            if !zngur.traits.contains_key(&supertrait) {
+                ctx.add_error_str(
+                    &format!(
+                        "Supertrait `{supertrait}` is not declared. Declare it with a `trait` block. \
+Traits that C++ can't implement, like `Debug`, can't be supertraits."
+                    ),
+                    span,
+                );
+            }
+        }
+        for (span, ty) in std::mem::take(&mut ctx.results) {
+            if !zngur.types.iter().any(|x| x.ty == ty) {
+                ctx.add_error_str(
+                    &format!(
+                        "Type `{ty}` is used with a result policy, but it is not declared. \
+Declare it with a `type` block."
+                    ),
+                    span,
+                );
+            }
+        }
+        files[index].diagnostics.append(&mut ctx.diagnostics);
     }
 
     pub fn parse(path: std::path::PathBuf) -> ZngurSpec {
+        Self::parse_with_source_map(path).0
+    }
+
+    /// Like [`ParsedZngFile::parse`], but also returns where the items of the spec are declared.
+    pub fn parse_with_source_map(path: std::path::PathBuf) -> (ZngurSpec, SourceMap) {
         let mut zngur = ZngurSpec::default();
+        let mut files = vec![];
         let text = std::fs::read_to_string(&path).unwrap();
         let mut ctx = ParseContext::new(path, &text);
-        Self::parse_into(&mut zngur, &mut ctx, &DefaultImportResolver);
+        Self::parse_into(&mut zngur, &mut ctx, &DefaultImportResolver, &mut files);
         if ctx.has_errors() {
             ctx.emit_ariadne_errors();
         }
-        zngur
+        (zngur, SourceMap::new(files))
     }
 
     pub fn parse_str(text: &str) -> ZngurSpec {
         let mut zngur = ZngurSpec::default();
         let mut ctx = ParseContext::new(std::path::PathBuf::from("test.zng"), text);
-        Self::parse_into(&mut zngur, &mut ctx, &DefaultImportResolver);
+        Self::parse_into(&mut zngur, &mut ctx, &DefaultImportResolver, &mut vec![]);
         if ctx.has_errors() {
             ctx.emit_ariadne_errors();
         }
         zngur
     }
 
+    #[cfg(test)]
+    pub(crate) fn parse_str_with_source_map(text: &str) -> (ZngurSpec, SourceMap) {
+        let mut zngur = ZngurSpec::default();
+        let mut files = vec![];
+        let mut ctx = ParseContext::new(std::path::PathBuf::from("test.zng"), text);
+        Self::parse_into(&mut zngur, &mut ctx, &DefaultImportResolver, &mut files);
+        if ctx.has_errors() {
+            ctx.emit_ariadne_errors();
+        }
+        (zngur, SourceMap::new(files))
+    }
+
     #[cfg(test)]
     pub(crate) fn parse_str_with_resolver(text: &str, resolver: &impl ImportResolver) -> ZngurSpec {
         let mut zngur = ZngurSpec::default();
         let mut ctx = ParseContext::new(std::path::PathBuf::from("test.zng"), text);
-        Self::parse_into(&mut zngur, &mut ctx, resolver);
+        Self::parse_into(&mut zngur, &mut ctx, resolver, &mut vec![]);
         if ctx.has_errors() {
             ctx.emit_ariadne_errors();
         }
@@ -959,6 +1352,9 @@ fn partition_parsed_item_vec(item: ParsedItem<'_>) -> Either<ParsedAlias<'_>, Pr
         ParsedItem::ConvertPanicToException(span) => {
             Either::Right(ProcessedItem::ConvertPanicToException(span))
         }
+        ParsedItem::HeapAllocator(span, allocator) => {
+            Either::Right(ProcessedItem::HeapAllocator(span, allocator))
+        }
         ParsedItem::CppAdditionalInclude(inc) => {
             Either::Right(ProcessedItem::CppAdditionalInclude(inc))
         }
@@ -972,8 +1368,18 @@ fn partition_parsed_item_vec(item: ParsedItem<'_>) -> Either<ParsedAlias<'_>, Pr
             })
         }
         ParsedItem::Type { ty, items } => Either::Right(ProcessedItem::Type { ty, items }),
-        ParsedItem::Trait { tr, methods } => Either::Right(ProcessedItem::Trait { tr, methods }),
-        ParsedItem::Fn(method) => Either::Right(ProcessedItem::Fn(method)),
+        ParsedItem::Trait {
+            tr,
+            supertraits,
+            methods,
+        } => Either::Right(ProcessedItem::Trait {
+            tr,
+            supertraits,
+            methods,
+        }),
+        ParsedItem::Fn(method, result_policy) => {
+            Either::Right(ProcessedItem::Fn(method, result_policy))
+        }
         ParsedItem::ExternCpp(items) => Either::Right(ProcessedItem::ExternCpp(items)),
         ParsedItem::Import(path) => Either::Right(ProcessedItem::Import(path)),
     }
@@ -990,6 +1396,7 @@ impl<'a> ProcessedZngFile<'a> {
         for item in self.items {
             item.add_to_zngur_spec(zngur, &root_scope, ctx);
         }
+        ctx.references = root_scope.references.take();
     }
 }
 
@@ -1013,6 +1420,7 @@ enum Token<'a> {
     Eq,
     Question,
     Comma,
+    Dot,
     Semicolon,
     KwAs,
     KwDyn,
@@ -1076,6 +1484,7 @@ impl Display for Token<'_> {
             Token::Eq => write!(f, "="),
             Token::Question => write!(f, "?"),
             Token::Comma => write!(f, ","),
+            Token::Dot => write!(f, "."),
             Token::Semicolon => write!(f, ";"),
             Token::KwAs => write!(f, "as"),
             Token::KwDyn => write!(f, "dyn"),
@@ -1120,10 +1529,16 @@ fn lexer<'src>()
             just("=").to(Token::Eq),
             just("?").to(Token::Question),
             just(",").to(Token::Comma),
+            just(".").to(Token::Dot),
             just(";").to(Token::Semicolon),
         ]),
         text::ident().map(Token::ident_or_kw),
-        text::int(10).map(|x: &str| Token::Number(x.parse().unwrap())),
+        text::int(10).validate(|x: &str, e, emitter| {
+            Token::Number(x.parse().unwrap_or_else(|_| {
+                emitter.emit(Rich::custom(e.span(), "This number is too large"));
+                0
+            }))
+        }),
         just('"')
             .ignore_then(none_of('"').repeated().to_slice().map(Token::Str))
             .then_ignore(just('"')),
@@ -1182,6 +1597,14 @@ fn rust_type<'a>()
         Token::Ident(c) if as_scalar(c, 'i').is_some() => PrimitiveRustType::Int(as_scalar(c, 'i').unwrap()),
         Token::Ident(c) if as_scalar(c, 'f').is_some() => PrimitiveRustType::Float(as_scalar(c, 'f').unwrap()),
     }.map(ParsedRustType::Primitive);
+    // Only the `Result` output of an `extern "C++"` function can have it, see `extern_cpp_item`.
+    let cpp_exception = select! {
+        Token::Ident("ZngurCppException") => (),
+    }
+    .validate(|_, extra, emitter| {
+        emitter.emit(Rich::custom(extra.span(), CPP_EXCEPTION_MISUSE));
+        ParsedRustType::Tuple(vec![])
+    });
 
     recursive(|parser| {
         let parser = parser.boxed();
@@ -1201,9 +1624,15 @@ fn rust_type<'a>()
             .map(|(x, y)| ParsedRustType::Dyn(x, y));
         let boxed = just(Token::Ident("Box"))
             .then(rust_generics(parser.clone()))
-            .map(|(_, x)| {
-                assert_eq!(x.len(), 1);
-                ParsedRustType::Boxed(Box::new(x.into_iter().next().unwrap().right().unwrap()))
+            .validate(|(_, x), e, emitter| match <[_; 1]>::try_from(x) {
+                Ok([Either::Right(ty)]) => ParsedRustType::Boxed(Box::new(ty)),
+                _ => {
+                    emitter.emit(Rich::custom(
+                        e.span(),
+                        "`Box` takes exactly one type argument",
+                    ));
+                    ParsedRustType::Tuple(vec![])
+                }
             });
         let unit = just(Token::ParenOpen)
             .then(just(Token::ParenClose))
@@ -1236,7 +1665,16 @@ fn rust_type<'a>()
             .then(parser)
             .map(|(m, x)| ParsedRustType::Raw(m, Box::new(x)));
         choice((
-            scalar, boxed, unit, tuple, slice, adt, reference, raw_ptr, dyn_trait,
+            scalar,
+            cpp_exception,
+            boxed,
+            unit,
+            tuple,
+            slice,
+            adt,
+            reference,
+            raw_ptr,
+            dyn_trait,
         ))
     })
     .boxed()
@@ -1314,12 +1752,7 @@ fn fn_args<'a>(
     (Vec<ParsedRustType<'a>>, ParsedRustType<'a>),
     extra::Err<Rich<'a, Token<'a>, Span>>,
 > + Clone {
-    rust_type
-        .clone()
-        .separated_by(just(Token::Comma))
-        .allow_trailing()
-        .collect::<Vec<_>>()
-        .delimited_by(just(Token::ParenOpen), just(Token::ParenClose))
+    fn_inputs(rust_type.clone())
         .then(
             just(Token::Arrow)
                 .ignore_then(rust_type)
@@ -1328,6 +1761,23 @@ fn fn_args<'a>(
         .boxed()
 }
 
+fn fn_inputs<'a>(
+    rust_type: Boxed<
+        'a,
+        'a,
+        ParserInput<'a>,
+        ParsedRustType<'a>,
+        extra::Err<Rich<'a, Token<'a>, Span>>,
+    >,
+) -> impl Parser<'a, ParserInput<'a>, Vec<ParsedRustType<'a>>, extra::Err<Rich<'a, Token<'a>, Span>>>
++ Clone {
+    rust_type
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .collect::<Vec<_>>()
+        .delimited_by(just(Token::ParenOpen), just(Token::ParenClose))
+}
+
 fn spanned<'a, T>(
     parser: impl Parser<'a, ParserInput<'a>, T, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone,
 ) -> impl Parser<'a, ParserInput<'a>, Spanned<T>, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone {
@@ -1364,6 +1814,17 @@ fn rust_trait<'a>(
 fn method<'a>()
 -> impl Parser<'a, ParserInput<'a>, ParsedMethod<'a>, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone
 {
+    method_name_and_generics()
+        .then(fn_args(rust_type()))
+        .map(|((name, generics), args)| parsed_method(name, generics, args))
+}
+
+fn method_name_and_generics<'a>() -> impl Parser<
+    'a,
+    ParserInput<'a>,
+    (&'a str, Vec<ParsedRustType<'a>>),
+    extra::Err<Rich<'a, Token<'a>, Span>>,
+> + Clone {
     just(Token::KwFn)
         .ignore_then(select! {
             Token::Ident(c) => c,
@@ -1375,31 +1836,66 @@ fn method<'a>()
                 .delimited_by(just(Token::AngleOpen), just(Token::AngleClose))
                 .or(empty().to(vec![])),
         )
-        .then(fn_args(rust_type()))
-        .map(|((name, generics), args)| {
-            let is_self = |c: &ParsedRustType<'_>| {
-                if let ParsedRustType::Adt(c) = c {
-                    c.path.start == ParsedPathStart::Relative
-                        && &c.path.segments == &["self"]
-                        && c.generics.is_empty()
-                } else {
-                    false
-                }
-            };
-            let (inputs, receiver) = match args.0.get(0) {
-                Some(x) if is_self(&x) => (args.0[1..].to_vec(), ZngurMethodReceiver::Move),
-                Some(ParsedRustType::Ref(m, x)) if is_self(&x) => {
-                    (args.0[1..].to_vec(), ZngurMethodReceiver::Ref(*m))
-                }
-                _ => (args.0, ZngurMethodReceiver::Static),
-            };
-            ParsedMethod {
-                name,
-                receiver,
-                generics,
-                inputs,
-                output: args.1,
-            }
+}
+
+/// Builds a method, taking its receiver from the first of the `args`.
+fn parsed_method<'a>(
+    name: &'a str,
+    generics: Vec<ParsedRustType<'a>>,
+    args: (Vec<ParsedRustType<'a>>, ParsedRustType<'a>),
+) -> ParsedMethod<'a> {
+    let is_self = |c: &ParsedRustType<'_>| {
+        if let ParsedRustType::Adt(c) = c {
+            c.path.start == ParsedPathStart::Relative
+                && &c.path.segments == &["self"]
+                && c.generics.is_empty()
+        } else {
+            false
+        }
+    };
+    let (inputs, receiver) = match args.0.get(0) {
+        Some(x) if is_self(&x) => (args.0[1..].to_vec(), ZngurMethodReceiver::Move),
+        Some(ParsedRustType::Ref(m, x)) if is_self(&x) => {
+            (args.0[1..].to_vec(), ZngurMethodReceiver::Ref(*m))
+        }
+        _ => (args.0, ZngurMethodReceiver::Static),
+    };
+    ParsedMethod {
+        name,
+        receiver,
+        generics,
+        inputs,
+        output: args.1,
+    }
+}
+
+/// A method in a `type` block, with its optional `use` path and `deref` type.
+struct ParsedTypeMethod<'a> {
+    data: ParsedMethod<'a>,
+    use_path: Option<ParsedPath<'a>>,
+    deref: Option<ParsedRustType<'a>>,
+}
+
+fn type_method<'a>()
+-> impl Parser<'a, ParserInput<'a>, ParsedTypeMethod<'a>, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone
+{
+    method()
+        .then(
+            just(Token::KwUse)
+                .ignore_then(path())
+                .map(Some)
+                .or(empty().to(None)),
+        )
+        .then(
+            just(Token::Ident("deref"))
+                .ignore_then(rust_type())
+                .map(Some)
+                .or(empty().to(None)),
+        )
+        .map(|((data, use_path), deref)| ParsedTypeMethod {
+            data,
+            use_path,
+            deref,
         })
 }
 
@@ -1418,6 +1914,7 @@ fn type_item<'a>()
         let layout = just([Token::Sharp, Token::Ident("layout")])
             .ignore_then(
                 property_item
+                    .clone()
                     .separated_by(just(Token::Comma))
                     .collect::<Vec<_>>()
                     .delimited_by(just(Token::ParenOpen), just(Token::ParenClose)),
@@ -1425,7 +1922,14 @@ fn type_item<'a>()
             .map(ParsedLayoutPolicy::StackAllocated)
             .or(just([Token::Sharp, Token::Ident("only_by_ref")]).to(ParsedLayoutPolicy::OnlyByRef))
             .or(just([Token::Sharp, Token::Ident("heap_allocated")])
-                .to(ParsedLayoutPolicy::HeapAllocated))
+                .ignore_then(
+                    property_item
+                        .separated_by(just(Token::Comma))
+                        .collect::<Vec<_>>()
+                        .delimited_by(just(Token::ParenOpen), just(Token::ParenClose))
+                        .or_not(),
+                )
+                .map(|p| ParsedLayoutPolicy::HeapAllocated(p.unwrap_or_default())))
             .map_with(|x, extra| ParsedTypeItem::Layout(extra.span(), x))
             .boxed();
         let trait_item = select! {
@@ -1469,26 +1973,34 @@ fn type_item<'a>()
             .then(constructor_args)
             .map(|(name, args)| ParsedTypeItem::Constructor { name, args }),
         );
+        let field_name = select! {
+            Token::Ident(c) => c.to_owned(),
+            Token::Number(c) => c.to_string(),
+        }
+        .separated_by(just(Token::Dot))
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .map(|segments| segments.join("."));
         let field = just(Token::Ident("field")).ignore_then(
-            (select! {
-                Token::Ident(c) => c.to_owned(),
-                Token::Number(c) => c.to_string(),
-            })
-            .then(
-                just(Token::Ident("offset"))
-                    .then(just(Token::Eq))
-                    .ignore_then(select! {
-                        Token::Number(c) => c,
-                    })
-                    .then(
-                        just(Token::Comma)
-                            .then(just(Token::KwType))
-                            .then(just(Token::Eq))
-                            .ignore_then(rust_type()),
-                    )
-                    .delimited_by(just(Token::ParenOpen), just(Token::ParenClose)),
-            )
-            .map(|(name, (offset, ty))| ParsedTypeItem::Field { name, ty, offset }),
+            field_name
+                .then(
+                    just(Token::Ident("offset"))
+                        .then(just(Token::Eq))
+                        .ignore_then(select! {
+                            Token::Number(c) => Some(c),
+                        })
+                        .then(
+                            just(Token::Comma)
+                                .then(just(Token::KwType))
+                                .then(just(Token::Eq))
+                                .ignore_then(rust_type()),
+                        )
+                        .delimited_by(just(Token::ParenOpen), just(Token::ParenClose))
+                        .or(just(Token::Colon)
+                            .ignore_then(rust_type())
+                            .map(|ty| (None, ty))),
+                )
+                .map(|(name, (offset, ty))| ParsedTypeItem::Field { name, ty, offset }),
         );
         let cpp_value = just(Token::Sharp)
             .then(just(Token::Ident("cpp_value")))
@@ -1508,6 +2020,26 @@ fn type_item<'a>()
                 Token::Str(c) => c,
             })
             .map(|x| ParsedTypeItem::CppRef { cpp_type: x });
+        let cpp_std_bridge =
+            just([Token::Sharp, Token::Ident("cpp_std_bridge")]).to(ParsedTypeItem::CppStdBridge);
+        let enum_item = just([Token::Sharp, Token::Ident("enum")]).to(ParsedTypeItem::Enum);
+        let method_item = type_method();
+        let result_policy_item =
+            result_policy()
+                .then(method_item.clone().or_not())
+                .map(|(result_policy, method)| match method {
+                    Some(ParsedTypeMethod {
+                        data,
+                        use_path,
+                        deref,
+                    }) => ParsedTypeItem::Method {
+                        deref,
+                        use_path,
+                        data,
+                        result_policy: Some(result_policy),
+                    },
+                    None => ParsedTypeItem::ResultPolicy(result_policy),
+                });
         choice((
             layout,
             traits,
@@ -1515,24 +2047,15 @@ fn type_item<'a>()
             field,
             cpp_value,
             cpp_ref,
-            method()
-                .then(
-                    just(Token::KwUse)
-                        .ignore_then(path())
-                        .map(Some)
-                        .or(empty().to(None)),
-                )
-                .then(
-                    just(Token::Ident("deref"))
-                        .ignore_then(rust_type())
-                        .map(Some)
-                        .or(empty().to(None)),
-                )
-                .map(|((data, use_path), deref)| ParsedTypeItem::Method {
-                    deref,
-                    use_path,
-                    data,
-                }),
+            cpp_std_bridge,
+            enum_item,
+            result_policy_item,
+            method_item.map(|method| ParsedTypeItem::Method {
+                deref: method.deref,
+                use_path: method.use_path,
+                data: method.data,
+                result_policy: None,
+            }),
         ))
         .then_ignore(just(Token::Semicolon))
     }
@@ -1553,21 +2076,46 @@ fn trait_item<'a>()
     just(Token::KwTrait)
         .ignore_then(spanned(rust_trait(rust_type())))
         .then(
-            method()
+            just(Token::Colon)
+                .ignore_then(
+                    spanned(rust_trait(rust_type()))
+                        .separated_by(just(Token::Plus))
+                        .at_least(1)
+                        .collect::<Vec<_>>(),
+                )
+                .or_not()
+                .map(Option::unwrap_or_default),
+        )
+        .then(
+            spanned(method())
                 .then_ignore(just(Token::Semicolon))
                 .repeated()
                 .collect::<Vec<_>>()
                 .delimited_by(just(Token::BraceOpen), just(Token::BraceClose)),
         )
-        .map(|(tr, methods)| ParsedItem::Trait { tr, methods })
+        .map(|((tr, supertraits), methods)| ParsedItem::Trait {
+            tr,
+            supertraits,
+            methods,
+        })
         .boxed()
 }
 
+fn result_policy<'a>()
+-> impl Parser<'a, ParserInput<'a>, ResultPolicy, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone {
+    just(Token::Sharp).ignore_then(select! {
+        Token::Ident("result_as_exception") => ResultPolicy::AsException,
+        Token::Ident("result_as_expected") => ResultPolicy::AsExpected,
+    })
+}
+
 fn fn_item<'a>()
 -> impl Parser<'a, ParserInput<'a>, ParsedItem<'a>, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone {
-    spanned(method())
+    result_policy()
+        .or_not()
+        .then(spanned(method()))
         .then_ignore(just(Token::Semicolon))
-        .map(ParsedItem::Fn)
+        .map(|(result_policy, method)| ParsedItem::Fn(method, result_policy))
 }
 
 fn additional_include_item<'a>()
@@ -1579,16 +2127,72 @@ fn additional_include_item<'a>()
                     Token::Str(c) => ParsedItem::CppAdditionalInclude(c),
                 })
                 .or(just(Token::Ident("convert_panic_to_exception"))
-                    .map_with(|_, extra| ParsedItem::ConvertPanicToException(extra.span()))),
+                    .map_with(|_, extra| ParsedItem::ConvertPanicToException(extra.span())))
+                .or(just(Token::Ident("heap_allocator"))
+                    .ignore_then(
+                        just(Token::Ident("rust"))
+                            .ignore_then(select! { Token::Str(c) => c })
+                            .map(|path| HeapAllocator::Rust(path.to_owned()))
+                            .or(just(Token::Ident("cpp"))
+                                .ignore_then(select! { Token::Str(c) => c })
+                                .then(select! { Token::Str(c) => c })
+                                .map(|(alloc, free)| HeapAllocator::Cpp {
+                                    alloc: alloc.to_owned(),
+                                    free: free.to_owned(),
+                                })),
+                    )
+                    .map_with(|allocator, extra| {
+                        ParsedItem::HeapAllocator(extra.span(), allocator)
+                    })),
         )
         .boxed()
 }
 
 fn extern_cpp_item<'a>()
 -> impl Parser<'a, ParserInput<'a>, ParsedItem<'a>, extra::Err<Rich<'a, Token<'a>, Span>>> + Clone {
-    let function = spanned(method())
+    let exception_policy = just([Token::Sharp, Token::Ident("cpp_exception")])
+        .ignore_then(
+            select! {
+                Token::Ident("abort") => CppExceptionPolicy::Abort,
+                Token::Ident("panic") => CppExceptionPolicy::Panic,
+                Token::Ident("result") => CppExceptionPolicy::Result,
+            }
+            .delimited_by(just(Token::ParenOpen), just(Token::ParenClose)),
+        )
+        .or(empty().to(CppExceptionPolicy::Abort));
+    // `Result<T, ZngurCppException>`, which `rust_type` rejects.
+    let cpp_exception_result = path()
+        .then_ignore(just(Token::ColonColon).or_not())
+        .then(
+            rust_type()
+                .then_ignore(just(Token::Comma))
+                .then_ignore(just(Token::Ident("ZngurCppException")))
+                .then_ignore(just(Token::Comma).or_not())
+                .delimited_by(just(Token::AngleOpen), just(Token::AngleClose)),
+        )
+        .map(|(path, ok)| (ok, Some(path)));
+    let output = just(Token::Arrow)
+        .ignore_then(cpp_exception_result.or(rust_type().map(|x| (x, None))))
+        .or(empty().to((ParsedRustType::Tuple(vec![]), None)));
+    let method = exception_policy
+        .then(spanned(
+            method_name_and_generics()
+                .then(fn_inputs(rust_type()))
+                .then(output),
+        ))
         .then_ignore(just(Token::Semicolon))
-        .map(ParsedExternCppItem::Function);
+        .map(|(exception_policy, method)| {
+            let (((name, generics), inputs), (output, cpp_exception_result)) = method.inner;
+            Spanned {
+                inner: ParsedExternCppMethod {
+                    data: parsed_method(name, generics, (inputs, output)),
+                    exception_policy,
+                    cpp_exception_result,
+                },
+                span: method.span,
+            }
+        });
+    let function = method.clone().map(ParsedExternCppItem::Function);
     let impl_block = just(Token::KwImpl)
         .ignore_then(
             rust_trait(rust_type())
@@ -1598,8 +2202,7 @@ fn extern_cpp_item<'a>()
                 .then(spanned(rust_type())),
         )
         .then(
-            method()
-                .then_ignore(just(Token::Semicolon))
+            method
                 .repeated()
                 .collect::<Vec<_>>()
                 .delimited_by(just(Token::BraceOpen), just(Token::BraceClose)),
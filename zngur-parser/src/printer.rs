@@ -0,0 +1,332 @@
+//! Printing of a [`ZngurSpec`] as a .zng file.
+//!
+//! The printed file parses back to the same spec. Every path is printed in full, so no `use`
+//! aliases are needed, and the types and traits are printed at the top level. Functions are put in
+//! `mod` blocks of their parent paths, since their names can't be paths. The imports of the spec are
+//! not printed, because a parsed spec already contains the items of the imported files.
+
+use std::fmt::Write;
+
+use itertools::Itertools;
+use zngur_def::{
+    CppExceptionPolicy, HeapAllocator, LayoutPolicy, ResultPolicy, RustType, ZngurConstructor,
+    ZngurExternCppImpl, ZngurFn, ZngurMethod, ZngurMethodDetails, ZngurMethodReceiver, ZngurSpec,
+    ZngurTrait, ZngurType, ZngurWellknownTrait,
+};
+
+use crate::ParsedZngFile;
+
+const INDENT: &str = "    ";
+
+fn path(path: &[String]) -> String {
+    match path {
+        [first, rest @ ..] if first == "crate" => std::iter::once("crate")
+            .chain(rest.iter().map(|x| x.as_str()))
+            .join("::"),
+        _ => path.iter().map(|x| format!("::{x}")).join(""),
+    }
+}
+
+fn result_policy(policy: ResultPolicy) -> &'static str {
+    match policy {
+        ResultPolicy::AsException => "#result_as_exception",
+        ResultPolicy::AsExpected => "#result_as_expected",
+    }
+}
+
+/// The signature of a method, without the `fn` keyword.
+fn signature(method: &ZngurMethod) -> String {
+    let mut out = signature_without_output(method);
+    if method.output != RustType::UNIT {
+        write!(out, " -> {}", method.output).unwrap();
+    }
+    out
+}
+
+fn signature_without_output(method: &ZngurMethod) -> String {
+    let mut out = method.name.clone();
+    if !method.generics.is_empty() {
+        write!(out, "<{}>", method.generics.iter().join(", ")).unwrap();
+    }
+    let receiver = match method.receiver {
+        ZngurMethodReceiver::Static => None,
+        ZngurMethodReceiver::Ref(zngur_def::Mutability::Not) => Some("&self".to_owned()),
+        ZngurMethodReceiver::Ref(zngur_def::Mutability::Mut) => Some("&mut self".to_owned()),
+        ZngurMethodReceiver::Move => Some("self".to_owned()),
+    };
+    let inputs = receiver
+        .into_iter()
+        .chain(method.inputs.iter().map(|x| x.to_string()));
+    write!(out, "({})", inputs.format(", ")).unwrap();
+    out
+}
+
+fn print_type(out: &mut String, ty: &ZngurType) {
+    writeln!(out, "type {} {{", ty.ty).unwrap();
+    let is_unsized = ty.wellknown_traits.contains(&ZngurWellknownTrait::Unsized);
+    // Unsized types and `#cpp_ref` types have an implicit layout.
+    if let Some(cpp_ref) = &ty.cpp_ref {
+        writeln!(out, "{INDENT}#cpp_ref \"{cpp_ref}\";").unwrap();
+    } else if !is_unsized {
+        let layout = match ty.layout {
+            LayoutPolicy::StackAllocated { size, align } => {
+                format!("#layout(size = {size}, align = {align})")
+            }
+            LayoutPolicy::HeapAllocated {
+                inline_size: Some(inline),
+            } => format!("#heap_allocated(inline = {inline})"),
+            LayoutPolicy::HeapAllocated { inline_size: None } => "#heap_allocated".to_owned(),
+            LayoutPolicy::OnlyByRef => "#only_by_ref".to_owned(),
+        };
+        writeln!(out, "{INDENT}{layout};").unwrap();
+    }
+    // `Drop` is added by the parser to the types which are neither `Copy` nor unsized.
+    let traits = ty
+        .wellknown_traits
+        .iter()
+        .filter_map(|x| match x {
+            ZngurWellknownTrait::Debug => Some("Debug"),
+            ZngurWellknownTrait::Copy => Some("Copy"),
+            ZngurWellknownTrait::Unsized => Some("?Sized"),
+            ZngurWellknownTrait::Drop => None,
+        })
+        .collect_vec();
+    if !traits.is_empty() {
+        writeln!(out, "{INDENT}wellknown_traits({});", traits.join(", ")).unwrap();
+    }
+    if let Some(cpp_value) = &ty.cpp_value {
+        writeln!(
+            out,
+            "{INDENT}#cpp_value \"{}\" \"{}\";",
+            cpp_value.0, cpp_value.1
+        )
+        .unwrap();
+    }
+    if ty.cpp_std_bridge {
+        writeln!(out, "{INDENT}#cpp_std_bridge;").unwrap();
+    }
+    if ty.is_enum {
+        writeln!(out, "{INDENT}#enum;").unwrap();
+    }
+    if let Some(policy) = ty.result_policy {
+        writeln!(out, "{INDENT}{};", result_policy(policy)).unwrap();
+    }
+    for constructor in &ty.constructors {
+        print_constructor(out, constructor);
+    }
+    for field in &ty.fields {
+        match field.offset {
+            Some(offset) => writeln!(
+                out,
+                "{INDENT}field {} (offset = {offset}, type = {});",
+                field.name, field.ty
+            ),
+            None => writeln!(out, "{INDENT}field {}: {};", field.name, field.ty),
+        }
+        .unwrap();
+    }
+    for method in &ty.methods {
+        print_method(out, method);
+    }
+    out.push_str("}\n");
+}
+
+fn print_constructor(out: &mut String, constructor: &ZngurConstructor) {
+    write!(out, "{INDENT}constructor").unwrap();
+    if let Some(name) = &constructor.name {
+        write!(out, " {name}").unwrap();
+    }
+    let is_tuple = constructor
+        .inputs
+        .iter()
+        .enumerate()
+        .all(|(i, (name, _))| *name == i.to_string());
+    if constructor.inputs.is_empty() {
+        // A unit constructor.
+    } else if is_tuple {
+        write!(
+            out,
+            "({})",
+            constructor.inputs.iter().map(|(_, ty)| ty).join(", ")
+        )
+        .unwrap();
+    } else {
+        write!(
+            out,
+            " {{ {} }}",
+            constructor
+                .inputs
+                .iter()
+                .map(|(name, ty)| format!("{name}: {ty}"))
+                .join(", ")
+        )
+        .unwrap();
+    }
+    out.push_str(";\n");
+}
+
+fn print_method(out: &mut String, method: &ZngurMethodDetails) {
+    if let Some(policy) = method.result_policy {
+        writeln!(out, "{INDENT}{}", result_policy(policy)).unwrap();
+    }
+    write!(out, "{INDENT}fn {}", signature(&method.data)).unwrap();
+    if let Some(use_path) = &method.use_path {
+        write!(out, " use {}", path(use_path)).unwrap();
+    }
+    // The mutability of the deref is that of the receiver.
+    if let Some((ty, _)) = &method.deref {
+        write!(out, " deref {ty}").unwrap();
+    }
+    out.push_str(";\n");
+}
+
+fn print_trait(out: &mut String, tr: &ZngurTrait) {
+    write!(out, "trait {}", tr.tr).unwrap();
+    if !tr.supertraits.is_empty() {
+        write!(out, ": {}", tr.supertraits.iter().join(" + ")).unwrap();
+    }
+    out.push_str(" {\n");
+    for method in &tr.methods {
+        writeln!(out, "{INDENT}fn {};", signature(method)).unwrap();
+    }
+    out.push_str("}\n");
+}
+
+fn print_fn(out: &mut String, indent: &str, func: &ZngurFn) {
+    if let Some(policy) = func.result_policy {
+        writeln!(out, "{indent}{}", result_policy(policy)).unwrap();
+    }
+    let method = ZngurMethod {
+        name: func.path.path.last().unwrap().clone(),
+        generics: func.path.generics.clone(),
+        receiver: ZngurMethodReceiver::Static,
+        inputs: func.inputs.clone(),
+        output: func.output.clone(),
+    };
+    writeln!(out, "{indent}fn {};", signature(&method)).unwrap();
+}
+
+fn print_extern_cpp_method(
+    out: &mut String,
+    indent: &str,
+    method: &ZngurMethod,
+    policy: CppExceptionPolicy,
+) {
+    match policy {
+        CppExceptionPolicy::Abort => (),
+        CppExceptionPolicy::Panic => writeln!(out, "{indent}#cpp_exception(panic)").unwrap(),
+        CppExceptionPolicy::Result => {
+            writeln!(out, "{indent}#cpp_exception(result)").unwrap();
+            writeln!(
+                out,
+                "{indent}fn {} -> ::std::result::Result<{}, ZngurCppException>;",
+                signature_without_output(method),
+                method.output
+            )
+            .unwrap();
+            return;
+        }
+    }
+    writeln!(out, "{indent}fn {};", signature(method)).unwrap();
+}
+
+fn print_extern_cpp_impl(out: &mut String, imp: &ZngurExternCppImpl) {
+    write!(out, "{INDENT}impl ").unwrap();
+    if let Some(tr) = &imp.tr {
+        write!(out, "{tr} for ").unwrap();
+    }
+    writeln!(out, "{} {{", imp.ty).unwrap();
+    for method in &imp.methods {
+        print_extern_cpp_method(
+            out,
+            &INDENT.repeat(2),
+            &method.data,
+            method.exception_policy,
+        );
+    }
+    writeln!(out, "{INDENT}}}").unwrap();
+}
+
+impl ParsedZngFile<'_> {
+    /// Prints `spec` as a .zng file, which [`ParsedZngFile::parse`] parses back to `spec`.
+    pub fn print(spec: &ZngurSpec) -> String {
+        let mut items = vec![];
+        let mut directives = String::new();
+        if spec.convert_panic_to_exception.0 {
+            directives.push_str("#convert_panic_to_exception\n");
+        }
+        match &spec.heap_allocator {
+            HeapAllocator::Global => (),
+            HeapAllocator::Rust(path) => {
+                writeln!(directives, "#heap_allocator rust \"{path}\"").unwrap()
+            }
+            HeapAllocator::Cpp { alloc, free } => {
+                writeln!(directives, "#heap_allocator cpp \"{alloc}\" \"{free}\"").unwrap()
+            }
+        }
+        if !spec.additional_includes.0.is_empty() {
+            writeln!(
+                directives,
+                "#cpp_additional_includes \"{}\"",
+                spec.additional_includes.0
+            )
+            .unwrap();
+        }
+        if !directives.is_empty() {
+            items.push(directives);
+        }
+        // The unit type is declared implicitly.
+        for ty in spec.types.iter().filter(|x| x.ty != RustType::UNIT) {
+            let mut out = String::new();
+            print_type(&mut out, ty);
+            items.push(out);
+        }
+        for tr in spec.traits.values().sorted_by_key(|x| x.tr.to_string()) {
+            let mut out = String::new();
+            print_trait(&mut out, tr);
+            items.push(out);
+        }
+        let mut mods: Vec<(&[String], Vec<&ZngurFn>)> = vec![];
+        for func in &spec.funcs {
+            let mod_path = &func.path.path[..func.path.path.len() - 1];
+            match mods.iter_mut().find(|x| x.0 == mod_path) {
+                Some((_, funcs)) => funcs.push(func),
+                None => mods.push((mod_path, vec![func])),
+            }
+        }
+        for (mod_path, funcs) in mods {
+            let mut out = String::new();
+            if mod_path.is_empty() {
+                for func in funcs {
+                    print_fn(&mut out, "", func);
+                }
+            } else {
+                writeln!(out, "mod {} {{", path(mod_path)).unwrap();
+                for func in funcs {
+                    print_fn(&mut out, INDENT, func);
+                }
+                out.push_str("}\n");
+            }
+            items.push(out);
+        }
+        if !spec.extern_cpp_funcs.is_empty() || !spec.extern_cpp_impls.is_empty() {
+            let mut out = String::from("extern \"C++\" {\n");
+            for func in &spec.extern_cpp_funcs {
+                let method = ZngurMethod {
+                    name: func.name.clone(),
+                    generics: vec![],
+                    receiver: ZngurMethodReceiver::Static,
+                    inputs: func.inputs.clone(),
+                    output: func.output.clone(),
+                };
+                print_extern_cpp_method(&mut out, INDENT, &method, func.exception_policy);
+            }
+            for imp in &spec.extern_cpp_impls {
+                print_extern_cpp_impl(&mut out, imp);
+            }
+            out.push_str("}\n");
+            items.push(out);
+        }
+        items.join("\n")
+    }
+}
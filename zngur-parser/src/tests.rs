@@ -1,9 +1,10 @@
 use std::panic::catch_unwind;
 
 use expect_test::{Expect, expect};
-use zngur_def::{RustPathAndGenerics, RustType};
+use itertools::Itertools;
+use zngur_def::{Merge, RustPathAndGenerics, RustType};
 
-use crate::{ImportResolver, ParsedZngFile};
+use crate::{ImportResolver, ParsedZngFile, SpecItem};
 
 fn check_success(zng: &str) {
     let _ = ParsedZngFile::parse_str(zng);
@@ -112,6 +113,158 @@ type ::std::string::String {
     );
 }
 
+#[test]
+fn heap_allocated_inline_size_should_be_positive() {
+    check_fail(
+        r#"
+type ::std::string::String {
+    #heap_allocated(inline = 0);
+}
+    "#,
+        expect![[r#"
+            Error: Inline size should be greater than zero
+               ╭─[test.zng:3:21]
+               │
+             3 │     #heap_allocated(inline = 0);
+               │                     ───┬──  
+               │                        ╰──── Inline size should be greater than zero
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn enum_needs_named_constructors() {
+    check_fail(
+        r#"
+type crate::Message {
+    #layout(size = 24, align = 8);
+    #enum;
+
+    constructor(i32);
+}
+    "#,
+        expect![[r#"
+            Error: Enums can only have named constructors
+               ╭─[test.zng:4:5]
+               │
+             4 │     #enum;
+               │     ───┬──  
+               │        ╰──── Enums can only have named constructors
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn duplicate_heap_allocator() {
+    check_fail(
+        r#"
+#heap_allocator rust "crate::ARENA"
+#heap_allocator cpp "arena_alloc" "arena_free"
+type ::std::string::String {
+    #heap_allocated(inline = 32);
+}
+    "#,
+        expect![[r#"
+            Error: Heap allocator is already declared
+               ╭─[test.zng:3:2]
+               │
+             3 │ #heap_allocator cpp "arena_alloc" "arena_free"
+               │  ──────────────────────┬──────────────────────  
+               │                        ╰──────────────────────── Heap allocator is already declared
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn trait_with_supertraits() {
+    let spec = ParsedZngFile::parse_str(
+        r#"
+trait crate::Named {
+    fn name(&self) -> ::std::string::String;
+}
+
+mod crate {
+    trait Shape: Named + Colored {
+        fn area(&self) -> f64;
+    }
+
+    trait Colored {
+        fn color(&self) -> u32;
+    }
+}
+    "#,
+    );
+    let shape = spec
+        .traits
+        .values()
+        .find(|x| x.tr.to_string() == "crate::Shape")
+        .unwrap();
+    expect!["crate::Named, crate::Colored"].assert_eq(&shape.supertraits.iter().join(", "));
+}
+
+#[test]
+fn trait_with_undeclared_supertrait() {
+    check_fail(
+        r#"
+trait crate::Shape: ::std::fmt::Debug {
+    fn area(&self) -> f64;
+}
+    "#,
+        expect![[r#"
+            Error: Supertrait `::std::fmt::Debug` is not declared. Declare it with a `trait` block. Traits that C++ can't implement, like `Debug`, can't be supertraits.
+               ╭─[test.zng:2:21]
+               │
+             2 │ trait crate::Shape: ::std::fmt::Debug {
+               │                     ────────┬────────  
+               │                             ╰────────── Supertrait `::std::fmt::Debug` is not declared. Declare it with a `trait` block. Traits that C++ can't implement, like `Debug`, can't be supertraits.
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn supertrait_declared_in_import() {
+    let resolver = MockFilesystem::new(vec![(
+        "./named.zng",
+        "trait crate::Named { fn name(&self) -> ::std::string::String; }",
+    )]);
+    let spec = ParsedZngFile::parse_str_with_resolver(
+        r#"
+import "./named.zng";
+
+trait crate::Shape: crate::Named {
+    fn area(&self) -> f64;
+}
+    "#,
+        &resolver,
+    );
+    assert_eq!(spec.traits.len(), 2);
+}
+
+#[test]
+fn trait_with_generic_method() {
+    check_fail(
+        r#"
+trait crate::Shape {
+    fn area(&self) -> f64;
+    fn scaled<f32>(&self, f32) -> f64;
+}
+    "#,
+        expect![[r#"
+            Error: Generic methods of traits can't be implemented in C++. Give them a default body with `where Self: Sized` in Rust, and remove them from here.
+               ╭─[test.zng:4:5]
+               │
+             4 │     fn scaled<f32>(&self, f32) -> f64;
+               │     ────────────────┬────────────────  
+               │                     ╰────────────────── Generic methods of traits can't be implemented in C++. Give them a default body with `where Self: Sized` in Rust, and remove them from here.
+            ───╯
+        "#]],
+    );
+}
+
 #[test]
 fn cpp_ref_should_not_need_layout_info() {
     check_fail(
@@ -141,6 +294,383 @@ type crate::Way {
     );
 }
 
+#[test]
+fn cpp_std_bridge_is_parsed() {
+    let parsed = ParsedZngFile::parse_str(
+        r#"
+type ::std::string::String {
+    #layout(size = 24, align = 8);
+    #cpp_std_bridge;
+}
+
+type ::std::vec::Vec<u8> {
+    #layout(size = 24, align = 8);
+}
+    "#,
+    );
+    let bridged = parsed
+        .types
+        .iter()
+        .map(|ty| (ty.ty.to_string(), ty.cpp_std_bridge))
+        .collect::<Vec<_>>();
+    expect![[r#"
+        [
+            (
+                "::std::string::String",
+                true,
+            ),
+            (
+                "::std::vec::Vec::<u8>",
+                false,
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(&bridged);
+}
+
+#[test]
+fn cpp_std_bridge_on_unsupported_type() {
+    check_fail(
+        r#"
+type ::std::vec::Vec<bool> {
+    #layout(size = 24, align = 8);
+    #cpp_std_bridge;
+}
+    "#,
+        expect![[r#"
+            Error: `#cpp_std_bridge` is only supported for `String`, `Vec<T>` with a numeric `T` and `Option<T>`
+               ╭─[test.zng:4:5]
+               │
+             4 │     #cpp_std_bridge;
+               │     ────────┬───────  
+               │             ╰───────── `#cpp_std_bridge` is only supported for `String`, `Vec<T>` with a numeric `T` and `Option<T>`
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn fields_without_offset_are_parsed() {
+    let parsed = ParsedZngFile::parse_str(
+        r#"
+type crate::Segment {
+    #heap_allocated;
+
+    field 0 (offset = 0, type = crate::Point);
+    field 1: crate::Point;
+    field 1.y: i32;
+    field inner.x: i32;
+}
+    "#,
+    );
+    let fields = parsed.types[0]
+        .fields
+        .iter()
+        .map(|field| (field.name.as_str(), field.offset))
+        .collect::<Vec<_>>();
+    expect![[r#"
+        [
+            (
+                "0",
+                Some(
+                    0,
+                ),
+            ),
+            (
+                "1",
+                None,
+            ),
+            (
+                "1.y",
+                None,
+            ),
+            (
+                "inner.x",
+                None,
+            ),
+        ]
+    "#]]
+    .assert_debug_eq(&fields);
+}
+
+#[test]
+fn result_policy_on_non_result() {
+    check_fail(
+        r#"
+type ::std::string::String {
+    #layout(size = 24, align = 8);
+
+    #result_as_exception
+    fn len(&self) -> usize;
+}
+    "#,
+        expect![[r#"
+            Error: Result policy needs a method returning `Result`
+               ╭─[test.zng:5:5]
+               │
+             5 │ ╭─▶     #result_as_exception
+             6 │ ├─▶     fn len(&self) -> usize;
+               │ │                                 
+               │ ╰───────────────────────────────── Result policy needs a method returning `Result`
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn result_policy_on_non_result_fn_and_type() {
+    check_fail(
+        r#"
+mod crate {
+    #result_as_expected
+    fn len(&str) -> usize;
+}
+    "#,
+        expect![[r#"
+            Error: Result policy needs a function returning `Result`
+               ╭─[test.zng:4:5]
+               │
+             4 │     fn len(&str) -> usize;
+               │     ──────────┬──────────  
+               │               ╰──────────── Result policy needs a function returning `Result`
+            ───╯
+        "#]],
+    );
+    check_fail(
+        r#"
+type ::std::option::Option<i32> {
+    #layout(size = 8, align = 4);
+    #result_as_exception;
+}
+    "#,
+        expect![[r#"
+            Error: Result policy is only allowed on `Result` types
+               ╭─[test.zng:4:5]
+               │
+             4 │     #result_as_exception;
+               │     ──────────┬──────────  
+               │               ╰──────────── Result policy is only allowed on `Result` types
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn result_policy_on_undeclared_result() {
+    check_fail(
+        r#"
+type ::std::string::String {
+    #layout(size = 24, align = 8);
+
+    #result_as_exception
+    fn parse(&self) -> ::std::result::Result<i32, ()>;
+}
+    "#,
+        expect![[r#"
+            Error: Type `::std::result::Result::<i32, ()>` is used with a result policy, but it is not declared. Declare it with a `type` block.
+               ╭─[test.zng:5:5]
+               │
+             5 │ ╭─▶     #result_as_exception
+             6 │ ├─▶     fn parse(&self) -> ::std::result::Result<i32, ()>;
+               │ │                                                            
+               │ ╰──────────────────────────────────────────────────────────── Type `::std::result::Result::<i32, ()>` is used with a result policy, but it is not declared. Declare it with a `type` block.
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn result_declared_in_import() {
+    let resolver = MockFilesystem::new(vec![(
+        "./result.zng",
+        "type ::std::result::Result<i32, ()> { #layout(size = 8, align = 4); }",
+    )]);
+    let spec = ParsedZngFile::parse_str_with_resolver(
+        r#"
+import "./result.zng";
+
+mod crate {
+    #result_as_expected
+    fn parse(&str) -> ::std::result::Result<i32, ()>;
+}
+    "#,
+        &resolver,
+    );
+    assert_eq!(spec.types.len(), 1);
+    assert_eq!(spec.funcs.len(), 1);
+}
+
+#[test]
+fn cpp_exception_result_needs_result() {
+    check_fail(
+        r#"
+extern "C++" {
+    #cpp_exception(result)
+    fn parse_port(&str) -> u16;
+}
+    "#,
+        expect![[r#"
+            Error: `#cpp_exception(result)` needs a function returning `Result<T, ZngurCppException>`
+               ╭─[test.zng:4:5]
+               │
+             4 │     fn parse_port(&str) -> u16;
+               │     ─────────────┬────────────  
+               │                  ╰────────────── `#cpp_exception(result)` needs a function returning `Result<T, ZngurCppException>`
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn cpp_exception_result_needs_policy() {
+    check_fail(
+        r#"
+extern "C++" {
+    fn parse_port(&str) -> ::std::result::Result<u16, ZngurCppException>;
+}
+    "#,
+        expect![[r#"
+            Error: A function returning `Result<T, ZngurCppException>` needs `#cpp_exception(result)`
+               ╭─[test.zng:3:5]
+               │
+             3 │     fn parse_port(&str) -> ::std::result::Result<u16, ZngurCppException>;
+               │     ──────────────────────────────────┬─────────────────────────────────  
+               │                                       ╰─────────────────────────────────── A function returning `Result<T, ZngurCppException>` needs `#cpp_exception(result)`
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn cpp_exception_outside_extern_cpp_result() {
+    check_fail(
+        r#"
+type crate::Foo {
+    #layout(size = 1, align = 1);
+
+    fn error() -> ZngurCppException;
+}
+    "#,
+        expect![[r#"
+            Error: `ZngurCppException` can only be the error type of a `Result` returned from an `extern "C++"` function
+               ╭─[test.zng:5:19]
+               │
+             5 │     fn error() -> ZngurCppException;
+               │                   ────────┬────────  
+               │                           ╰────────── `ZngurCppException` can only be the error type of a `Result` returned from an `extern "C++"` function
+            ───╯
+        "#]],
+    );
+    check_fail(
+        r#"
+extern "C++" {
+    #cpp_exception(result)
+    fn parse_port(Box<ZngurCppException>) -> ::std::result::Result<u16, ZngurCppException>;
+}
+    "#,
+        expect![[r#"
+            Error: `ZngurCppException` can only be the error type of a `Result` returned from an `extern "C++"` function
+               ╭─[test.zng:4:23]
+               │
+             4 │     fn parse_port(Box<ZngurCppException>) -> ::std::result::Result<u16, ZngurCppException>;
+               │                       ────────┬────────  
+               │                               ╰────────── `ZngurCppException` can only be the error type of a `Result` returned from an `extern "C++"` function
+            ───╯
+        "#]],
+    );
+    check_fail(
+        r#"
+extern "C++" {
+    #cpp_exception(result)
+    fn parse_port(&str) -> crate::Either<u16, ZngurCppException>;
+}
+    "#,
+        expect![[r#"
+            Error: `ZngurCppException` can only be the error type of a `Result` returned from an `extern "C++"` function
+               ╭─[test.zng:4:5]
+               │
+             4 │     fn parse_port(&str) -> crate::Either<u16, ZngurCppException>;
+               │     ──────────────────────────────┬─────────────────────────────  
+               │                                   ╰─────────────────────────────── `ZngurCppException` can only be the error type of a `Result` returned from an `extern "C++"` function
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn cpp_exception_result_through_alias() {
+    let spec = ParsedZngFile::parse_str(
+        r#"
+use ::std::result::Result as Res;
+
+extern "C++" {
+    #cpp_exception(result)
+    fn parse_port(&str) -> Res::<u16, ZngurCppException>;
+}
+    "#,
+    );
+    let func = &spec.extern_cpp_funcs[0];
+    assert_eq!(func.output.to_string(), "u16");
+    assert_eq!(func.exception_policy, zngur_def::CppExceptionPolicy::Result);
+}
+
+#[test]
+fn source_map_locates_items() {
+    let (_, source_map) = ParsedZngFile::parse_str_with_source_map(
+        r#"
+mod crate {
+    type Point {
+        #heap_allocated;
+
+        field x: i32;
+        fn norm(&self) -> f64;
+    }
+
+    fn origin() -> Point;
+}
+    "#,
+    );
+    let point = RustType::Adt(RustPathAndGenerics {
+        path: vec!["crate".to_owned(), "Point".to_owned()],
+        generics: vec![],
+        named_generics: vec![],
+    });
+    let mut out = vec![];
+    for item in [
+        SpecItem::Field(point.clone(), "x".to_owned()),
+        SpecItem::Method(point, "norm".to_owned()),
+        SpecItem::Fn(vec!["crate".to_owned(), "origin".to_owned()]),
+    ] {
+        source_map
+            .write_error(&item, "Mismatch", "declared here", &mut out)
+            .unwrap();
+    }
+    expect![[r#"
+        Error: Mismatch
+           ╭─[test.zng:6:9]
+           │
+         6 │         field x: i32;
+           │         ──────┬──────  
+           │               ╰──────── declared here
+        ───╯
+        Error: Mismatch
+           ╭─[test.zng:7:9]
+           │
+         7 │         fn norm(&self) -> f64;
+           │         ───────────┬──────────  
+           │                    ╰──────────── declared here
+        ───╯
+        Error: Mismatch
+            ╭─[test.zng:10:5]
+            │
+         10 │     fn origin() -> Point;
+            │     ──────────┬─────────  
+            │               ╰─────────── declared here
+        ────╯
+    "#]]
+    .assert_eq(&String::from_utf8(strip_ansi_escapes::strip(out)).unwrap());
+}
+
 #[test]
 fn alias_expands_correctly() {
     let parsed = ParsedZngFile::parse_str(
@@ -397,3 +927,669 @@ type A {
         "#,
     );
 }
+
+fn check_format(zng: &str, expected: Expect) {
+    let formatted = ParsedZngFile::format(std::path::PathBuf::from("test.zng"), zng);
+    expected.assert_eq(&formatted);
+    assert_eq!(
+        ParsedZngFile::format(std::path::PathBuf::from("test.zng"), &formatted),
+        formatted,
+        "formatting is not idempotent",
+    );
+}
+
+#[test]
+fn format_keeps_comments() {
+    check_format(
+        r#"
+// The header of the file.
+
+#convert_panic_to_exception
+use ::std::vec::Vec as Vec;
+use ::std::string::String as String; // Strings
+  use crate::Foo as Foo;
+
+mod ::std {
+      // Options
+    type option::Option<i32> {
+        fn unwrap(self) -> i32;
+
+        wellknown_traits(Debug);
+#layout(size = 8, align = 4); // measured on x86_64
+    } // end of Option
+
+
+    type Vec<i32>
+    {
+  #layout(size = 24, align = 8);
+        fn   get( &self , usize )->option::Option<&i32>;
+        // Nothing else yet
+    }
+}
+
+type crate::Foo {
+    #layout(size = 8, align = 8);
+    constructor { x : i32, y: ( i32 , i32 ) };
+    field x (offset = 0, type = i32);
+    field y.0: i32;
+    #result_as_exception fn try_new(i32) -> ::std::result::Result<crate::Foo, ::std::string::String>;
+}
+
+extern "C++" {
+    #cpp_exception(result) fn parse(&str) -> ::std::result::Result<crate::Foo, ZngurCppException>;
+}
+// The end.
+"#,
+        expect![[r#"
+            // The header of the file.
+
+            #convert_panic_to_exception
+            use ::std::string::String as String; // Strings
+            use ::std::vec::Vec as Vec;
+            use crate::Foo as Foo;
+
+            mod ::std {
+                // Options
+                type option::Option<i32> {
+                    #layout(size = 8, align = 4); // measured on x86_64
+                    wellknown_traits(Debug);
+                    fn unwrap(self) -> i32;
+                } // end of Option
+
+                type Vec<i32> {
+                    #layout(size = 24, align = 8);
+                    fn get(&self, usize) -> option::Option<&i32>;
+                    // Nothing else yet
+                }
+            }
+
+            type crate::Foo {
+                #layout(size = 8, align = 8);
+                constructor { x: i32, y: (i32, i32) };
+                field x (offset = 0, type = i32);
+                field y.0: i32;
+                #result_as_exception
+                fn try_new(i32) -> ::std::result::Result<crate::Foo, ::std::string::String>;
+            }
+
+            extern "C++" {
+                #cpp_exception(result)
+                fn parse(&str) -> ::std::result::Result<crate::Foo, ZngurCppException>;
+            }
+            // The end.
+        "#]],
+    );
+}
+
+#[test]
+fn format_keeps_detached_comments_before_aliases() {
+    check_format(
+        r#"// header
+use ::std::vec::Vec as V;
+use ::a::B as A;
+
+mod ::std {
+    // About the aliases.
+
+    use string::String as S;
+    // The C++ type of `Option`.
+    use option::Option as O;
+}
+"#,
+        expect![[r#"
+            // header
+            use ::a::B as A;
+            use ::std::vec::Vec as V;
+
+            mod ::std {
+                // About the aliases.
+
+                // The C++ type of `Option`.
+                use option::Option as O;
+                use string::String as S;
+            }
+        "#]],
+    );
+}
+
+#[test]
+fn format_splits_long_signatures() {
+    check_format(
+        r#"
+type ::std::vec::IntoIter<i32> {
+    #layout(size = 32, align = 8);
+
+    fn map<i32, Box<dyn Fn(i32) -> i32>>(self, Box<dyn Fn(i32) -> i32>) -> ::std::iter::Map<::std::vec::IntoIter<i32>, Box<dyn Fn(i32) -> i32>> use ::std::iter::Iterator;
+}
+"#,
+        expect![[r#"
+            type ::std::vec::IntoIter<i32> {
+                #layout(size = 32, align = 8);
+
+                fn map<i32, Box<dyn Fn(i32) -> i32>>(self, Box<dyn Fn(i32) -> i32>)
+                    -> ::std::iter::Map<::std::vec::IntoIter<i32>, Box<dyn Fn(i32) -> i32>>
+                    use ::std::iter::Iterator;
+            }
+        "#]],
+    );
+}
+
+#[test]
+fn format_syntax_error() {
+    let r = catch_unwind(|| {
+        ParsedZngFile::format(std::path::PathBuf::from("test.zng"), "type X { fn f(; }")
+    });
+    match r {
+        Ok(_) => panic!("Formatting succeeded but we expected fail"),
+        Err(e) => match e.downcast::<ErrorText>() {
+            Ok(t) => expect![[r#"
+                Error: found ';' expected something else, 'Box', '(', '[', '::', 'crate', '&', '*', 'dyn', or ')'
+                   ╭─[test.zng:1:15]
+                   │
+                 1 │ type X { fn f(; }
+                   │               ┬  
+                   │               ╰── found ';' expected something else, 'Box', '(', '[', '::', 'crate', '&', '*', 'dyn', or ')'
+                ───╯
+            "#]].assert_eq(&t.0),
+            Err(e) => std::panic::resume_unwind(e),
+        },
+    }
+}
+
+#[test]
+fn analyze_records_references_and_errors() {
+    let resolver = MockFilesystem::new(vec![(
+        "./imported.zng",
+        r#"
+type crate::Foo {
+    #layout(size = 1, align = 1);
+}
+"#,
+    )]);
+    let analysis = ParsedZngFile::analyze(
+        std::path::PathBuf::from("test.zng"),
+        r#"
+import "./imported.zng";
+use ::std::string::String as Str;
+
+mod ::std::vec {
+    type Vec<Str> {
+        fn len(&self) -> usize;
+    }
+}
+
+fn make() -> crate::Foo;
+"#,
+        &resolver,
+    );
+    let mut out = String::new();
+    let text = |file: &std::path::Path, range: &std::ops::Range<usize>| {
+        &analysis.sources[file][range.clone()]
+    };
+    for x in &analysis.diagnostics {
+        out += &format!(
+            "error {} `{}`: {}\n",
+            x.file.display(),
+            text(&x.file, &x.range),
+            x.value
+        );
+    }
+    for x in &analysis.declarations {
+        let item = match &x.value {
+            SpecItem::Type(ty) => format!("type {ty}"),
+            SpecItem::Method(ty, name) => format!("method {ty}::{name}"),
+            SpecItem::Fn(path) => format!("fn {}", path.join("::")),
+            x => format!("{x:?}"),
+        };
+        out += &format!(
+            "declared {} `{}`: {item}\n",
+            x.file.display(),
+            text(&x.file, &x.range)
+        );
+    }
+    for x in &analysis.references {
+        out += &format!(
+            "path {} `{}`: {}\n",
+            x.file.display(),
+            text(&x.file, &x.range),
+            x.value.join("::")
+        );
+    }
+    for x in &analysis.imports {
+        out += &format!("import {} `{}`\n", x.file.display(), x.value.display());
+    }
+    expect![[r#"
+        error test.zng `Vec<Str>`: No layout policy found for this type. Use one of `#layout(size = X, align = Y)`, `#heap_allocated` or `#only_by_ref`.
+        declared test.zng `Vec<Str>`: type ::std::vec::Vec::<::std::string::String>
+        declared test.zng `fn len(&self) -> usize;`: method ::std::vec::Vec::<::std::string::String>::len
+        declared test.zng `fn make() -> crate::Foo`: fn make
+        declared ./imported.zng `crate::Foo`: type crate::Foo
+        path test.zng `::std::vec`: std::vec
+        path test.zng `Vec`: std::vec::Vec
+        path test.zng `Str`: std::string::String
+        path test.zng `crate::Foo`: crate::Foo
+        path ./imported.zng `crate::Foo`: crate::Foo
+        import test.zng `./imported.zng`
+    "#]].assert_eq(&out);
+}
+
+fn check_print(zng: &str, expected: Expect) {
+    let spec = ParsedZngFile::parse_str(zng);
+    let printed = ParsedZngFile::print(&spec);
+    expected.assert_eq(&printed);
+    assert_eq!(ParsedZngFile::parse_str(&printed), spec);
+}
+
+#[test]
+fn print_round_trips() {
+    check_print(
+        r#"
+#convert_panic_to_exception
+#heap_allocator cpp "my_alloc" "my_free"
+#cpp_additional_includes "
+    #include <iostream>
+"
+use ::std::string::String as String;
+
+mod crate {
+    type Point {
+        #layout(size = 8, align = 4);
+        wellknown_traits(Debug, Copy);
+        constructor { x: i32, y: i32 };
+        field x (offset = 0, type = i32);
+        field y: i32;
+        fn norm(&self) -> f64;
+        fn scale(&mut self, i32);
+        fn into_string(self) -> String;
+    }
+
+    type Shape {
+        #heap_allocated(inline = 16);
+        #enum;
+        constructor Circle(f64);
+        constructor Empty;
+        constructor Rect { w: f64, h: f64 };
+        fn area(&self) -> f64 use crate::shape_area;
+        #result_as_expected
+        fn check(&self) -> ::std::result::Result<(), String>;
+    }
+
+    type Wrapper {
+        #only_by_ref;
+        fn get(&self) -> i32 deref crate::Inner;
+    }
+
+    fn make<i32>(i32, &[u8]) -> Box<dyn Fn(i32) -> i32 + Send>;
+    #result_as_exception
+    fn parse(&str) -> ::std::result::Result<crate::Point, String>;
+
+    trait Named {
+        fn name(&self) -> String;
+    }
+
+    trait Visitor: Named + crate::Resettable {
+        fn visit(&mut self, *const u8, *mut i64);
+    }
+
+    trait Resettable {
+        fn reset(&mut self);
+    }
+}
+
+type str {
+    wellknown_traits(?Sized);
+    fn len(&self) -> usize;
+}
+
+type ::std::result::Result<(), String> {
+    #layout(size = 24, align = 8);
+    #result_as_exception;
+}
+
+type ::std::result::Result<crate::Point, String> {
+    #layout(size = 24, align = 8);
+}
+
+type crate::Handle {
+    #cpp_ref "::cpp::Handle";
+}
+
+type crate::Value {
+    #layout(size = 16, align = 8);
+    #cpp_value "0" "::cpp::Value";
+}
+
+type ::std::vec::Vec<u8> {
+    #layout(size = 24, align = 8);
+    #cpp_std_bridge;
+}
+
+type (i32, bool) {
+    #layout(size = 8, align = 4);
+    wellknown_traits(Copy);
+}
+
+mod ::std::iter {
+    trait Iterator<Item = i32> {
+        fn next(&mut self) -> ::std::option::Option<i32>;
+    }
+}
+
+fn top_level();
+
+extern "C++" {
+    fn log(&str);
+    #cpp_exception(result)
+    fn read() -> ::std::result::Result<i32, ZngurCppException>;
+    impl crate::Visitor for crate::Point {
+        #cpp_exception(panic)
+        fn visit(&mut self, *const u8, *mut i64);
+    }
+    impl crate::Point {
+        fn from_cpp(ZngurCppOpaqueOwnedObject) -> crate::Point;
+    }
+}
+"#,
+        expect![[r##"
+            #convert_panic_to_exception
+            #heap_allocator cpp "my_alloc" "my_free"
+            #cpp_additional_includes "
+                #include <iostream>
+            "
+
+            type crate::Point {
+                #layout(size = 8, align = 4);
+                wellknown_traits(Debug, Copy);
+                constructor { x: i32, y: i32 };
+                field x (offset = 0, type = i32);
+                field y: i32;
+                fn norm(&self) -> f64;
+                fn scale(&mut self, i32);
+                fn into_string(self) -> ::std::string::String;
+            }
+
+            type crate::Shape {
+                #heap_allocated(inline = 16);
+                #enum;
+                constructor Circle(f64);
+                constructor Empty;
+                constructor Rect { w: f64, h: f64 };
+                fn area(&self) -> f64 use crate::shape_area;
+                #result_as_expected
+                fn check(&self) -> ::std::result::Result::<(), ::std::string::String>;
+            }
+
+            type crate::Wrapper {
+                #only_by_ref;
+                fn get(&self) -> i32 deref crate::Inner;
+            }
+
+            type str {
+                wellknown_traits(?Sized);
+                fn len(&self) -> usize;
+            }
+
+            type ::std::result::Result::<(), ::std::string::String> {
+                #layout(size = 24, align = 8);
+                #result_as_exception;
+            }
+
+            type ::std::result::Result::<crate::Point, ::std::string::String> {
+                #layout(size = 24, align = 8);
+            }
+
+            type crate::Handle {
+                #cpp_ref "::cpp::Handle";
+            }
+
+            type crate::Value {
+                #layout(size = 16, align = 8);
+                #cpp_value "0" "::cpp::Value";
+            }
+
+            type ::std::vec::Vec::<u8> {
+                #layout(size = 24, align = 8);
+                #cpp_std_bridge;
+            }
+
+            type (i32, bool) {
+                #layout(size = 8, align = 4);
+                wellknown_traits(Copy);
+            }
+
+            trait ::std::iter::Iterator::<Item = i32> {
+                fn next(&mut self) -> ::std::option::Option::<i32>;
+            }
+
+            trait crate::Named {
+                fn name(&self) -> ::std::string::String;
+            }
+
+            trait crate::Resettable {
+                fn reset(&mut self);
+            }
+
+            trait crate::Visitor: crate::Named + crate::Resettable {
+                fn visit(&mut self, *const u8, *mut i64);
+            }
+
+            mod crate {
+                fn make<i32>(i32, &[u8]) -> Box<dyn Fn(i32) -> i32+ Send>;
+                #result_as_exception
+                fn parse(&str) -> ::std::result::Result::<crate::Point, ::std::string::String>;
+            }
+
+            fn top_level();
+
+            extern "C++" {
+                fn log(&str);
+                #cpp_exception(result)
+                fn read() -> ::std::result::Result<i32, ZngurCppException>;
+                impl crate::Visitor for crate::Point {
+                    #cpp_exception(panic)
+                    fn visit(&mut self, *const u8, *mut i64);
+                }
+                impl crate::Point {
+                    fn from_cpp(ZngurCppOpaqueOwnedObject) -> crate::Point;
+                }
+            }
+        "##]],
+    );
+}
+
+#[test]
+fn fragments_match_zng_file() {
+    let spec = ParsedZngFile::parse_str(
+        r#"
+type ::std::vec::Vec<i32> {
+    #layout(size = 24, align = 8);
+    #result_as_exception
+    fn first(&self) -> ::std::result::Result<&i32, ()>;
+    fn as_slice(&self) -> &[i32] deref [i32];
+}
+
+type ::std::result::Result<&i32, ()> {
+    #layout(size = 8, align = 8);
+}
+"#,
+    );
+    let methods = &spec.types[0].methods;
+    assert_eq!(
+        ParsedZngFile::parse_type("::std::vec::Vec<i32>").unwrap(),
+        spec.types[0].ty
+    );
+    assert_eq!(
+        ParsedZngFile::parse_method(
+            "#result_as_exception fn first(&self) -> ::std::result::Result<&i32, ()>"
+        )
+        .unwrap(),
+        methods[0]
+    );
+    assert_eq!(
+        ParsedZngFile::parse_method("fn as_slice(&self) -> &[i32] deref [i32]").unwrap(),
+        methods[1]
+    );
+    assert_eq!(
+        ParsedZngFile::parse_path("crate::utils").unwrap(),
+        ["crate", "utils"]
+    );
+    expect!["Invalid `Vec<i32`: found end of input expected '=', ',', or '>'"]
+        .assert_eq(&ParsedZngFile::parse_type("Vec<i32").unwrap_err());
+    expect!["Invalid `fn len(self) -> usize deref str`: Deref needs reference receiver"]
+        .assert_eq(&ParsedZngFile::parse_method("fn len(self) -> usize deref str").unwrap_err());
+}
+
+#[test]
+fn merge_specs_like_imports() {
+    let a = r#"
+#heap_allocator rust "crate::ALLOCATOR"
+type crate::Foo {
+    #layout(size = 8, align = 8);
+    fn a(&self);
+}
+mod crate {
+    fn make() -> Foo;
+}
+"#;
+    let b = r#"
+type crate::Foo {
+    #layout(size = 8, align = 8);
+    wellknown_traits(Debug);
+    fn b(&self);
+}
+trait crate::Bar {
+    fn bar(&self);
+}
+"#;
+    let mut spec = ParsedZngFile::parse_str(a);
+    ParsedZngFile::parse_str(b).merge(&mut spec).ok().unwrap();
+    assert_eq!(spec, ParsedZngFile::parse_str(&format!("{a}{b}")));
+}
+
+#[test]
+fn too_large_number() {
+    check_fail(
+        r#"
+type crate::Foo {
+    #layout(size = 100000000000000000000, align = 1);
+}
+    "#,
+        expect![[r#"
+            Error: This number is too large
+               ╭─[test.zng:3:20]
+               │
+             3 │     #layout(size = 100000000000000000000, align = 1);
+               │                    ──────────┬──────────  
+               │                              ╰──────────── This number is too large
+            ───╯
+        "#]],
+    );
+}
+
+#[test]
+fn box_with_two_generics() {
+    check_fail(
+        r#"
+mod crate {
+    fn make() -> Box<i32, i32>;
+}
+    "#,
+        expect![[r#"
+            Error: `Box` takes exactly one type argument
+               ╭─[test.zng:3:18]
+               │
+             3 │     fn make() -> Box<i32, i32>;
+               │                  ──────┬──────  
+               │                        ╰──────── `Box` takes exactly one type argument
+            ───╯
+        "#]],
+    );
+}
+
+#[cfg(feature = "serde")]
+const SERDE_ZNG: &str = r#"
+#heap_allocator rust "crate::ALLOC"
+mod crate {
+    type Point {
+        #layout(size = 8, align = 4);
+        wellknown_traits(Debug, Copy);
+        constructor { x: i32, y: i32 };
+        field x (offset = 0, type = i32);
+        fn norm(&self) -> f64;
+    }
+
+    type Shape {
+        #heap_allocated;
+        #enum;
+        constructor Circle(f64);
+        constructor Empty;
+    }
+
+    trait Named {
+        fn name(&self) -> ::std::string::String;
+    }
+
+    trait Visitor: Named {
+        fn visit(&mut self, &crate::Point);
+    }
+
+    fn make(i32) -> Box<dyn Fn(i32) -> i32>;
+}
+
+type ::std::result::Result<crate::Point, ::std::string::String> {
+    #layout(size = 24, align = 8);
+    #result_as_exception;
+}
+
+extern "C++" {
+    #cpp_exception(result)
+    fn parse(&str) -> ::std::result::Result<crate::Point, ZngurCppException>;
+}
+"#;
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trips() {
+    let spec = ParsedZngFile::parse_str(SERDE_ZNG);
+    let json = serde_json::to_string(&spec).unwrap();
+    assert_eq!(
+        serde_json::from_str::<zngur_def::ZngurSpec>(&json).unwrap(),
+        spec
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_duplicate_traits() {
+    let spec = ParsedZngFile::parse_str(SERDE_ZNG);
+    let mut json = serde_json::to_value(&spec).unwrap();
+    let traits = json["traits"].as_array_mut().unwrap();
+    traits.push(traits[0].clone());
+    let error = serde_json::from_value::<zngur_def::ZngurSpec>(json).unwrap_err();
+    expect!["Trait `crate::Named` is declared more than once"].assert_eq(&error.to_string());
+}
+
+#[test]
+fn debug_sorts_traits() {
+    let zng = r#"
+mod crate {
+    trait Shape {
+        fn area(&self) -> f64;
+    }
+
+    trait Colored {
+        fn color(&self) -> u32;
+    }
+
+    trait Named {
+        fn name(&self) -> ::std::string::String;
+    }
+}
+"#;
+    let first = format!("{:?}", ParsedZngFile::parse_str(zng));
+    for _ in 0..10 {
+        assert_eq!(format!("{:?}", ParsedZngFile::parse_str(zng)), first);
+    }
+    let names = ["Colored", "Named", "Shape"].map(|name| first.find(&format!("\"{name}\"")));
+    assert!(names.iter().all(Option::is_some) && names.is_sorted());
+}
@@ -0,0 +1,470 @@
+use zngur_def::{
+    AdditionalIncludes, ConvertPanicToException, CppRef, CppValue, HeapAllocator, LayoutPolicy,
+    Merge, MergeFailure, RustType, ZngurConstructor, ZngurField, ZngurFn, ZngurMethodDetails,
+    ZngurMethodReceiver, ZngurSpec, ZngurTrait, ZngurType, ZngurWellknownTrait,
+};
+use zngur_generator::ParsedZngFile;
+
+fn expect<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| panic!("{e}"))
+}
+
+fn merge<T: Merge<U>, U>(item: T, into: &mut U, what: impl std::fmt::Display) {
+    if let Err(MergeFailure::Conflict(message)) = item.merge(into) {
+        panic!("Conflicting declarations of {what}: {message}");
+    }
+}
+
+/// Builder for a [`ZngurSpec`], for bindings which are computed in the build script instead of
+/// being written in a zng file. Types, traits and methods are written like in a zng file, but with
+/// full paths, since there are no `use` aliases or `mod` blocks. Invalid ones cause a panic.
+///
+/// Usage:
+/// ```no_run
+/// # use zngur::{SpecBuilder, Zngur};
+/// # let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+/// let mut spec = SpecBuilder::new();
+/// for ty in ["i32", "u8", "::std::string::String"] {
+///     spec = spec.ty(&format!("::std::vec::Vec<{ty}>"), |t| {
+///         t.layout(24, 8)
+///             .method("fn len(&self) -> usize")
+///             .method("fn is_empty(&self) -> bool")
+///     });
+/// }
+/// Zngur::from_spec(spec.build())
+///     .with_cpp_file(out_dir.join("generated.cpp"))
+///     .with_h_file(out_dir.join("generated.h"))
+///     .with_rs_file(out_dir.join("generated.rs"))
+///     .generate();
+/// ```
+#[must_use]
+#[derive(Default)]
+pub struct SpecBuilder {
+    spec: ZngurSpec,
+}
+
+impl SpecBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a type, like a `type` block. Declaring the same type again adds to it.
+    pub fn ty(mut self, ty: &str, f: impl FnOnce(TypeBuilder) -> TypeBuilder) -> Self {
+        let ty = f(TypeBuilder::new(expect(ParsedZngFile::parse_type(ty)))).build();
+        let name = format!("`{}`", ty.ty);
+        merge(ty, &mut self.spec, name);
+        self
+    }
+
+    /// Declares a trait, like a `trait` block.
+    pub fn tr(mut self, tr: &str, f: impl FnOnce(TraitBuilder) -> TraitBuilder) -> Self {
+        let tr = f(TraitBuilder::new(expect(ParsedZngFile::parse_trait(tr)))).tr;
+        let name = format!("`{}`", tr.tr);
+        merge(tr, &mut self.spec, name);
+        self
+    }
+
+    /// Declares a free function of the module at `module`, like `fn add(i32, i32) -> i32` in
+    /// `crate::utils`.
+    pub fn func(mut self, module: &str, method: &str) -> Self {
+        let ZngurMethodDetails {
+            data,
+            use_path,
+            deref,
+            result_policy,
+        } = expect(ParsedZngFile::parse_method(method));
+        if data.receiver != ZngurMethodReceiver::Static || use_path.is_some() || deref.is_some() {
+            panic!("Invalid `{method}`: Free functions can't have `self`, `use` or `deref`");
+        }
+        let mut path = expect(ParsedZngFile::parse_path(module));
+        path.push(data.name);
+        let func = ZngurFn {
+            path: zngur_def::RustPathAndGenerics {
+                path,
+                generics: data.generics,
+                named_generics: vec![],
+            },
+            inputs: data.inputs,
+            output: data.output,
+            result_policy,
+        };
+        merge(func, &mut self.spec, format!("`{method}`"));
+        self
+    }
+
+    /// Like `#convert_panic_to_exception` in the main zng file.
+    pub fn convert_panic_to_exception(mut self) -> Self {
+        self.spec.convert_panic_to_exception = ConvertPanicToException(true);
+        self
+    }
+
+    /// Like `#heap_allocator`.
+    pub fn heap_allocator(mut self, allocator: HeapAllocator) -> Self {
+        merge(allocator, &mut self.spec, "the heap allocator");
+        self
+    }
+
+    /// Like `#cpp_additional_includes`.
+    pub fn additional_includes(mut self, includes: &str) -> Self {
+        merge(
+            AdditionalIncludes(includes.to_owned()),
+            &mut self.spec,
+            "the additional includes",
+        );
+        self
+    }
+
+    /// Adds the items of another spec, like one parsed from a zng file.
+    pub fn with_spec(mut self, spec: ZngurSpec) -> Self {
+        merge(spec, &mut self.spec, "the merged spec");
+        self
+    }
+
+    pub fn build(self) -> ZngurSpec {
+        self.spec
+    }
+}
+
+/// Builder for the items of a type, see [`SpecBuilder::ty`].
+#[must_use]
+pub struct TypeBuilder {
+    ty: ZngurType,
+    layout: Option<LayoutPolicy>,
+}
+
+impl TypeBuilder {
+    fn new(ty: RustType) -> Self {
+        TypeBuilder {
+            ty: ZngurType {
+                ty,
+                layout: LayoutPolicy::OnlyByRef,
+                wellknown_traits: vec![],
+                methods: vec![],
+                constructors: vec![],
+                fields: vec![],
+                cpp_value: None,
+                cpp_ref: None,
+                cpp_std_bridge: false,
+                result_policy: None,
+                is_enum: false,
+            },
+            layout: None,
+        }
+    }
+
+    /// Like `#layout(size = X, align = Y)`.
+    pub fn layout(mut self, size: usize, align: usize) -> Self {
+        self.layout = Some(LayoutPolicy::StackAllocated { size, align });
+        self
+    }
+
+    /// Like `#heap_allocated`.
+    pub fn heap_allocated(mut self) -> Self {
+        self.layout = Some(LayoutPolicy::HeapAllocated { inline_size: None });
+        self
+    }
+
+    /// Like `#only_by_ref`.
+    pub fn only_by_ref(mut self) -> Self {
+        self.layout = Some(LayoutPolicy::OnlyByRef);
+        self
+    }
+
+    /// Like `wellknown_traits(...)`.
+    pub fn wellknown_traits(mut self, traits: &[ZngurWellknownTrait]) -> Self {
+        self.ty.wellknown_traits.extend_from_slice(traits);
+        self
+    }
+
+    /// Declares a method, written like in a `type` block but without the semicolon, for example
+    /// `fn get(&self, usize) -> ::std::option::Option<&i32>`.
+    pub fn method(mut self, method: &str) -> Self {
+        self.ty
+            .methods
+            .push(expect(ParsedZngFile::parse_method(method)));
+        self
+    }
+
+    /// Declares a constructor with the given field names and types. The fields of tuple structs
+    /// are named `0`, `1` and so on.
+    pub fn constructor(mut self, name: Option<&str>, inputs: &[(&str, &str)]) -> Self {
+        self.ty.constructors.push(ZngurConstructor {
+            name: name.map(|x| x.to_owned()),
+            inputs: inputs
+                .iter()
+                .map(|(name, ty)| (name.to_string(), expect(ParsedZngFile::parse_type(ty))))
+                .collect(),
+        });
+        self
+    }
+
+    /// Declares a field. Without an offset, it is taken from `offset_of!`.
+    pub fn field(mut self, name: &str, ty: &str, offset: Option<usize>) -> Self {
+        self.ty.fields.push(ZngurField {
+            name: name.to_owned(),
+            ty: expect(ParsedZngFile::parse_type(ty)),
+            offset,
+        });
+        self
+    }
+
+    /// Like `#cpp_value "field" "cpp_type"`.
+    pub fn cpp_value(mut self, field: &str, cpp_type: &str) -> Self {
+        self.ty.cpp_value = Some(CppValue(field.to_owned(), cpp_type.to_owned()));
+        self
+    }
+
+    /// Like `#cpp_ref "cpp_type"`, which implies a zero sized layout.
+    pub fn cpp_ref(mut self, cpp_type: &str) -> Self {
+        self.ty.cpp_ref = Some(CppRef(cpp_type.to_owned()));
+        self.layout = Some(LayoutPolicy::ZERO_SIZED_TYPE);
+        self
+    }
+
+    /// Applies the implicit parts of a `type` block, like the parser does.
+    fn build(mut self) -> ZngurType {
+        let traits = &mut self.ty.wellknown_traits;
+        let is_unsized = traits.contains(&ZngurWellknownTrait::Unsized);
+        if !is_unsized && !traits.contains(&ZngurWellknownTrait::Copy) {
+            traits.push(ZngurWellknownTrait::Drop);
+        }
+        self.ty.layout = match self.layout {
+            _ if is_unsized => LayoutPolicy::OnlyByRef,
+            Some(layout) => layout,
+            None => panic!(
+                "No layout policy found for `{}`. Use one of `layout`, `heap_allocated` or \
+`only_by_ref`.",
+                self.ty.ty
+            ),
+        };
+        self.ty
+    }
+}
+
+/// Builder for the items of a trait, see [`SpecBuilder::tr`].
+#[must_use]
+pub struct TraitBuilder {
+    tr: ZngurTrait,
+}
+
+impl TraitBuilder {
+    fn new(tr: zngur_def::RustTrait) -> Self {
+        TraitBuilder {
+            tr: ZngurTrait {
+                tr,
+                supertraits: vec![],
+                methods: vec![],
+            },
+        }
+    }
+
+    /// Declares a supertrait, like `trait Foo: Bar`.
+    pub fn supertrait(mut self, tr: &str) -> Self {
+        self.tr
+            .supertraits
+            .push(expect(ParsedZngFile::parse_trait(tr)));
+        self
+    }
+
+    /// Declares a method, like `fn call(&self, i32) -> i32`.
+    pub fn method(mut self, method: &str) -> Self {
+        let details = expect(ParsedZngFile::parse_method(method));
+        if details.use_path.is_some() || details.deref.is_some() || details.result_policy.is_some()
+        {
+            panic!(
+                "Invalid `{method}`: Trait methods can't have `use`, `deref` or a result policy"
+            );
+        }
+        self.tr.methods.push(details.data);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_spec_equals_parsed_spec() {
+        let built = SpecBuilder::new()
+            .ty("::std::vec::Vec<i32>", |t| {
+                t.layout(24, 8)
+                    .wellknown_traits(&[ZngurWellknownTrait::Debug])
+                    .method("fn len(&self) -> usize")
+                    .method("fn push(&mut self, i32)")
+            })
+            .ty("crate::Point", |t| {
+                t.layout(8, 4)
+                    .wellknown_traits(&[ZngurWellknownTrait::Copy])
+                    .constructor(None, &[("x", "i32"), ("y", "i32")])
+                    .field("x", "i32", Some(0))
+                    .field("y", "i32", None)
+            })
+            .ty("crate::Handle", |t| t.cpp_ref("::cpp::Handle"))
+            .tr("crate::Named", |t| {
+                t.method("fn name(&self) -> ::std::string::String")
+            })
+            .tr("crate::Shape", |t| {
+                t.supertrait("crate::Named").method("fn area(&self) -> f64")
+            })
+            .func("crate::utils", "fn add(i32, i32) -> i32")
+            .build();
+        let parsed = ParsedZngFile::parse_str(
+            r#"
+type ::std::vec::Vec<i32> {
+    #layout(size = 24, align = 8);
+    wellknown_traits(Debug);
+    fn len(&self) -> usize;
+    fn push(&mut self, i32);
+}
+
+type crate::Point {
+    #layout(size = 8, align = 4);
+    wellknown_traits(Copy);
+    constructor { x: i32, y: i32 };
+    field x (offset = 0, type = i32);
+    field y: i32;
+}
+
+type crate::Handle {
+    #cpp_ref "::cpp::Handle";
+}
+
+trait crate::Named {
+    fn name(&self) -> ::std::string::String;
+}
+
+trait crate::Shape: crate::Named {
+    fn area(&self) -> f64;
+}
+
+mod crate::utils {
+    fn add(i32, i32) -> i32;
+}
+"#,
+        );
+        assert_eq!(built, parsed);
+    }
+
+    #[test]
+    fn declaring_a_type_again_adds_to_it() {
+        let built = SpecBuilder::new()
+            .ty("::std::vec::Vec<i32>", |t| {
+                t.layout(24, 8).method("fn len(&self) -> usize")
+            })
+            .ty("::std::vec::Vec<i32>", |t| {
+                t.layout(24, 8).method("fn is_empty(&self) -> bool")
+            })
+            .build();
+        let parsed = ParsedZngFile::parse_str(
+            r#"
+type ::std::vec::Vec<i32> {
+    #layout(size = 24, align = 8);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+}
+"#,
+        );
+        assert_eq!(built, parsed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Free functions can't have `self`, `use` or `deref`")]
+    fn func_rejects_self() {
+        let _ = SpecBuilder::new().func("crate", "fn len(&self) -> usize");
+    }
+
+    #[test]
+    #[should_panic(expected = "Free functions can't have `self`, `use` or `deref`")]
+    fn func_rejects_use() {
+        let _ = SpecBuilder::new().func("crate", "fn len() -> usize use crate::size");
+    }
+
+    #[test]
+    #[should_panic(expected = "Free functions can't have `self`, `use` or `deref`")]
+    fn func_rejects_deref() {
+        let _ = SpecBuilder::new().func("crate", "fn len(&self) -> usize deref crate::Inner");
+    }
+
+    #[test]
+    #[should_panic(expected = "Conflicting declarations of `::std::vec::Vec::<i32>`")]
+    fn conflicting_layouts_panic() {
+        let _ = SpecBuilder::new()
+            .ty("::std::vec::Vec<i32>", |t| t.layout(24, 8))
+            .ty("::std::vec::Vec<i32>", |t| t.layout(16, 8));
+    }
+
+    #[test]
+    #[should_panic(expected = "Conflicting declarations of the heap allocator")]
+    fn conflicting_heap_allocators_panic() {
+        let _ = SpecBuilder::new()
+            .heap_allocator(HeapAllocator::Rust("::std::alloc::System".to_owned()))
+            .heap_allocator(HeapAllocator::Rust("crate::Alloc".to_owned()));
+    }
+
+    #[test]
+    fn generator_rejects_unsupported_std_bridge() {
+        let mut spec = SpecBuilder::new()
+            .ty("::std::vec::Vec<bool>", |t| t.layout(24, 8))
+            .build();
+        spec.types[0].cpp_std_bridge = true;
+        let Err(e) = zngur_generator::ZngurGenerator::build_from_zng(spec).render() else {
+            panic!("The bridge of `Vec<bool>` was generated");
+        };
+        assert_eq!(
+            e.0,
+            "`#cpp_std_bridge` is only supported for `String`, `Vec<T>` with a numeric `T` and \
+`Option<T>`, found ::std::vec::Vec::<bool>"
+        );
+    }
+
+    #[test]
+    fn generator_rejects_undeclared_supertrait() {
+        let spec = SpecBuilder::new()
+            .tr("crate::Shape", |t| {
+                t.supertrait("crate::Named").method("fn area(&self) -> f64")
+            })
+            .build();
+        let Err(e) = zngur_generator::ZngurGenerator::build_from_zng(spec).render() else {
+            panic!("The trait was generated without its supertrait");
+        };
+        assert_eq!(
+            e.0,
+            "Supertrait `crate::Named` of `crate::Shape` is not declared. Declare it with a \
+`trait` block."
+        );
+    }
+
+    #[test]
+    fn generator_rejects_undeclared_result_type() {
+        let spec = SpecBuilder::new()
+            .func(
+                "crate",
+                "#result_as_exception fn parse(&str) -> ::std::result::Result<i32, u8>",
+            )
+            .build();
+        let Err(e) = zngur_generator::ZngurGenerator::build_from_zng(spec).render() else {
+            panic!("The result policy was generated for an undeclared type");
+        };
+        assert_eq!(
+            e.0,
+            "Type ::std::result::Result::<i32, u8> is used with a result policy, but it is not \
+declared."
+        );
+    }
+
+    #[test]
+    fn generator_rejects_result_policy_on_other_types() {
+        let mut spec = SpecBuilder::new()
+            .func("crate", "fn len() -> usize")
+            .build();
+        spec.funcs[0].result_policy = Some(zngur_def::ResultPolicy::AsExpected);
+        let Err(e) = zngur_generator::ZngurGenerator::build_from_zng(spec).render() else {
+            panic!("The result policy was generated for `usize`");
+        };
+        assert_eq!(
+            e.0,
+            "Result policy is only supported for functions returning `Result`, found usize"
+        );
+    }
+}
@@ -9,6 +9,13 @@ use std::{
 
 use zngur_generator::{ParsedZngFile, ZngurGenerator};
 
+pub use zngur_generator::InvalidSpec;
+
+mod builder;
+
+pub use builder::{SpecBuilder, TraitBuilder, TypeBuilder};
+pub use zngur_def::{HeapAllocator, Merge, MergeFailure, ZngurSpec, ZngurWellknownTrait};
+
 #[must_use]
 /// Builder for the Zngur generator.
 ///
@@ -23,26 +30,134 @@ use zngur_generator::{ParsedZngFile, ZngurGenerator};
 ///     .generate();
 /// ```
 pub struct Zngur {
-    zng_file: PathBuf,
+    input: Input,
+    extra_inputs: Vec<Input>,
     h_file_path: Option<PathBuf>,
     cpp_file_path: Option<PathBuf>,
     rs_file_path: Option<PathBuf>,
     mangling_base: Option<String>,
     cpp_namespace: Option<String>,
+    split_headers: bool,
+}
+
+enum Input {
+    ZngFile(PathBuf),
+    Str(String),
+    Spec(Box<ZngurSpec>),
+    #[cfg(feature = "json")]
+    JsonFile(PathBuf),
+}
+
+impl Input {
+    /// Names the input in errors, by its path, or by its `index` among the inputs for the inputs
+    /// which are in memory.
+    fn name(&self, index: usize) -> String {
+        match self {
+            Input::ZngFile(path) => path.display().to_string(),
+            Input::Str(_) => format!("the zng text of input {index}"),
+            Input::Spec(_) => format!("the spec of input {index}"),
+            #[cfg(feature = "json")]
+            Input::JsonFile(path) => path.display().to_string(),
+        }
+    }
+
+    fn into_spec(self) -> ZngurSpec {
+        match self {
+            Input::ZngFile(path) => ParsedZngFile::parse(path),
+            Input::Str(zng) => ParsedZngFile::parse_str(&zng),
+            Input::Spec(spec) => *spec,
+            #[cfg(feature = "json")]
+            Input::JsonFile(path) => {
+                let text = std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+                serde_json::from_str(&text)
+                    .unwrap_or_else(|e| panic!("Invalid JSON spec in {}: {e}", path.display()))
+            }
+        }
+    }
 }
 
 impl Zngur {
-    pub fn from_zng_file(zng_file_path: impl AsRef<Path>) -> Self {
+    fn new(input: Input) -> Self {
         Zngur {
-            zng_file: zng_file_path.as_ref().to_owned(),
+            input,
+            extra_inputs: vec![],
             h_file_path: None,
             cpp_file_path: None,
             rs_file_path: None,
             mangling_base: None,
             cpp_namespace: None,
+            split_headers: false,
         }
     }
 
+    pub fn from_zng_file(zng_file_path: impl AsRef<Path>) -> Self {
+        Self::new(Input::ZngFile(zng_file_path.as_ref().to_owned()))
+    }
+
+    /// Uses several zng files, like separate roots of the crates which are linked together, and
+    /// generates a single set of files for them. See [`Zngur::with_zng_file`].
+    ///
+    /// # Panics
+    ///
+    /// If `zng_file_paths` is empty.
+    pub fn from_zng_files(zng_file_paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Self {
+        let mut paths = zng_file_paths.into_iter();
+        let first = paths.next().expect("No zng file path provided");
+        paths.fold(Self::from_zng_file(first), Self::with_zng_file)
+    }
+
+    /// Uses the text of a zng file. Its imports are relative to the current directory.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(zng: &str) -> Self {
+        Self::new(Input::Str(zng.to_owned()))
+    }
+
+    /// Uses a spec built in Rust, for example by [`SpecBuilder`].
+    pub fn from_spec(spec: ZngurSpec) -> Self {
+        Self::new(Input::Spec(Box::new(spec)))
+    }
+
+    /// Uses a spec serialized as JSON, like the output of `zngur dump --json`, instead of a zng
+    /// file.
+    #[cfg(feature = "json")]
+    pub fn from_json_file(json_file_path: impl AsRef<Path>) -> Self {
+        Self::new(Input::JsonFile(json_file_path.as_ref().to_owned()))
+    }
+
+    /// Like [`Zngur::with_zng_file`], for a spec serialized as JSON.
+    #[cfg(feature = "json")]
+    pub fn with_json_file(mut self, json_file_path: impl AsRef<Path>) -> Self {
+        self.extra_inputs
+            .push(Input::JsonFile(json_file_path.as_ref().to_owned()));
+        self
+    }
+
+    /// Adds the items of `spec` to the spec of the input, like an import does.
+    ///
+    /// Errors name the specs by their index among the inputs, where the input of the constructor
+    /// is 0 and the added inputs follow in order.
+    pub fn with_spec(mut self, spec: ZngurSpec) -> Self {
+        self.extra_inputs.push(Input::Spec(Box::new(spec)));
+        self
+    }
+
+    /// Adds the items of another zng file to the spec of the input. Unlike an import, the file is a
+    /// separate root, so it may use `#convert_panic_to_exception`, but like the other items it is
+    /// merged into the single spec, so it converts the panics of the functions of every file.
+    ///
+    /// Types declared in more than one file, like `str` or `Vec<u8>`, are generated once with the
+    /// union of their methods, and the runtime is generated once in the single C++ namespace, so
+    /// the files don't need separate namespaces. Conflicting declarations, like two different
+    /// layouts for a type, or the same function with two different result policies, are errors of
+    /// [`Zngur::try_generate`]. So are specs which set different values for a setting like the C++
+    /// namespace.
+    pub fn with_zng_file(mut self, zng_file_path: impl AsRef<Path>) -> Self {
+        self.extra_inputs
+            .push(Input::ZngFile(zng_file_path.as_ref().to_owned()));
+        self
+    }
+
     pub fn with_h_file(mut self, path: impl AsRef<Path>) -> Self {
         self.h_file_path = Some(path.as_ref().to_owned());
         self
@@ -68,8 +183,34 @@ impl Zngur {
         self
     }
 
+    /// Splits the header into `zngur_runtime.h`, with the parts which don't depend on the
+    /// declared types, and headers for each Rust module, like `rust.std.vec.h` for the types of
+    /// `::std::vec`. The headers are written next to the h file, which includes all of them, and
+    /// a translation unit can include only the headers of the modules it uses.
+    pub fn with_split_headers(mut self) -> Self {
+        self.split_headers = true;
+        self
+    }
+
+    /// Generates the files, and panics if the inputs conflict or describe something which can't
+    /// be generated. See [`Zngur::try_generate`] for handling these errors.
     pub fn generate(self) {
-        let mut file = ZngurGenerator::build_from_zng(ParsedZngFile::parse(self.zng_file));
+        self.try_generate()
+            .unwrap_or_else(|e| panic!("Invalid spec: {e}"));
+    }
+
+    /// Like [`Zngur::generate`], but returns an error if the inputs conflict or describe
+    /// something which can't be generated, like a `#cpp_std_bridge` on an unsupported type. It
+    /// still panics on a missing output path or a failed write.
+    pub fn try_generate(self) -> Result<(), InvalidSpec> {
+        let mut spec = self.input.into_spec();
+        for (index, extra) in self.extra_inputs.into_iter().enumerate() {
+            let name = extra.name(index + 1);
+            if let Err(MergeFailure::Conflict(message)) = extra.into_spec().merge(&mut spec) {
+                return Err(InvalidSpec(format!("Failed to merge {name}: {message}")));
+            }
+        }
+        let mut file = ZngurGenerator::build_from_zng(spec);
 
         let rs_file_path = self.rs_file_path.expect("No rs file path provided");
         let h_file_path = self.h_file_path.expect("No h file path provided");
@@ -80,7 +221,9 @@ impl Zngur {
             .to_string_lossy()
             .into_owned();
 
-        file.0.cpp_namespace = "rust".to_owned();
+        if file.0.cpp_namespace.is_empty() {
+            file.0.cpp_namespace = "rust".to_owned();
+        }
 
         if let Some(cpp_namespace) = self.cpp_namespace {
             file.0.mangling_base = cpp_namespace.clone();
@@ -93,22 +236,31 @@ impl Zngur {
 
         let cpp_namespace = file.0.cpp_namespace.clone();
 
-        let (rust, mut h, mut cpp) = file.render();
+        let (rust, headers, mut cpp) = if self.split_headers {
+            file.render_split()
+        } else {
+            let header_name = file.0.cpp_include_header_name.clone();
+            file.render()
+                .map(|(rust, h, cpp)| (rust, vec![(header_name, h)], cpp))
+        }?;
 
         // TODO: Don't hard code namespace as "::rust" and remove this replace
-        h = h
-            .replace("rust::", &format!("{cpp_namespace}::"))
-            .replace("namespace rust", &format!("namespace {cpp_namespace}"));
         cpp = cpp.map(|cpp| cpp.replace("rust::", &format!("{cpp_namespace}::")));
 
         File::create(rs_file_path)
             .unwrap()
             .write_all(rust.as_bytes())
             .unwrap();
-        File::create(h_file_path)
-            .unwrap()
-            .write_all(h.as_bytes())
-            .unwrap();
+        let h_dir = h_file_path.parent().unwrap();
+        for (name, h) in headers {
+            let h = h
+                .replace("rust::", &format!("{cpp_namespace}::"))
+                .replace("namespace rust", &format!("namespace {cpp_namespace}"));
+            File::create(h_dir.join(name))
+                .unwrap()
+                .write_all(h.as_bytes())
+                .unwrap();
+        }
         if let Some(cpp) = cpp {
             let cpp_file_path = self.cpp_file_path.expect("No cpp file path provided");
             File::create(cpp_file_path)
@@ -116,5 +268,6 @@ impl Zngur {
                 .write_all(cpp.as_bytes())
                 .unwrap();
         }
+        Ok(())
     }
 }